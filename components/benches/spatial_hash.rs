@@ -0,0 +1,80 @@
+//! Proximity queries of one frame with 2000 entities:
+//! rebuilding the index, magnet query around the character
+//! and nearest target search for every ship.
+//! Run with `cargo bench -p components`
+#![feature(test)]
+extern crate test;
+
+use common::*;
+use components::SpatialHash;
+use rand::prelude::*;
+use specs::prelude::*;
+use test::Bencher;
+
+const ENTITIES: usize = 2000;
+// ACTIVE_AREA of the game
+const AREA: f32 = 40f32;
+const SHIPS: usize = 100;
+const MAGNETO_RADIUS: f32 = 4f32;
+const TARGET_RADIUS: f32 = 42f32;
+
+fn scene() -> Vec<(Entity, Point2)> {
+    let mut world = World::new();
+    let mut rng = thread_rng();
+    (0..ENTITIES)
+        .map(|_| {
+            let point = Point2::new(
+                rng.gen_range(-AREA, AREA),
+                rng.gen_range(-AREA, AREA),
+            );
+            (world.create_entity().build(), point)
+        })
+        .collect()
+}
+
+#[bench]
+fn frame_full_scan(b: &mut Bencher) {
+    let scene = scene();
+    let character = Point2::new(0f32, 0f32);
+    b.iter(|| {
+        let collected = scene
+            .iter()
+            .filter(|(_, point)| (point - character).norm() < MAGNETO_RADIUS)
+            .count();
+        let mut targets = 0;
+        for (entity, position) in scene.iter().take(SHIPS) {
+            targets += scene
+                .iter()
+                .filter(|(target, _)| target != entity)
+                .min_by(|(_, pos1), (_, pos2)| {
+                    let (d1, d2) =
+                        ((pos1 - position).norm(), (pos2 - position).norm());
+                    d1.partial_cmp(&d2).unwrap()
+                })
+                .is_some() as usize;
+        }
+        test::black_box((collected, targets))
+    });
+}
+
+#[bench]
+fn frame_spatial_hash(b: &mut Bencher) {
+    let scene = scene();
+    let character = Point2::new(0f32, 0f32);
+    let mut spatial_hash = SpatialHash::default();
+    b.iter(|| {
+        spatial_hash.clear();
+        for &(entity, point) in scene.iter() {
+            spatial_hash.insert(entity, point);
+        }
+        let collected =
+            spatial_hash.query_radius(character, MAGNETO_RADIUS).len();
+        let mut targets = 0;
+        for (entity, position) in scene.iter().take(SHIPS) {
+            targets += spatial_hash
+                .nearest(*position, TARGET_RADIUS, |target| target != *entity)
+                .is_some() as usize;
+        }
+        test::black_box((collected, targets))
+    });
+}
@@ -1,15 +1,16 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::ops::AddAssign;
 use std::time::{Duration, Instant};
 
 use common::*;
-pub use geometry::{
-    BlockSegment, FogGrid, Geometry, NebulaGrid, PlanetGrid, Polygon, StarsGrid,
-};
-pub use gfx_h::animation::{Animation, AnimationFrame};
+pub use geometry::{BlockSegment, Geometry, Grid, Polygon};
+pub use gfx_h::animation::{Animation, AnimationFrame, LoopMode};
 use gfx_h::{ortho_unproject, unproject_with_z, Canvas as SDLCanvas};
 pub use gfx_h::{AtlasImage, ImageData};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::joint::ConstraintHandle;
 pub use physics::{BodiesMap, PhysicsComponent, PHYSICS_SIMULATION_TIME};
+use physics::{CollisionId, DT, MAX_FACTIONS};
 pub use sound::{SoundData, SoundPlacement};
 
 use serde::{Deserialize, Serialize};
@@ -37,6 +38,66 @@ pub static TRACKER: Lazy<Mutex<TimeTracker>> = Lazy::new(|| {
     Mutex::new(time_tracker)
 });
 
+pub const BULLET_TIME_SCALE: f32 = 0.3;
+pub const NEAR_MISS_SCALE: f32 = 0.5;
+/// part of the gap to the target scale closed every 60 fps frame
+pub const TIME_SCALE_SMOOTHING: f32 = 0.2;
+
+/// Global speed of the game time. Physics, cooldowns, lifetimes,
+/// particles and music are slowed down together
+#[derive(Debug)]
+pub struct TimeScale {
+    /// 1 is the normal speed
+    pub scale: f32,
+    // real time, otherwise slow motion would stretch itself
+    bullet_time_until: Option<Instant>,
+    near_miss_until: Option<Instant>,
+    updated: Instant,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale {
+            scale: 1f32,
+            bullet_time_until: None,
+            near_miss_until: None,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl TimeScale {
+    pub fn bullet_time(&mut self, duration: Duration) {
+        self.bullet_time_until = Some(Instant::now() + duration);
+    }
+
+    pub fn near_miss(&mut self, duration: Duration) {
+        self.near_miss_until = Some(Instant::now() + duration);
+    }
+
+    pub fn target(&self) -> f32 {
+        let now = Instant::now();
+        let mut target = 1f32;
+        if self.bullet_time_until.map_or(false, |until| until > now) {
+            target = target.min(BULLET_TIME_SCALE);
+        }
+        if self.near_miss_until.map_or(false, |until| until > now) {
+            target = target.min(NEAR_MISS_SCALE);
+        }
+        target
+    }
+
+    /// ease the current scale towards the target one,
+    /// independent of the frame rate
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let frames = (now - self.updated).as_secs_f32() / DT;
+        self.updated = now;
+        let smoothing = 1.0 - (1.0 - TIME_SCALE_SMOOTHING).powf(frames);
+        self.scale += smoothing * (self.target() - self.scale);
+    }
+}
+
 #[derive(Debug)]
 pub struct UpgradesStats {
     pub coins_mult: usize,
@@ -67,12 +128,14 @@ impl TimeTracker {
         }
     }
 
-    pub fn update(&mut self) -> Duration {
+    /// game time runs time_scale times slower than the real one
+    pub fn update(&mut self, time_scale: f32) -> Duration {
         let now = Instant::now();
         let mut res = now - self.timestamp;
         if res > Duration::from_millis(800) {
             res = Duration::from_millis(0);
         }
+        let res = res.mul_f32(time_scale);
         self.game_timestamp += res;
         self.timestamp = now;
         self.delta = res;
@@ -139,6 +202,262 @@ pub struct CurrentWave {
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Waves(pub Vec<Wave>);
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BiomeSave {
+    pub name: String,
+    /// asteroids kept around the character
+    pub asteroids: usize,
+    /// names of background layers not spawned here
+    #[serde(default)]
+    pub hidden_layers: Vec<String>,
+    /// fog reduces visibility
+    #[serde(default)]
+    pub fog: bool,
+    /// shield lost by the character every frame
+    #[serde(default)]
+    pub shield_drain: usize,
+    /// replaces distribution of the wave if not empty
+    #[serde(default)]
+    pub enemies: Vec<(String, f32)>,
+    /// battle music track
+    #[serde(default)]
+    pub music: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Biome {
+    pub name: String,
+    pub asteroids: usize,
+    pub hidden_layers: Vec<String>,
+    pub fog: bool,
+    pub shield_drain: usize,
+    pub enemies: Vec<(usize, f32)>,
+    pub music: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectorsSave {
+    pub sector_size: f32,
+    pub biomes: Vec<BiomeSave>,
+    /// biome names, the map repeats in all directions
+    pub map: Vec<Vec<String>>,
+}
+
+/// World split on square sectors, each with its own biome
+#[derive(Debug)]
+pub struct Sectors {
+    pub sector_size: f32,
+    pub biomes: Vec<Biome>,
+    map: Vec<Vec<usize>>,
+}
+
+impl Sectors {
+    pub fn load(
+        sectors_save: &SectorsSave,
+        enemy_name_to_id: &HashMap<String, usize>,
+    ) -> Self {
+        let biomes: Vec<Biome> = sectors_save
+            .biomes
+            .iter()
+            .map(|biome| Biome {
+                name: biome.name.clone(),
+                asteroids: biome.asteroids,
+                hidden_layers: biome.hidden_layers.clone(),
+                fog: biome.fog,
+                shield_drain: biome.shield_drain,
+                enemies: biome
+                    .enemies
+                    .iter()
+                    .map(|p| (enemy_name_to_id[&p.0], p.1))
+                    .collect(),
+                music: biome.music,
+            })
+            .collect();
+        let map = sectors_save
+            .map
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|name| {
+                        biomes
+                            .iter()
+                            .position(|biome| &biome.name == name)
+                            .expect(&format!("unknown biome {}", name))
+                    })
+                    .collect()
+            })
+            .collect();
+        Sectors {
+            sector_size: sectors_save.sector_size,
+            biomes: biomes,
+            map: map,
+        }
+    }
+
+    pub fn sector(&self, position: Point2) -> (i32, i32) {
+        (
+            (position.x / self.sector_size).floor() as i32,
+            (position.y / self.sector_size).floor() as i32,
+        )
+    }
+
+    pub fn biome_id(&self, sector: (i32, i32)) -> usize {
+        let row =
+            &self.map[sector.1.rem_euclid(self.map.len() as i32) as usize];
+        row[sector.0.rem_euclid(row.len() as i32) as usize]
+    }
+
+    pub fn biome(&self, sector: (i32, i32)) -> &Biome {
+        &self.biomes[self.biome_id(sector)]
+    }
+}
+
+/// Physics moves the world so the character stays in the origin,
+/// this is the accumulated shift, i.e. absolute position of the origin
+#[derive(Debug)]
+pub struct WorldOffset(pub Vector2);
+
+impl Default for WorldOffset {
+    fn default() -> Self {
+        WorldOffset(Vector2::new(0f32, 0f32))
+    }
+}
+
+fn default_density() -> usize {
+    1
+}
+
+fn default_parallax() -> f32 {
+    1.0
+}
+
+fn default_rotate() -> bool {
+    true
+}
+
+/// Grid of sprites kept around the character, e.g. stars or nebulas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundLayerSave {
+    pub name: String,
+    /// atlas images, one is picked at random for every sprite
+    #[serde(default)]
+    pub images: Vec<String>,
+    /// looped atlas animation, used instead of images
+    #[serde(default)]
+    pub animation: Option<String>,
+    /// cells around the center in every direction
+    pub cells: usize,
+    /// half width and height of a cell
+    pub cell_size: (f32, f32),
+    /// sprites don't spawn that close to the cell border
+    pub border: (f32, f32),
+    /// sprites kept in every cell
+    #[serde(default = "default_density")]
+    pub density: usize,
+    /// depth range, further layers are more negative
+    pub z: (f32, f32),
+    pub size: f32,
+    /// part of the world movement the layer follows,
+    /// 1.0 is plain perspective, 0.0 sticks to the camera
+    #[serde(default = "default_parallax")]
+    pub parallax: f32,
+    /// r, g, b, intensity as in AtlasImage color, none by default
+    #[serde(default)]
+    pub tint: (f32, f32, f32, f32),
+    /// random angle of sprites
+    #[serde(default = "default_rotate")]
+    pub rotate: bool,
+}
+
+/// Content of rons/background.ron, the farthest layer goes first
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BackgroundSave {
+    pub layers: Vec<BackgroundLayerSave>,
+}
+
+#[derive(Debug, Clone)]
+pub enum LayerSprite {
+    Images(Vec<AtlasImage>),
+    Animation(Animation),
+}
+
+pub struct BackgroundLayer {
+    pub desc: BackgroundLayerSave,
+    pub sprite: LayerSprite,
+    /// number of sprites in every cell
+    pub grid: Grid<usize>,
+}
+
+impl BackgroundLayer {
+    pub fn z(&self, rng: &mut ThreadRng) -> f32 {
+        let (min, max) = self.desc.z;
+        if min < max {
+            rng.gen_range(min, max)
+        } else {
+            min
+        }
+    }
+}
+
+/// Background layers in drawing order, filled by BackgroundSystem
+pub struct BackgroundLayers {
+    pub layers: Vec<BackgroundLayer>,
+    /// world offset the layers were moved to, shared by the dispatchers
+    pub offset: Option<Vector2>,
+}
+
+impl BackgroundLayers {
+    pub fn new(
+        descs: &[BackgroundLayerSave],
+        name_to_atlas: &HashMap<String, AtlasImage>,
+        name_to_animation: &HashMap<String, Animation>,
+    ) -> Self {
+        let layers = descs
+            .iter()
+            .map(|desc| {
+                let sprite = if let Some(animation) = &desc.animation {
+                    LayerSprite::Animation(name_to_animation[animation].clone())
+                } else {
+                    assert!(
+                        !desc.images.is_empty(),
+                        "background layer {} has no images",
+                        desc.name
+                    );
+                    LayerSprite::Images(
+                        desc.images
+                            .iter()
+                            .map(|image| name_to_atlas[image])
+                            .collect(),
+                    )
+                };
+                let grid = Grid::new(
+                    desc.cells,
+                    desc.cell_size.0,
+                    desc.cell_size.1,
+                    desc.border.0,
+                    desc.border.1,
+                );
+                BackgroundLayer {
+                    desc: desc.clone(),
+                    sprite,
+                    grid,
+                }
+            })
+            .collect();
+        BackgroundLayers {
+            layers,
+            offset: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CurrentSector {
+    /// None before the first update of the run
+    pub sector: Option<(i32, i32)>,
+    pub biome: usize,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct WavesSave(pub Vec<WaveSave>);
 
@@ -168,12 +487,40 @@ impl Pallete {
     }
 }
 
+/// Off-screen indicators, radar and floating text, from rons/hud.ron
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HudSettings {
+    pub indicators: bool,
+    pub radar: bool,
+    /// world distance at the radar rim
+    pub radar_range: f32,
+    /// arrows aren't shown for things further than that
+    pub indicator_range: f32,
+    /// damage numbers, pickups and level ups over the world
+    pub floating_text: bool,
+}
+
+impl Default for HudSettings {
+    fn default() -> Self {
+        HudSettings {
+            indicators: true,
+            radar: true,
+            radar_range: 40.0,
+            indicator_range: 40.0,
+            floating_text: true,
+        }
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Wave {
     pub distribution: Vec<(usize, f32)>,
     pub ships_number: usize,
     pub const_distribution: Vec<(usize, usize)>,
     pub iterations: usize,
+    pub elite_chance: f32,
+    pub gravity_wells: Vec<GravityWell>,
+    pub arena: Option<Arena>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -182,6 +529,14 @@ pub struct WaveSave {
     pub ships_number: usize,
     pub const_distribution: Vec<(String, usize)>,
     pub iterations: usize,
+    /// chance for every spawned ship to become elite
+    #[serde(default)]
+    pub elite_chance: f32,
+    #[serde(default)]
+    pub gravity_wells: Vec<GravityWell>,
+    /// unbounded world if None
+    #[serde(default)]
+    pub arena: Option<Arena>,
 }
 
 #[derive(Debug, Default)]
@@ -227,12 +582,206 @@ pub struct EnemyKind {
     pub image: AtlasImage,
     pub snake: Option<usize>,
     pub rift: Option<Rift>,
+    pub faction: Faction,
+    pub telegraph: Option<Duration>,
+    pub trail: Option<TrailDesc>,
+    pub boss: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EliteModifier {
+    Shielded,
+    Fast,
+    /// splits into two smaller copies on death
+    Splitting,
+    /// heals when damages the player
+    Vampiric,
+    /// leaves a mine on death
+    Mine,
+}
+
+pub const ELITE_MODIFIERS: [EliteModifier; 5] = [
+    EliteModifier::Shielded,
+    EliteModifier::Fast,
+    EliteModifier::Splitting,
+    EliteModifier::Vampiric,
+    EliteModifier::Mine,
+];
+
+impl EliteModifier {
+    /// tint of elite sprite
+    pub fn color(self) -> (f32, f32, f32) {
+        match self {
+            EliteModifier::Shielded => (0.5, 0.7, 0.9),
+            EliteModifier::Fast => (1.0, 0.9, 0.3),
+            EliteModifier::Splitting => (0.5, 1.0, 0.5),
+            EliteModifier::Vampiric => (0.9, 0.1, 0.2),
+            EliteModifier::Mine => (1.0, 0.5, 0.1),
+        }
+    }
+
+    pub fn promote(self, kind: &mut EnemyKind) {
+        match self {
+            EliteModifier::Shielded => {
+                kind.ship_stats.max_shield += kind.ship_stats.max_health
+            }
+            EliteModifier::Fast => kind.ship_stats.thrust_force *= 1.5,
+            _ => (),
+        }
+    }
+}
+
+/// Enemy randomly promoted on spawn
+#[derive(Component, Debug, Clone)]
+pub struct Elite {
+    pub modifier: EliteModifier,
+    /// kind before promotion, splitting spawns smaller copies of it
+    pub kind: EnemyKind,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum EntityType {
     Player,
-    Enemy,
+    Enemy(Faction),
+}
+
+impl EntityType {
+    pub fn from_faction(faction: Faction) -> Self {
+        if faction == PLAYER_FACTION {
+            EntityType::Player
+        } else {
+            EntityType::Enemy(faction)
+        }
+    }
+
+    pub fn faction(&self) -> Faction {
+        match self {
+            EntityType::Player => PLAYER_FACTION,
+            EntityType::Enemy(faction) => *faction,
+        }
+    }
+}
+
+/// index in Factions table
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Faction(pub usize);
+
+pub const PLAYER_FACTION: Faction = Faction(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Relation {
+    Hostile,
+    Neutral,
+    Allied,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FactionsSave {
+    pub factions: Vec<String>,
+    pub relations: Vec<(String, String, Relation)>,
+}
+
+/// Relation table between factions. First faction is always the player.
+/// Player is hostile to everyone and factions are allied with themselves
+/// unless the table says otherwise, other pairs are neutral.
+#[derive(Debug, Default)]
+pub struct Factions {
+    pub names: Vec<String>,
+    relations: Vec<Relation>,
+}
+
+impl Factions {
+    pub fn load(factions_save: &FactionsSave) -> Self {
+        let n = factions_save.factions.len();
+        assert!(n > 0 && n <= MAX_FACTIONS, "wrong number of factions");
+        let mut relations = vec![Relation::Neutral; n * n];
+        for i in 0..n {
+            relations[i * n + i] = Relation::Allied;
+            if i != 0 {
+                relations[i] = Relation::Hostile;
+                relations[i * n] = Relation::Hostile;
+            }
+        }
+        let mut factions = Factions {
+            names: factions_save.factions.clone(),
+            relations: relations,
+        };
+        for (name1, name2, relation) in factions_save.relations.iter() {
+            let (f1, f2) = (factions.by_name(name1), factions.by_name(name2));
+            factions.relations[f1.0 * n + f2.0] = *relation;
+            factions.relations[f2.0 * n + f1.0] = *relation;
+        }
+        factions
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn by_name(&self, name: &str) -> Faction {
+        let id = self
+            .names
+            .iter()
+            .position(|x| x == name)
+            .expect(&format!("unknown faction {}", name));
+        Faction(id)
+    }
+
+    pub fn relation(&self, f1: Faction, f2: Faction) -> Relation {
+        self.relations[f1.0 * self.len() + f2.0]
+    }
+
+    pub fn is_hostile(&self, f1: Faction, f2: Faction) -> bool {
+        self.relation(f1, f2) == Relation::Hostile
+    }
+
+    fn hostile_groups(
+        &self,
+        faction: Faction,
+        to_id: fn(usize) -> CollisionId,
+    ) -> Vec<usize> {
+        (0..self.len())
+            .filter(|&other| self.is_hostile(faction, Faction(other)))
+            .map(|other| to_id(other).group())
+            .collect()
+    }
+
+    pub fn ship_collision_groups(&self, faction: Faction) -> CollisionGroups {
+        let mut whitelist = vec![CollisionId::Asteroid.group()];
+        // ships push each other regardless of relations
+        whitelist.extend((0..self.len()).map(|f| CollisionId::Ship(f).group()));
+        whitelist.extend(self.hostile_groups(faction, CollisionId::Bullet));
+        let mut collision_groups = CollisionGroups::new();
+        collision_groups
+            .set_membership(&[CollisionId::Ship(faction.0).group()]);
+        collision_groups.set_whitelist(&whitelist);
+        collision_groups
+            .set_blacklist(&[CollisionId::Bullet(faction.0).group()]);
+        collision_groups
+    }
+
+    pub fn bullet_collision_groups(&self, faction: Faction) -> CollisionGroups {
+        let mut whitelist = vec![CollisionId::Asteroid.group()];
+        whitelist.extend(self.hostile_groups(faction, CollisionId::Ship));
+        let mut collision_groups = CollisionGroups::new();
+        collision_groups
+            .set_membership(&[CollisionId::Bullet(faction.0).group()]);
+        collision_groups.set_whitelist(&whitelist);
+        collision_groups.set_blacklist(&[CollisionId::Ship(faction.0).group()]);
+        collision_groups
+    }
+
+    pub fn asteroid_collision_groups(&self) -> CollisionGroups {
+        let mut whitelist = vec![CollisionId::Asteroid.group()];
+        for f in 0..self.len() {
+            whitelist.push(CollisionId::Ship(f).group());
+            whitelist.push(CollisionId::Bullet(f).group());
+        }
+        let mut collision_groups = CollisionGroups::new();
+        collision_groups.set_membership(&[CollisionId::Asteroid.group()]);
+        collision_groups.set_whitelist(&whitelist);
+        collision_groups
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -241,12 +790,14 @@ pub enum InsertEvent {
         gun_kind: GunKind,
         ship_stats: ShipStats,
         image: AtlasImage,
+        trail: Option<TrailDesc>,
     },
     Asteroid {
         iso: Point3,
         velocity: Velocity2,
         polygon: Polygon,
         spin: f32,
+        kind: AsteroidType,
     },
     Ship {
         iso: Point3,
@@ -259,6 +810,11 @@ pub enum InsertEvent {
         size: f32,
         snake: Option<usize>,
         rift: Option<Rift>,
+        faction: Faction,
+        telegraph: Option<Duration>,
+        elite: Option<Elite>,
+        trail: Option<TrailDesc>,
+        boss: bool,
     },
     Bullet {
         kind: EntityType,
@@ -295,6 +851,9 @@ pub enum InsertEvent {
     DoubleExpCollectable {
         position: Point2,
     },
+    BulletTimeCollectable {
+        position: Point2,
+    },
     SideBulletCollectable {
         position: Point2,
     },
@@ -316,23 +875,31 @@ pub enum InsertEvent {
     // },
     Explosion {
         position: Point2,
-        num: usize,
-        lifetime: Duration,
+        /// emitter name from rons/particles.ron
+        emitter: String,
         with_animation: Option<f32>,
     },
-    Nebula {
+    /// sprite of the background layer, z of iso is the angle
+    Background {
+        layer: usize,
         iso: Point3,
     },
-    Stars {
-        iso: Point3,
+    /// camera shake profile by name from rons/camera.ron
+    Wobble(String),
+    FloatingText {
+        position: Point2,
+        text: String,
+        color: (f32, f32, f32, f32),
+        font_size: f32,
     },
-    Fog {
-        iso: Point3,
+    Blast {
+        position: Point2,
+        blast: Blast,
     },
-    Planet {
-        iso: Point3,
+    GravityWell {
+        position: Point2,
+        well: GravityWell,
     },
-    Wobble(f32),
     Animation {
         animation: Animation,
         lifetime: Duration,
@@ -393,6 +960,14 @@ pub enum AIType {
     },
 }
 
+fn default_ram_speed_threshold() -> f32 {
+    0.05
+}
+
+fn default_ram_reference_speed() -> f32 {
+    0.1
+}
+
 #[derive(Debug, Clone, Copy, Component, Serialize, Deserialize)]
 pub struct ShipStats {
     pub thrust_force: f32,
@@ -403,18 +978,41 @@ pub struct ShipStats {
     pub max_health: usize,
     pub max_shield: usize,
     pub damage: usize,
+    /// reduces ramming damage
+    #[serde(default)]
+    pub hull_armor: usize,
+    /// relative speed along contact normal below which rams do no damage
+    #[serde(default = "default_ram_speed_threshold")]
+    pub ram_speed_threshold: f32,
+    /// relative speed at which a ram deals exactly the damage of the other
+    #[serde(default = "default_ram_reference_speed")]
+    pub ram_reference_speed: f32,
+}
+
+impl ShipStats {
+    /// Ramming damage multiplier for this ship,
+    /// speed is relative along the contact normal
+    pub fn ram_factor(&self, speed: f32, mass_ratio: f32) -> f32 {
+        if speed < self.ram_speed_threshold {
+            return 0f32;
+        }
+        mass_ratio * speed / self.ram_reference_speed
+    }
 }
 
 #[derive(Debug, Clone, Component, Serialize, Deserialize)]
 pub struct ShipKindSave {
     ship_stats: ShipStats,
     image: String,
+    #[serde(default)]
+    trail: Option<TrailDesc>,
 }
 
 #[derive(Debug, Clone, Component)]
 pub struct ShipKind {
     pub ship_stats: ShipStats,
     pub image: AtlasImage,
+    pub trail: Option<TrailDesc>,
 }
 
 impl ShipKindSave {
@@ -422,10 +1020,114 @@ impl ShipKindSave {
         ShipKind {
             ship_stats: self.ship_stats,
             image: name_to_image[&self.image],
+            trail: self.trail,
         }
     }
 }
 
+/// Look of a ribbon trail, loaded from desc.ron for ships
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TrailDesc {
+    /// seconds a point of the trail lives
+    pub length: f32,
+    pub width: f32,
+    /// r, g, b, alpha at the head, fades to zero at the tail
+    pub color: (f32, f32, f32, f32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrailPoint {
+    pub position: Point2,
+    /// game time in seconds
+    pub age: f32,
+    pub width: f32,
+}
+
+/// Ribbon left behind the entity, see TrailSystem
+#[derive(Component, Debug, Clone)]
+pub struct Trail {
+    pub desc: TrailDesc,
+    /// engine plume width follows the thrust, other trails are steady
+    engine: bool,
+    /// thrust of the current frame in parts of ship thrust force
+    thrust: f32,
+    // newest first
+    points: VecDeque<TrailPoint>,
+}
+
+impl Trail {
+    pub fn new(desc: TrailDesc) -> Self {
+        Trail {
+            desc,
+            engine: false,
+            thrust: 0.0,
+            points: VecDeque::new(),
+        }
+    }
+
+    pub fn engine(desc: TrailDesc) -> Self {
+        Trail {
+            engine: true,
+            ..Trail::new(desc)
+        }
+    }
+
+    pub fn add_thrust(&mut self, thrust: f32) {
+        self.thrust += thrust;
+    }
+
+    /// Move all points, used when the world is re-centred
+    pub fn translate(&mut self, shift: Vector2) {
+        for point in self.points.iter_mut() {
+            point.position += shift;
+        }
+    }
+
+    /// Age the points by delta seconds and add the current position
+    pub fn update(&mut self, position: Point2, delta: f32) {
+        if delta <= 0.0 {
+            return;
+        }
+        for point in self.points.iter_mut() {
+            point.age += delta;
+        }
+        while let Some(tail) = self.points.back() {
+            if tail.age < self.desc.length {
+                break;
+            }
+            self.points.pop_back();
+        }
+        let width = if self.engine {
+            self.desc.width * self.thrust.min(1.0)
+        } else {
+            self.desc.width
+        };
+        self.thrust = 0.0;
+        self.points.push_front(TrailPoint {
+            position,
+            age: 0.0,
+            width,
+        });
+    }
+
+    /// Points from head to tail: position, half width and color,
+    /// both shrink to zero with age
+    pub fn ribbon<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (Point2, f32, (f32, f32, f32, f32))> + 'a {
+        let (r, g, b, a) = self.desc.color;
+        let length = self.desc.length;
+        self.points.iter().map(move |point| {
+            let rest = (1.0 - point.age / length).max(0.0);
+            (
+                point.position,
+                rest * point.width / 2.0,
+                (r, g, b, rest * a),
+            )
+        })
+    }
+}
+
 // impl Into<ShipKind> for &ShipKindSave {
 //     fn into(self) -> ShipKind {
 //         ShipKind {
@@ -444,6 +1146,14 @@ pub struct TextComponent {
 #[derive(Debug, Clone, Component)]
 pub struct Position2D(pub Point2);
 
+/// World text which rises and fades over its Lifetime
+#[derive(Debug, Clone, Copy, Component)]
+pub struct FloatingText {
+    /// world units per second of game time
+    pub velocity: Vector2,
+    pub font_size: f32,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlayState {
     Action,
@@ -469,6 +1179,22 @@ pub struct Chain {
     pub follow: specs::Entity,
 }
 
+/// Physics joint between two snake links
+#[derive(Debug, Clone, Copy)]
+pub struct ChainJoint {
+    pub handle: ConstraintHandle,
+    pub leader: specs::Entity,
+    pub follower: specs::Entity,
+    /// torque per radian straightening the link
+    pub stiffness: f32,
+    /// link can't bend more than that relative to the leader
+    pub max_angle: f32,
+}
+
+/// Joints are removed as soon as one of the links is destroyed
+#[derive(Debug, Default)]
+pub struct ChainJoints(pub Vec<ChainJoint>);
+
 #[derive(Component, Debug, Clone, Copy)]
 pub struct LazerConnect(pub specs::Entity);
 
@@ -487,7 +1213,7 @@ pub struct Charge {
 impl Charge {
     pub fn new(recharge_time: Duration) -> Self {
         Charge {
-            recharge_start: Instant::now(),
+            recharge_start: TRACKER.lock().unwrap().now(),
             recharge_time: recharge_time,
         }
     }
@@ -519,10 +1245,74 @@ impl Gun for Charge {
     }
 }
 
+/// Windup before kamikadze or charging dash
+#[derive(Component, Debug, Clone, Copy)]
+pub struct AttackTelegraph {
+    pub duration: Duration,
+    start: Option<Instant>,
+    /// point the dash is aimed at
+    pub target: Point2,
+    /// kamikadze warns once and keeps lunging until the target is lost
+    pub released: bool,
+}
+
+impl AttackTelegraph {
+    pub fn new(duration: Duration) -> Self {
+        AttackTelegraph {
+            duration: duration,
+            start: None,
+            target: Point2::new(0f32, 0f32),
+            released: false,
+        }
+    }
+
+    pub fn start(&mut self, target: Point2) {
+        self.start = Some(TRACKER.lock().unwrap().now());
+        self.target = target;
+    }
+
+    pub fn stop(&mut self) {
+        self.start = None;
+    }
+
+    /// back to idle, so the next attack is telegraphed again
+    pub fn reset(&mut self) {
+        self.stop();
+        self.released = false;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// from 0 at the start of windup to 1 right before the dash
+    pub fn progress(&self) -> f32 {
+        if let Some(start) = self.start {
+            let elapsed = TRACKER
+                .lock()
+                .unwrap()
+                .now()
+                .duration_since(start)
+                .as_secs_f32();
+            (elapsed / self.duration.as_secs_f32()).min(1f32)
+        } else {
+            0f32
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.is_active() && self.progress() >= 1f32
+    }
+}
+
 // used to add white color for entities that were damaged
 #[derive(Component, Debug, Clone, Copy)]
 pub struct DamageFlash(pub f32);
 
+/// Own color of the image (e.g. black holes), the damage flash overrides it
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Tint(pub (f32, f32, f32, f32));
+
 #[derive(Component, Debug, Clone, Copy)]
 pub struct Size(pub f32);
 
@@ -531,6 +1321,13 @@ pub struct Size(pub f32);
 #[derive(Component, Clone, Copy)]
 pub struct Sound(pub specs::Entity, pub Point2);
 
+/// Event on a frame of the animation, declared in the atlas metadata
+#[derive(Debug, Clone)]
+pub struct AnimationEvent {
+    pub entity: specs::Entity,
+    pub name: String,
+}
+
 #[derive(Component, Clone, Copy)]
 pub struct Particles(pub usize);
 
@@ -601,10 +1398,7 @@ impl Progress {
 /// contains preloaded images ids
 /// use it when you need to insert entity in system
 pub struct PreloadedImages {
-    pub nebulas: Vec<AtlasImage>,
     pub planets: Vec<AtlasImage>,
-    pub stars: Vec<AtlasImage>,
-    pub fog: AtlasImage,
     pub ship_speed_upgrade: AtlasImage,
     pub bullet_speed_upgrade: AtlasImage,
     pub attack_speed_upgrade: AtlasImage,
@@ -618,6 +1412,7 @@ pub struct PreloadedImages {
     pub health: AtlasImage,
     pub double_coin: AtlasImage,
     pub double_exp: AtlasImage,
+    pub bullet_time: AtlasImage,
     pub side_bullet_ability: AtlasImage,
     pub bar: AtlasImage,
     pub upg_bar: AtlasImage,
@@ -631,10 +1426,12 @@ pub struct PreloadedImages {
     pub bullet_contact: Animation,
     pub locked: AtlasImage,
     pub cursor: AtlasImage,
+    pub mine: AtlasImage,
 }
 
 pub struct PreloadedParticles {
     pub movement: specs::Entity,
+    pub menu: specs::Entity,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -753,21 +1550,14 @@ impl Mouse {
 #[storage(NullStorage)]
 pub struct CharacterMarker;
 
-#[derive(Default, Component, Clone, Copy)]
-#[storage(NullStorage)]
-pub struct NebulaMarker;
-
-#[derive(Default, Component, Clone, Copy)]
-#[storage(NullStorage)]
-pub struct StarsMarker;
-
-#[derive(Default, Component, Clone, Copy)]
+/// Kept on screen together with the player by the camera
+#[derive(Default, Component)]
 #[storage(NullStorage)]
-pub struct FogMarker;
+pub struct CameraTarget;
 
-#[derive(Default, Component, Clone, Copy)]
-#[storage(NullStorage)]
-pub struct PlanetMarker;
+/// Sprite of the background layer with that index
+#[derive(Debug, Component, Clone, Copy)]
+pub struct BackgroundMarker(pub usize);
 
 #[derive(Default, Component)]
 #[storage(NullStorage)]
@@ -781,10 +1571,331 @@ pub struct EnemyMarker;
 #[storage(NullStorage)]
 pub struct AsteroidMarker;
 
+/// Asteroid variety, loaded from asteroids.ron
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct AsteroidType {
+    pub name: String,
+    /// spawn weight
+    pub weight: f32,
+    pub color: (f32, f32, f32),
+    /// chained blast on destruction
+    #[serde(default)]
+    pub blast: Option<Blast>,
+    /// number of shards the asteroid is split into
+    pub shards: usize,
+    /// bullets bounce off
+    #[serde(default)]
+    pub reflective: bool,
+    /// extra coins from the smallest shards
+    #[serde(default)]
+    pub coins: usize,
+    /// jagged concave outline instead of a convex blob
+    #[serde(default)]
+    pub concave: bool,
+    /// atlas image drawn over the tinted polygon
+    #[serde(default)]
+    pub image: Option<String>,
+    /// `image` looked up in the atlas on load
+    #[serde(skip)]
+    pub atlas_image: Option<AtlasImage>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AsteroidTypes(pub Vec<AsteroidType>);
+
+impl AsteroidTypes {
+    pub fn load_images(
+        &mut self,
+        name_to_atlas: &HashMap<String, AtlasImage>,
+    ) -> Result<(), String> {
+        for kind in self.0.iter_mut() {
+            if let Some(image) = &kind.image {
+                let atlas_image = name_to_atlas.get(image).ok_or(format!(
+                    "unknown image {} of asteroid {}",
+                    image, kind.name
+                ))?;
+                kind.atlas_image = Some(*atlas_image);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Planet or black hole that pulls dynamic bodies
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GravityWell {
+    /// acceleration at unit distance, falls off with squared distance
+    pub strength: f32,
+    /// no pull further than that
+    pub radius: f32,
+    /// sprite size
+    pub size: f32,
+    /// black holes destroy everything closer than horizon
+    #[serde(default)]
+    pub horizon: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ArenaShape {
+    Rectangle { width: f32, height: f32 },
+    Circle { radius: f32 },
+}
+
+/// What happens with bodies crossing arena border
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ArenaBehavior {
+    Bounce,
+    /// appear on the opposite side like in classic Asteroids
+    Wrap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Arena {
+    pub shape: ArenaShape,
+    pub behavior: ArenaBehavior,
+}
+
+/// Arena of the current wave, placed around the character on wave start
+#[derive(Debug)]
+pub struct ActiveArena {
+    /// absolute position, see WorldOffset
+    pub center: Point2,
+    pub arena: Option<Arena>,
+}
+
+impl Default for ActiveArena {
+    fn default() -> Self {
+        ActiveArena {
+            center: Point2::new(0f32, 0f32),
+            arena: None,
+        }
+    }
+}
+
+pub const WARP_ACCELERATE_MS: u64 = 1500;
+pub const WARP_JUMP_MS: u64 = 600;
+pub const WARP_ARRIVE_MS: u64 = 1200;
+
+/// Steps of the warp jump between waves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarpState {
+    Idle,
+    /// character speeds up, stars turn into streaks
+    Accelerate,
+    /// world is cleared and filled again for the next sector
+    Jump,
+    /// character slows down, camera comes back
+    Arrive,
+}
+
+#[derive(Debug)]
+pub struct Warp {
+    pub state: WarpState,
+    /// heading of the ship when the warp started
+    pub direction: Vector2,
+    state_start: Instant,
+}
+
+impl Default for Warp {
+    fn default() -> Self {
+        Warp {
+            state: WarpState::Idle,
+            direction: Vector2::new(0f32, -1f32),
+            state_start: TRACKER.lock().unwrap().now(),
+        }
+    }
+}
+
+impl Warp {
+    pub fn start(&mut self, direction: Vector2) {
+        if self.is_active() {
+            return;
+        }
+        if direction.norm() > 0f32 {
+            self.direction = direction.normalize();
+        }
+        self.switch(WarpState::Accelerate);
+    }
+
+    pub fn switch(&mut self, state: WarpState) {
+        self.state = state;
+        self.state_start = TRACKER.lock().unwrap().now();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state != WarpState::Idle
+    }
+
+    /// from 0 to 1 during the current step
+    pub fn progress(&self) -> f32 {
+        let duration = match self.state {
+            WarpState::Idle => return 0f32,
+            WarpState::Accelerate => WARP_ACCELERATE_MS,
+            WarpState::Jump => WARP_JUMP_MS,
+            WarpState::Arrive => WARP_ARRIVE_MS,
+        };
+        let elapsed = TRACKER.lock().unwrap().now() - self.state_start;
+        (elapsed.as_millis() as f32 / duration as f32).min(1f32)
+    }
+
+    /// strength of warp effects: streaks and camera pull back
+    pub fn intensity(&self) -> f32 {
+        match self.state {
+            WarpState::Idle => 0f32,
+            WarpState::Accelerate => self.progress(),
+            WarpState::Jump => 1f32,
+            WarpState::Arrive => 1f32 - self.progress(),
+        }
+    }
+}
+
+/// cell of the spatial hash, about the magnet radius
+pub const SPATIAL_HASH_CELL_SIZE: f32 = 4f32;
+
+/// Uniform grid of entity positions for proximity queries,
+/// rebuilt every tick from Isometries by SpatialHashSystem
+#[derive(Debug)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(Entity, Point2)>>,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        SpatialHash::new(SPATIAL_HASH_CELL_SIZE)
+    }
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, point: Point2) -> (i32, i32) {
+        (
+            (point.x / self.cell_size).floor() as i32,
+            (point.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Cells are kept allocated, the world is recentered on the character
+    /// so the same cells are reused
+    pub fn clear(&mut self) {
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, point: Point2) {
+        let cell = self.cell(point);
+        self.cells
+            .entry(cell)
+            .or_insert_with(Vec::new)
+            .push((entity, point));
+    }
+
+    /// Entities closer than radius to the center
+    pub fn query_radius(
+        &self,
+        center: Point2,
+        radius: f32,
+    ) -> Vec<(Entity, Point2)> {
+        let (min_x, min_y) = self.cell(center - Vector2::new(radius, radius));
+        let (max_x, max_y) = self.cell(center + Vector2::new(radius, radius));
+        let mut res = vec![];
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    res.extend(
+                        cell.iter().filter(|(_, point)| {
+                            (point - center).norm() < radius
+                        }),
+                    );
+                }
+            }
+        }
+        res
+    }
+
+    /// Nearest entity passing the filter not farther than max_radius.
+    /// Cells are visited in growing square rings around the center
+    pub fn nearest<F: Fn(Entity) -> bool>(
+        &self,
+        center: Point2,
+        max_radius: f32,
+        filter: F,
+    ) -> Option<(Entity, Point2)> {
+        let (cx, cy) = self.cell(center);
+        let mut best: Option<(Entity, Point2, f32)> = None;
+        let max_ring = (max_radius / self.cell_size).ceil() as i32 + 1;
+        for ring in 0..=max_ring {
+            let mut visit = |x: i32, y: i32| {
+                let cell = if let Some(cell) = self.cells.get(&(x, y)) {
+                    cell
+                } else {
+                    return;
+                };
+                for &(entity, point) in cell.iter() {
+                    let distance = (point - center).norm();
+                    let closer = match best {
+                        Some((_, _, best_distance)) => distance < best_distance,
+                        None => true,
+                    };
+                    if distance <= max_radius && closer && filter(entity) {
+                        best = Some((entity, point, distance));
+                    }
+                }
+            };
+            // only the border of the ring, inner cells are already visited
+            if ring == 0 {
+                visit(cx, cy);
+            } else {
+                for x in cx - ring..=cx + ring {
+                    visit(x, cy - ring);
+                    visit(x, cy + ring);
+                }
+                for y in cy - ring + 1..cy + ring {
+                    visit(cx - ring, y);
+                    visit(cx + ring, y);
+                }
+            }
+            // next rings are at least that far away
+            if let Some((entity, point, distance)) = best {
+                if distance <= ring as f32 * self.cell_size {
+                    return Some((entity, point));
+                }
+            }
+        }
+        best.map(|(entity, point, _)| (entity, point))
+    }
+}
+
 #[derive(Default, Component)]
 #[storage(NullStorage)]
 pub struct LightMarker;
 
+/// Point light carried by any entity: explosions, rockets, pickups.
+/// Lights up its surroundings and reveals what is hidden in shadows
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Light {
+    pub color: (f32, f32, f32),
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn new(color: (f32, f32, f32), radius: f32, intensity: f32) -> Self {
+        Light {
+            color,
+            radius,
+            intensity,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Projectile {
     pub owner: specs::Entity,
@@ -821,6 +1932,9 @@ pub struct DoubleExpCollectable;
 #[derive(Component)]
 pub struct DoubleExpAbility;
 
+#[derive(Component)]
+pub struct BulletTimeCollectable;
+
 #[derive(Component)]
 pub struct SideBulletCollectable;
 
@@ -1072,15 +2186,20 @@ pub trait Gun {
 
     fn recharge_time(&self) -> Duration;
 
+    /// cooldowns run on the game clock, so they slow down with the time
     fn is_ready(&self) -> bool {
-        Instant::now().duration_since(self.recharge_start())
+        TRACKER
+            .lock()
+            .unwrap()
+            .now()
+            .duration_since(self.recharge_start())
             >= self.recharge_time()
     }
 
     fn shoot(&mut self) -> bool {
         let result = self.is_ready();
         if result {
-            self.set_recharge_start(Instant::now());
+            self.set_recharge_start(TRACKER.lock().unwrap().now());
         };
         result
     }
@@ -1163,7 +2282,7 @@ impl ShotGun {
         bullet_image: AtlasImage,
     ) -> Self {
         Self {
-            recharge_start: Instant::now(),
+            recharge_start: TRACKER.lock().unwrap().now(),
             recharge_time: recharge_time,
             bullets_damage: bullets_damage,
             side_projectiles_number: side_projectiles_number,
@@ -1301,7 +2420,7 @@ impl RocketGun {
         bullet_image: AtlasImage,
     ) -> Self {
         Self {
-            recharge_start: Instant::now(),
+            recharge_start: TRACKER.lock().unwrap().now(),
             recharge_time: recharge_time,
             bullets_damage: bullets_damage,
             bullet_speed,
@@ -1372,7 +2491,7 @@ impl Cannon {
         bullet_image: AtlasImage,
     ) -> Self {
         Self {
-            recharge_start: Instant::now(),
+            recharge_start: TRACKER.lock().unwrap().now(),
             recharge_time: recharge_time,
             bullet_size: bullet_size,
             bullets_damage: bullets_damage,
@@ -9,6 +9,10 @@ use voronois::destruction;
 pub const EPS: f32 = 1E-3;
 pub const SHADOW_LENGTH: f32 = 100f32;
 pub const DECONSTRUCT_SHADR_MIN_R: f32 = 0.1f32;
+/// carved pieces with smaller area are thrown away
+pub const CARVE_MIN_AREA: f32 = 0.05f32;
+/// part of the shortest adjacent edge cut from a corner by into_rounded
+pub const ROUNDING_CUT: f32 = 0.4f32;
 
 #[derive(Component, Debug, Clone)]
 pub enum Geometry {
@@ -22,50 +26,6 @@ pub struct BlockSegment {
     pub point2: Point2,
 }
 
-pub struct NebulaGrid {
-    pub grid: Grid<bool>,
-}
-
-impl NebulaGrid {
-    pub fn new(n: usize, rw: f32, rh: f32, rbw: f32, rbh: f32) -> Self {
-        let grid = Grid::new(n, rw, rh, rbw, rbh);
-        NebulaGrid { grid: grid }
-    }
-}
-
-pub struct PlanetGrid {
-    pub grid: Grid<bool>,
-}
-
-impl PlanetGrid {
-    pub fn new(n: usize, rw: f32, rh: f32, rbw: f32, rbh: f32) -> Self {
-        let grid = Grid::new(n, rw, rh, rbw, rbh);
-        PlanetGrid { grid: grid }
-    }
-}
-
-pub struct StarsGrid {
-    pub grid: Grid<bool>,
-}
-
-impl StarsGrid {
-    pub fn new(n: usize, rw: f32, rh: f32, rbw: f32, rbh: f32) -> Self {
-        let grid = Grid::new(n, rw, rh, rbw, rbh);
-        StarsGrid { grid: grid }
-    }
-}
-
-pub struct FogGrid {
-    pub grid: Grid<bool>,
-}
-
-impl FogGrid {
-    pub fn new(n: usize, rw: f32, rh: f32, rbw: f32, rbh: f32) -> Self {
-        let grid = Grid::new(n, rw, rh, rbw, rbh);
-        FogGrid { grid: grid }
-    }
-}
-
 pub struct Grid<T> {
     bricks: Vec<T>,
     x: f32,
@@ -162,6 +122,18 @@ where
         self.bricks = vec![T::default(); self.bricks.len()];
     }
 
+    /// Value of the cell containing the point, None outside of the grid
+    pub fn cell_mut(&mut self, point: Point2) -> Option<&mut T> {
+        if (point.x - self.x).abs() < self.max_w
+            && (point.y - self.y).abs() < self.max_h
+        {
+            let id =
+                self.size * self.get_row(point.y) + self.get_column(point.x);
+            return Some(&mut self.bricks[id]);
+        }
+        None
+    }
+
     pub fn update(&mut self, point: Point2, value: T) -> Result<(), ()> {
         if (point.x - self.x).abs() < self.max_w
             && (point.y - self.y).abs() < self.max_h
@@ -197,6 +169,279 @@ pub fn generate_convex_polygon(samples_num: usize, size: f32) -> Polygon {
     Polygon::new(points)
 }
 
+/// Random star shaped polygon around the origin, usually concave
+pub fn generate_polygon(samples_num: usize, size: f32) -> Polygon {
+    let mut rng = thread_rng();
+    let mut angles: Vec<f32> = (0..samples_num)
+        .map(|_| rng.gen_range(0.0, 2.0 * std::f32::consts::PI))
+        .collect();
+    angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let points = angles
+        .iter()
+        .map(|angle| {
+            let r = rng.gen_range(size / 2.0, size);
+            Point2::new(r * angle.cos(), r * angle.sin())
+        })
+        .collect();
+    Polygon::new(points)
+}
+
+/// Regular polygon approximating a circle, e.g. a crater
+pub fn circle_polygon(
+    center: Point2,
+    radius: f32,
+    samples_num: usize,
+) -> Vec<Point2> {
+    let mut rng = thread_rng();
+    let shift = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+    (0..samples_num)
+        .map(|i| {
+            let angle = shift
+                + 2.0 * std::f32::consts::PI * i as f32 / samples_num as f32;
+            center + radius * Vector2::new(angle.cos(), angle.sin())
+        })
+        .collect()
+}
+
+/// Signed area, positive for counter clockwise polygons
+pub fn polygon_area(points: &[Point2]) -> f32 {
+    let mut area = 0f32;
+    for i in 0..points.len() {
+        let p = points[i];
+        let q = points[(i + 1) % points.len()];
+        area += p.coords.perp(&q.coords);
+    }
+    area / 2.0
+}
+
+/// Center of mass of a simple polygon, vertex average if it's degenerate
+pub fn polygon_centroid(points: &[Point2]) -> Point2 {
+    let area = polygon_area(points);
+    if area.abs() < EPS * EPS {
+        let w = 1.0 / points.len() as f32;
+        return Point2::from(
+            points
+                .iter()
+                .fold(Vector2::zeros(), |acc, p| acc + w * p.coords),
+        );
+    }
+    let mut center = Vector2::zeros();
+    for i in 0..points.len() {
+        let p = points[i];
+        let q = points[(i + 1) % points.len()];
+        center += (p.coords + q.coords) * p.coords.perp(&q.coords);
+    }
+    Point2::from(center / (6.0 * area))
+}
+
+/// Part of convex polygon to the left of the directed line a -> b
+fn clip_by_line(points: &[Point2], a: Point2, b: Point2) -> Vec<Point2> {
+    let side = |p: Point2| (b - a).perp(&(p - a));
+    let mut res = vec![];
+    for i in 0..points.len() {
+        let p = points[i];
+        let q = points[(i + 1) % points.len()];
+        let (side_p, side_q) = (side(p), side(q));
+        if side_p >= 0.0 {
+            res.push(p);
+        }
+        if (side_p >= 0.0) != (side_q >= 0.0) {
+            let t = side_p / (side_p - side_q);
+            res.push(p + (q - p) * t);
+        }
+    }
+    res
+}
+
+/// Lower left and upper right corners
+fn bounding_box(points: &[Point2]) -> (Point2, Point2) {
+    let mut min = Point2::new(std::f32::MAX, std::f32::MAX);
+    let mut max = Point2::new(std::f32::MIN, std::f32::MIN);
+    for p in points.iter() {
+        min = Point2::new(min.x.min(p.x), min.y.min(p.y));
+        max = Point2::new(max.x.max(p.x), max.y.max(p.y));
+    }
+    (min, max)
+}
+
+/// Boolean difference of two convex polygons as a list of convex pieces.
+/// Every piece is what is left outside of one clip edge
+/// and inside of all previous ones
+pub fn convex_difference(
+    subject: &[Point2],
+    clip: &[Point2],
+) -> Vec<Vec<Point2>> {
+    let (subject_min, subject_max) = bounding_box(subject);
+    let (clip_min, clip_max) = bounding_box(clip);
+    // clip can't touch the subject, keep it in one piece
+    if subject_max.x < clip_min.x
+        || clip_max.x < subject_min.x
+        || subject_max.y < clip_min.y
+        || clip_max.y < subject_min.y
+    {
+        return vec![subject.to_vec()];
+    }
+    let mut clip = clip.to_vec();
+    if polygon_area(&clip) < 0.0 {
+        clip.reverse();
+    }
+    let mut rest = subject.to_vec();
+    let mut res = vec![];
+    for i in 0..clip.len() {
+        let a = clip[i];
+        let b = clip[(i + 1) % clip.len()];
+        let outside = clip_by_line(&rest, b, a);
+        if outside.len() >= 3 {
+            res.push(outside);
+        }
+        rest = clip_by_line(&rest, a, b);
+        if rest.len() < 3 {
+            break;
+        }
+    }
+    res
+}
+
+/// Point is inside of convex polygon or closer than EPS to its border
+fn near_convex(points: &[Point2], point: Point2) -> bool {
+    let orientation = polygon_area(points).signum();
+    (0..points.len()).all(|i| {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        orientation * (b - a).perp(&(point - a)) / (b - a).norm().max(EPS)
+            > -EPS
+    })
+}
+
+fn pieces_touch(piece1: &[Point2], piece2: &[Point2]) -> bool {
+    piece1.iter().any(|p| near_convex(piece2, *p))
+        || piece2.iter().any(|p| near_convex(piece1, *p))
+}
+
+/// Collinear vertices are allowed
+pub fn is_convex(points: &[Point2]) -> bool {
+    let mut sign = 0f32;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        let c = points[(i + 2) % points.len()];
+        let cross = (b - a).perp(&(c - b));
+        if cross == 0.0 {
+            continue;
+        }
+        if sign == 0.0 {
+            sign = cross.signum();
+        } else if cross.signum() != sign {
+            return false;
+        }
+    }
+    true
+}
+
+fn in_triangle(p: Point2, a: Point2, b: Point2, c: Point2) -> bool {
+    let d1 = (b - a).perp(&(p - a));
+    let d2 = (c - b).perp(&(p - b));
+    let d3 = (a - c).perp(&(p - c));
+    let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(has_neg && has_pos)
+}
+
+/// Triangulation of a simple (possibly concave) polygon by ear clipping.
+/// Triangles keep the orientation of the polygon
+pub fn ear_clipping(points: &[Point2]) -> Vec<[usize; 3]> {
+    let orientation = polygon_area(points).signum();
+    let mut ids: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = vec![];
+    while ids.len() > 3 {
+        let n = ids.len();
+        let ear = (0..n).find(|&i| {
+            let (prev, next) = (ids[(i + n - 1) % n], ids[(i + 1) % n]);
+            let (a, b, c) = (points[prev], points[ids[i]], points[next]);
+            // convex vertex without other vertices inside of the triangle
+            orientation * (b - a).perp(&(c - b)) > 0.0
+                && ids.iter().all(|&j| {
+                    j == prev
+                        || j == ids[i]
+                        || j == next
+                        || !in_triangle(points[j], a, b, c)
+                })
+        });
+        // only degenerate polygons have no ears, clip anything then
+        let i = ear.unwrap_or(0);
+        triangles.push([ids[(i + n - 1) % n], ids[i], ids[(i + 1) % n]]);
+        ids.remove(i);
+    }
+    if ids.len() == 3 {
+        triangles.push([ids[0], ids[1], ids[2]]);
+    }
+    triangles
+}
+
+/// Union of two pieces sharing an edge, pieces are lists of outline ids
+fn merge_pieces(piece1: &[usize], piece2: &[usize]) -> Option<Vec<usize>> {
+    let (n1, n2) = (piece1.len(), piece2.len());
+    for i in 0..n1 {
+        let (a, b) = (piece1[i], piece1[(i + 1) % n1]);
+        for j in 0..n2 {
+            if piece2[j] == b && piece2[(j + 1) % n2] == a {
+                // piece1 from b to a, then piece2 strictly between a and b
+                let mut union: Vec<usize> =
+                    (1..=n1).map(|k| piece1[(i + k) % n1]).collect();
+                union.extend((2..n2).map(|k| piece2[(j + k) % n2]));
+                return Some(union);
+            }
+        }
+    }
+    None
+}
+
+/// Convex pieces of a simple polygon:
+/// ear clipping triangles greedily merged while their union stays convex
+pub fn convex_decomposition(points: &[Point2]) -> Vec<Vec<Point2>> {
+    if is_convex(points) {
+        return vec![points.to_vec()];
+    }
+    let to_points =
+        |piece: &[usize]| piece.iter().map(|&k| points[k]).collect();
+    let mut pieces: Vec<Vec<usize>> =
+        ear_clipping(points).iter().map(|t| t.to_vec()).collect();
+    let mut merged = true;
+    while merged {
+        merged = false;
+        'search: for i in 0..pieces.len() {
+            for j in i + 1..pieces.len() {
+                if let Some(union) = merge_pieces(&pieces[i], &pieces[j]) {
+                    let union_points: Vec<Point2> = to_points(&union);
+                    if is_convex(&union_points) {
+                        pieces[i] = union;
+                        pieces.remove(j);
+                        merged = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+    pieces.iter().map(|piece| to_points(piece)).collect()
+}
+
+/// Intersection of two convex polygons
+pub fn convex_intersection(subject: &[Point2], clip: &[Point2]) -> Vec<Point2> {
+    let mut clip = clip.to_vec();
+    if polygon_area(&clip) < 0.0 {
+        clip.reverse();
+    }
+    let mut res = subject.to_vec();
+    for i in 0..clip.len() {
+        res = clip_by_line(&res, clip[i], clip[(i + 1) % clip.len()]);
+        if res.len() < 3 {
+            return vec![];
+        }
+    }
+    res
+}
+
 // @vlad TODO refactor (it's copy paste from stack overflow)
 /// get tangent to circle from point
 pub fn get_tangent(
@@ -289,6 +534,9 @@ pub trait TriangulateFromCenter {
 #[derive(Debug, Component, Clone)]
 pub struct Polygon {
     pub points: Vec<Point2>,
+    /// convex decomposition, outline itself for convex polygons
+    pub pieces: Vec<Vec<Point2>>,
+    carved: bool,
     mass_center: Point2,
     pub min_r: f32,
     pub max_r: f32,
@@ -297,64 +545,31 @@ pub struct Polygon {
 }
 
 impl Polygon {
+    /// Cut every corner with a quadratic Bezier curve.
+    /// Reflex corners are cut the same way, so concavities are kept
     pub fn into_rounded(self, smooth_points: usize) -> Self {
+        let smooth_points = smooth_points.max(1);
+        let n = self.points.len();
         let mut res = vec![];
-        for i in 0..self.points.len() {
-            let prev = self.points[if i == 0 {
-                self.points.len() - 1
-            } else {
-                (i - 1) % self.points.len()
-            }];
+        for i in 0..n {
+            let prev = self.points[(i + n - 1) % n];
             let p = self.points[i];
-            let next = self.points[(i + 1) % self.points.len()];
-            let edge_vec1 = p.coords - prev.coords;
-            let edge_vec2 = next.coords - p.coords;
-            let segment1 = Segment::new(prev, prev + edge_vec1);
-            let inside_vec =
-                (-edge_vec1.normalize() + edge_vec2.normalize()) / 2.0;
-            let d = 1.0
-                * inside_vec
-                    .dot(&edge_vec1)
-                    .abs()
-                    .min(inside_vec.dot(&edge_vec2).abs());
-            let inside_vec = d * inside_vec;
-            let o = p + inside_vec;
-            let mut h1 = Vector2::new(-edge_vec1.y, edge_vec1.x).normalize();
-            let mut dbg_flag = false;
-            {
-                // try different direction of perpendicular
-                let ray1 = Ray::new(o, h1);
-                let ray2 = Ray::new(o, -h1);
-                // dbg!((&ray1, &segment1));
-                let toi1 =
-                    segment1.toi_with_ray(&Isometry2::identity(), &ray1, true);
-                let toi2 =
-                    segment1.toi_with_ray(&Isometry2::identity(), &ray2, true);
-                match (toi1, toi2) {
-                    (Some(toi), _) => {
-                        h1 = h1 * toi;
-                        dbg_flag = true;
-                    }
-                    (_, Some(toi)) => {
-                        h1 = -h1 * toi;
-                        dbg_flag = true;
-                    }
-                    _ => (),
-                }
-            };
-            if dbg_flag {
-                let angle = edge_vec1.angle(&edge_vec2);
-                // if smooth_points == 1 {
-                //     res.push(o + Rotation2::new(-angle / 2.0) * h1);
-                //     continue;
-                // }
-                let rotation = Rotation2::new(-angle / (smooth_points as f32));
-                for _ in 0..=smooth_points {
-                    res.push(o + h1);
-                    h1 = rotation * h1;
-                }
-            } else {
-                res.push(p)
+            let next = self.points[(i + 1) % n];
+            // less than a half of the edge, so neighbour cuts don't meet
+            let cut = ROUNDING_CUT * (p - prev).norm().min((next - p).norm());
+            if cut < EPS {
+                res.push(p);
+                continue;
+            }
+            let start = p + (prev - p).normalize() * cut;
+            let end = p + (next - p).normalize() * cut;
+            for j in 0..=smooth_points {
+                let t = j as f32 / smooth_points as f32;
+                res.push(Point2::from(
+                    (1.0 - t) * (1.0 - t) * start.coords
+                        + 2.0 * t * (1.0 - t) * p.coords
+                        + t * t * end.coords,
+                ));
             }
         }
         Self::new(res)
@@ -383,13 +598,12 @@ impl Polygon {
             min_r = min_r.min((p - center).norm());
             max_r = max_r.max((p - center).norm());
         }
-        if (points[0].coords - center.coords)
-            .perp(&(points[1].coords - center.coords))
-            > 0.0
-        {
+        if polygon_area(&points) > 0.0 {
             points.reverse();
         }
         Polygon {
+            pieces: convex_decomposition(&points),
+            carved: false,
             points: points,
             mass_center: center,
             min_r,
@@ -399,15 +613,80 @@ impl Polygon {
         }
     }
 
+    /// Polygon made of convex pieces, outline is their convex hull.
+    /// Mass center and radii are taken from the pieces, not from the hull
+    pub fn from_pieces(pieces: Vec<Vec<Point2>>) -> Self {
+        let points: Vec<Point2> = pieces.iter().flatten().cloned().collect();
+        let hull = convex_hull_idx(&points)
+            .iter()
+            .map(|&i| points[i])
+            .collect();
+        let mut polygon = Polygon::new(hull);
+        let mut area = 0f32;
+        let mut center = Vector2::zeros();
+        for piece in pieces.iter() {
+            let piece_area = polygon_area(piece).abs();
+            area += piece_area;
+            center += piece_area * polygon_centroid(piece).coords;
+        }
+        if area > EPS * EPS {
+            polygon.mass_center = Point2::from(center / area);
+        }
+        polygon.min_r = std::f32::MAX;
+        polygon.max_r = 0f32;
+        for p in points.iter() {
+            let r = (p - polygon.mass_center).norm();
+            polygon.min_r = polygon.min_r.min(r);
+            polygon.max_r = polygon.max_r.max(r);
+        }
+        polygon.pieces = pieces;
+        polygon.carved = true;
+        polygon
+    }
+
+    /// Outline of carved polygon is only a hull of its pieces
+    pub fn is_carved(&self) -> bool {
+        self.carved
+    }
+
     pub fn centralize(&mut self, rot: Rotation2<f32>) {
-        for p in self.points.iter_mut() {
+        let mass_center = self.mass_center;
+        let transform = |p: &mut Point2| {
             *p = rot * *p;
-            p.x -= self.mass_center.x;
-            p.y -= self.mass_center.y;
-        }
+            p.x -= mass_center.x;
+            p.y -= mass_center.y;
+        };
+        self.points.iter_mut().for_each(transform);
+        self.pieces.iter_mut().flatten().for_each(transform);
         self.mass_center = Point2::new(0f32, 0f32);
     }
 
+    /// Subtract convex crater from the polygon.
+    /// Parts which are not connected anymore become separate polygons
+    pub fn carve(&self, crater: &[Point2]) -> Vec<Polygon> {
+        let mut groups: Vec<Vec<Vec<Point2>>> = vec![];
+        for piece in self.pieces.iter() {
+            for piece in convex_difference(piece, crater) {
+                if polygon_area(&piece).abs() < CARVE_MIN_AREA {
+                    continue;
+                }
+                let mut group = vec![];
+                let mut i = 0;
+                while i < groups.len() {
+                    if groups[i].iter().any(|other| pieces_touch(&piece, other))
+                    {
+                        group.extend(groups.remove(i));
+                    } else {
+                        i += 1;
+                    }
+                }
+                group.push(piece);
+                groups.push(group);
+            }
+        }
+        groups.into_iter().map(Polygon::from_pieces).collect()
+    }
+
     pub fn deconstruct(&self, bullet: Point2, sites: usize) -> Vec<Polygon> {
         if self.max_r < 1.2 {
             return vec![];
@@ -436,9 +715,16 @@ impl Polygon {
                 p.y *= h_div;
                 p.y -= self.height / 2.0;
             }
-            let poly = Polygon::new(poly.to_vec());
-            if poly.min_r > DECONSTRUCT_SHADR_MIN_R {
-                res.push(poly);
+            // voronoi cells are clipped by the outline as if it was convex
+            for piece in self.pieces.iter() {
+                let shard = convex_intersection(&poly, piece);
+                if shard.len() < 3 {
+                    continue;
+                }
+                let shard = Polygon::new(shard);
+                if shard.min_r > DECONSTRUCT_SHADR_MIN_R {
+                    res.push(shard);
+                }
             }
         }
         res
@@ -453,6 +739,31 @@ impl TriangulateFromCenter for Polygon {
     fn center(&self) -> Point2 {
         self.mass_center
     }
+
+    /// fan for every convex piece, so carved polygons keep craters
+    fn triangulate(&self) -> Triangulation {
+        let mut points = vec![];
+        let mut indicies = vec![];
+        for piece in self.pieces.iter() {
+            let shift = points.len() as u16;
+            let w = 1.0 / piece.len() as f32;
+            let mut center = Point2::new(0f32, 0f32);
+            for p in piece.iter() {
+                center += w * p.coords;
+            }
+            points.push(center);
+            points.extend(piece.iter());
+            for i in 1..=piece.len() {
+                indicies.push(shift);
+                indicies.push(shift + i as u16);
+                indicies.push(shift + (i % piece.len()) as u16 + 1);
+            }
+        }
+        Triangulation {
+            points: points,
+            indicies: indicies,
+        }
+    }
 }
 
 fn x_angle(vec: Vector2) -> f32 {
@@ -490,13 +801,15 @@ pub fn poly_to_segment(poly: Polygon, position: Point2) -> BlockSegment {
     BlockSegment { point1, point2 }
 }
 
+/// Shadow quads for every convex piece of the occluder,
+/// so concave and carved polygons don't cast their hull
 pub fn shadow_geometry(
     center: Point2,
     geom: Geometry,
     position: Point2,
     rotation: Rotation2<f32>,
 ) -> Option<Triangulation> {
-    let segment = match geom {
+    let segments = match geom {
         Geometry::Circle { radius } => {
             let dirs = match get_tangent(position, radius, center) {
                 (Some(p1), Some(p2)) => Some((
@@ -508,36 +821,43 @@ pub fn shadow_geometry(
             if let Some((dir1, dir2)) = dirs {
                 let shape_point1 = center + dir1;
                 let shape_point2 = center + dir2;
-                Some(BlockSegment {
+                vec![BlockSegment {
                     point1: shape_point1,
                     point2: shape_point2,
-                })
+                }]
             } else {
-                None
+                vec![]
             }
         }
-        Geometry::Polygon(mut block_polygon) => {
-            let points: Vec<Point2> =
-                block_polygon.points.iter().map(|x| rotation * x).collect();
-            block_polygon.points = points;
-            Some(poly_to_segment(block_polygon, position))
-        }
+        Geometry::Polygon(block_polygon) => block_polygon
+            .pieces
+            .iter()
+            .map(|piece| {
+                let points: Vec<Point2> =
+                    piece.iter().map(|x| rotation * x).collect();
+                poly_to_segment(Polygon::new(points), position)
+            })
+            .collect(),
     };
-    if let Some(segment) = segment {
+    if segments.is_empty() {
+        return None;
+    }
+    let mut points = vec![];
+    let mut indicies = vec![];
+    for segment in segments.iter() {
+        let shift = points.len() as u16;
         let dir1 = segment.point1.coords + position.coords - center.coords;
         let dir2 = segment.point2.coords + position.coords - center.coords;
-        let points = vec![
+        points.extend(&[
             segment.point1,
             segment.point2,
             segment.point1 + SHADOW_LENGTH * dir1,
             segment.point2 + SHADOW_LENGTH * dir2,
-        ];
-        let indicies = vec![0, 2, 3, 0, 3, 1];
-        Some(Triangulation {
-            points: points,
-            indicies: indicies,
-        })
-    } else {
-        None
+        ]);
+        indicies.extend([0, 2, 3, 0, 3, 1].iter().map(|i| shift + i));
     }
+    Some(Triangulation {
+        points: points,
+        indicies: indicies,
+    })
 }
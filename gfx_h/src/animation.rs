@@ -3,6 +3,7 @@ use specs_derive::Component;
 
 // use crate::Image;
 use crate::AtlasImage;
+pub use packer::LoopMode;
 
 #[derive(Debug, Clone, Copy)]
 pub struct AnimationFrame {
@@ -13,41 +14,96 @@ pub struct AnimationFrame {
 #[derive(Component, Debug, Clone)]
 pub struct Animation {
     frames: Vec<AnimationFrame>,
-    iterations: usize,
-    current_iteration: usize,
+    mode: LoopMode,
+    /// (frame, event) from the atlas metadata
+    events: Vec<(usize, String)>,
     pub current_frame: usize,
     frame_ticks: usize,
+    // going backwards in ping-pong mode
+    reverse: bool,
+    finished: bool,
+    // frame returned by the last next_frame if it was just entered
+    entered: Option<usize>,
 }
 
 impl Animation {
     pub fn new(
         frames: Vec<AnimationFrame>,
-        iterations: usize,
-        current_frame: usize,
+        mode: LoopMode,
+        events: Vec<(usize, String)>,
     ) -> Self {
         assert!(frames.len() > 0);
         Animation {
             frames,
-            iterations,
-            current_iteration: 0,
-            current_frame,
+            mode,
+            events,
+            current_frame: 0,
             frame_ticks: 0,
+            reverse: false,
+            finished: false,
+            entered: None,
         }
     }
 
+    fn advance(&mut self) {
+        let last = self.frames.len() - 1;
+        match self.mode {
+            LoopMode::Once => {
+                if self.current_frame < last {
+                    self.current_frame += 1;
+                } else {
+                    self.finished = true;
+                }
+            }
+            LoopMode::Loop => {
+                self.current_frame =
+                    (self.current_frame + 1) % self.frames.len()
+            }
+            LoopMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if self.reverse && self.current_frame == 0 {
+                    self.reverse = false;
+                } else if !self.reverse && self.current_frame == last {
+                    self.reverse = true;
+                }
+                if self.reverse {
+                    self.current_frame -= 1;
+                } else {
+                    self.current_frame += 1;
+                }
+            }
+        }
+    }
+
+    /// Frame to draw on this tick, None when a `Once` animation is over
     pub fn next_frame(&mut self) -> Option<AnimationFrame> {
+        if self.finished {
+            self.entered = None;
+            return None;
+        }
         let res = self.frames[self.current_frame];
+        self.entered = if self.frame_ticks == 0 {
+            Some(self.current_frame)
+        } else {
+            None
+        };
         self.frame_ticks += 1;
         if self.frame_ticks >= res.ticks {
-            self.current_frame = (self.current_frame + 1) % self.frames.len();
-            if self.current_frame == 0 {
-                self.current_iteration += 1;
-            }
             self.frame_ticks = 0;
-        }
-        if self.current_iteration >= self.iterations {
-            return None;
+            self.advance();
         }
         Some(res)
     }
+
+    /// Events of the frame returned by the last `next_frame`,
+    /// only on the tick the frame is entered
+    pub fn events<'a>(&'a self) -> impl Iterator<Item = &'a str> + 'a {
+        let entered = self.entered;
+        self.events
+            .iter()
+            .filter(move |(frame, _)| Some(*frame) == entered)
+            .map(|(_, event)| event.as_str())
+    }
 }
@@ -0,0 +1,672 @@
+// Executors of the frames recorded by `Canvas`
+use super::*;
+use glyph_brush::{
+    rusttype::Scale, HorizontalAlign, Layout, Section, VerticalAlign,
+};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+/// sprite batch buffers are never smaller than that
+const MIN_BATCH_CAPACITY: usize = 64;
+
+/// Draws the frame recorded by `Canvas`
+pub trait Backend {
+    fn execute(&mut self, viewport: &red::Viewport, commands: CommandList);
+}
+
+/// Null backend which keeps frames instead of drawing them, for tests
+#[derive(Default)]
+pub struct RecordingBackend {
+    pub frames: Vec<CommandList>,
+}
+
+impl Backend for RecordingBackend {
+    fn execute(&mut self, _viewport: &red::Viewport, commands: CommandList) {
+        self.frames.push(commands);
+    }
+}
+
+/// GPU buffers by the handle of their contents.
+/// Buffers which were not drawn during a frame are dropped at its end
+struct BufferCache<T> {
+    buffers: HashMap<MeshHandle, T>,
+    used: HashSet<MeshHandle>,
+}
+
+impl<T> Default for BufferCache<T> {
+    fn default() -> Self {
+        BufferCache {
+            buffers: HashMap::new(),
+            used: HashSet::new(),
+        }
+    }
+}
+
+impl<T> BufferCache<T> {
+    /// uploads the buffers only if they are not cached yet
+    fn get_or_create(
+        &mut self,
+        handle: MeshHandle,
+        create: impl FnOnce() -> Result<T, String>,
+    ) -> Result<&T, String> {
+        self.used.insert(handle);
+        match self.buffers.entry(handle) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(create()?)),
+        }
+    }
+
+    fn end_frame(&mut self) {
+        let used = &self.used;
+        self.buffers.retain(|handle, _| used.contains(handle));
+        self.used.clear();
+    }
+}
+
+/// projection and view matrices of the frame
+struct FrameView {
+    perspective: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    orthographic: [[f32; 4]; 4],
+}
+
+impl FrameView {
+    fn new(viewport: &red::Viewport, observer: Point3) -> Self {
+        let dims = viewport.dimensions();
+        let dims = (dims.0 as u32, dims.1 as u32);
+        FrameView {
+            perspective: perspective(dims.0, dims.1).to_homogeneous().into(),
+            view: get_view(observer).to_homogeneous().into(),
+            orthographic: orthographic(dims.0, dims.1).to_homogeneous().into(),
+        }
+    }
+
+    /// projection and view
+    fn matrices(
+        &self,
+        with_projection: bool,
+    ) -> ([[f32; 4]; 4], [[f32; 4]; 4]) {
+        if with_projection {
+            (self.perspective, self.view)
+        } else {
+            (self.orthographic, Matrix4::identity().into())
+        }
+    }
+}
+
+fn image_draw_params(
+    stencil: StencilMode,
+    blend: bool,
+    draw_type: DrawType,
+) -> DrawParams {
+    let blend = if blend { Some(red::Blend) } else { None };
+    match stencil {
+        StencilMode::Check => red::DrawParams {
+            draw_type,
+            stencil: Some(Stencil {
+                ref_value: 1,
+                mask: 0xFF,
+                test: StencilTest::NotEqual,
+                pass_operation: None,
+            }),
+            blend,
+            ..Default::default()
+        },
+        _ => DrawParams {
+            draw_type,
+            blend,
+            ..Default::default()
+        },
+    }
+}
+
+/// Draws recorded frames with OpenGL
+pub struct GlBackend {
+    gl: red::GL,
+    program_light: red::Program,
+    program_instancing: red::Program,
+    program_primitive: red::Program,
+    program_primitive_texture: red::Program,
+    program_sprite_batch: red::Program,
+    program_trail: red::Program,
+    program_glyph: red::Program,
+    program_atlas: red::Program,
+    atlas: red::shader::Texture,
+    image_model: ImageModel,
+    text_data: TextData<'static>,
+    // separate brush, world text is drawn with a different transformation
+    world_text_data: TextData<'static>,
+    post_process: Option<PostProcess>,
+    // instance buffers reused between frames, one per batch of the frame
+    batches: Vec<SpriteBatch>,
+    // geometry and instance shapes
+    meshes: BufferCache<GeometryData>,
+    instances: BufferCache<WorldVertexBuffer<WorldVertex>>,
+    trails:
+        BufferCache<(TrailVertexBuffer<TrailVertex>, red::buffer::IndexBuffer)>,
+}
+
+impl GlBackend {
+    pub fn new(
+        gl: red::GL,
+        pref: &str,
+        atlas: &str,
+        glsl_version: &str,
+        font: &'static [u8],
+    ) -> Result<Self, String> {
+        let program_primitive =
+            create_shader_program(&gl, pref, "primitive", glsl_version)?;
+        let program_primitive_texture = create_shader_program(
+            &gl,
+            pref,
+            "primitive_texture",
+            glsl_version,
+        )?;
+        let program_light =
+            create_shader_program(&gl, pref, "light", glsl_version)?;
+        let program_instancing =
+            create_shader_program(&gl, pref, "instancing", glsl_version)?;
+        let program_glyph =
+            create_shader_program(&gl, pref, "text", glsl_version)?;
+        let program_atlas =
+            create_shader_program(&gl, pref, "atlas", glsl_version)?;
+        let program_sprite_batch =
+            create_shader_program(&gl, pref, "spritebatch", glsl_version)?;
+        let program_trail =
+            create_shader_program(&gl, pref, "trail", glsl_version)?;
+        let atlas = load_texture(&gl, atlas);
+        let image_model = ImageModel::new(&gl)?;
+        let text_data = TextData::new(&gl, font)?;
+        let world_text_data = TextData::new(&gl, font)?;
+        // frames are drawn straight to the screen without the passes
+        let post_process = match PostProcess::new(&gl, pref, glsl_version) {
+            Ok(post_process) => Some(post_process),
+            Err(err) => {
+                log::warn!("post processing is disabled: {}", err);
+                None
+            }
+        };
+        Ok(GlBackend {
+            gl,
+            program_light,
+            program_instancing,
+            program_primitive,
+            program_primitive_texture,
+            program_sprite_batch,
+            program_trail,
+            program_glyph,
+            program_atlas,
+            atlas,
+            image_model,
+            text_data,
+            world_text_data,
+            post_process,
+            batches: vec![],
+            meshes: BufferCache::default(),
+            instances: BufferCache::default(),
+            trails: BufferCache::default(),
+        })
+    }
+
+    pub fn gl(&self) -> &red::GL {
+        &self.gl
+    }
+
+    fn draw_geometry(
+        &mut self,
+        frame: &mut red::Frame,
+        view: &FrameView,
+        program: ProgramKind,
+        model: &Transform,
+        mesh: &Mesh,
+        color: (f32, f32, f32),
+        with_projection: bool,
+        stencil: StencilMode,
+    ) {
+        let gl = &self.gl;
+        let geometry_data = match self
+            .meshes
+            .get_or_create(mesh.handle(), || GeometryData::from_mesh(gl, mesh))
+        {
+            Ok(geometry_data) => geometry_data,
+            Err(err) => {
+                log::warn!("failed to create geometry: {}", err);
+                return;
+            }
+        };
+        let model: [[f32; 4]; 4] = model.isometry().to_homogeneous().into();
+        let (projection, view) = view.matrices(with_projection);
+        let vao = &geometry_data.positions.vao;
+        let program = match program {
+            ProgramKind::Primitive => {
+                let program = &self.program_primitive;
+                program.set_uniform("projection", projection);
+                program.set_uniform("fill_color", color);
+                program
+            }
+            _ => {
+                let program = &self.program_light;
+                program.set_uniform("perspective", projection);
+                program.set_uniform("color", color);
+                program
+            }
+        };
+        program.set_uniform("model", model);
+        program.set_uniform("view", view);
+        program.set_layout(&self.gl, vao, &[&geometry_data.positions]);
+        let draw_params = RenderMode::from(stencil).into();
+        frame.draw(
+            vao,
+            Some(&geometry_data.index_buffer),
+            program,
+            &draw_params,
+        );
+    }
+
+    fn draw_image(
+        &self,
+        frame: &mut red::Frame,
+        view: &FrameView,
+        program: ProgramKind,
+        model: &Transform,
+        region: &Region,
+        scale: f32,
+        with_projection: bool,
+        stencil: StencilMode,
+        blend: bool,
+    ) {
+        let model: [[f32; 4]; 4] = model.isometry().to_homogeneous().into();
+        let (projection, view) = view.matrices(with_projection);
+        let vao = &self.image_model.positions.vao;
+        let program = match program {
+            ProgramKind::PrimitiveTexture => {
+                let program = &self.program_primitive_texture;
+                program.set_uniform("projection", projection);
+                program
+            }
+            _ => {
+                let program = &self.program_atlas;
+                program.set_uniform("perspective", projection);
+                program.set_uniform("scale", scale);
+                program
+            }
+        };
+        program.set_uniform("model", model);
+        program.set_uniform("view", view);
+        program.set_uniform("tex", self.atlas.clone()); // this is just shallow copy if idx
+        program.set_uniform("dim_scales", region.dim_scales);
+        program.set_uniform("offset", region.offset);
+        program.set_uniform("fraction_wh", region.fraction_wh);
+        program.set_layout(&self.gl, vao, &[&self.image_model.positions]);
+        let draw_params = image_draw_params(stencil, blend, DrawType::Standart);
+        frame.draw(vao, Some(&self.image_model.indices), program, &draw_params);
+    }
+
+    fn draw_batch(
+        &mut self,
+        frame: &mut red::Frame,
+        view: &FrameView,
+        id: usize,
+        sprites: &[Sprite],
+        stencil: StencilMode,
+        blend: bool,
+    ) {
+        let images: Vec<AtlasImage> = sprites
+            .iter()
+            .map(|sprite| (&sprite.region).into())
+            .collect();
+        let isometries: Vec<Isometry3> = sprites
+            .iter()
+            .map(|sprite| sprite.model.isometry())
+            .collect();
+        let sizes: Vec<f32> =
+            sprites.iter().map(|sprite| sprite.size).collect();
+        let capacity =
+            sprites.len().next_power_of_two().max(MIN_BATCH_CAPACITY);
+        if id >= self.batches.len() {
+            self.batches
+                .push(SpriteBatch::with_capacity(&self.gl, capacity));
+        } else if self.batches[id].capacity() < sprites.len() {
+            self.batches[id] = SpriteBatch::with_capacity(&self.gl, capacity);
+        }
+        let sprite_batch = &mut self.batches[id];
+        sprite_batch.update(&images, &isometries, &sizes);
+        let vao = &sprite_batch.instancing_data.image_model.positions.vao;
+        let program = &self.program_sprite_batch;
+        program.set_uniform("view", view.view);
+        program.set_uniform("perspective", view.perspective);
+        program.set_uniform("tex", self.atlas.clone());
+        program.set_layout(
+            &self.gl,
+            vao,
+            &[
+                &sprite_batch.instancing_data.image_model.positions,
+                &sprite_batch.instancing_data.regions,
+                &sprite_batch.instancing_data.isometries,
+            ],
+        );
+        let draw_type = red::DrawType::Instancing(sprite_batch.len);
+        let draw_params = image_draw_params(stencil, blend, draw_type);
+        frame.draw(
+            vao,
+            Some(&sprite_batch.instancing_data.image_model.indices),
+            program,
+            &draw_params,
+        );
+    }
+
+    fn draw_instances(
+        &mut self,
+        frame: &mut red::Frame,
+        view: &FrameView,
+        model: &Transform,
+        mesh: &Mesh,
+        positions: &[(f32, f32, f32)],
+    ) {
+        let gl = &self.gl;
+        let shape = match self
+            .meshes
+            .get_or_create(mesh.handle(), || GeometryData::from_mesh(gl, mesh))
+        {
+            Ok(shape) => shape,
+            Err(err) => {
+                log::warn!("failed to create instance shape: {}", err);
+                return;
+            }
+        };
+        let handle = MeshHandle::new(
+            positions.iter().flat_map(|&(x, y, z)| {
+                std::iter::once(x).chain(Some(y)).chain(Some(z))
+            }),
+            &[],
+        );
+        let per_instance = match self.instances.get_or_create(handle, || {
+            let per_instance: Vec<WorldVertex> = positions
+                .iter()
+                .map(|&(x, y, z)| WorldVertex {
+                    world_position: red::data::f32_f32_f32::new(x, y, z),
+                })
+                .collect();
+            WorldVertexBuffer::new(gl, &per_instance)
+        }) {
+            Ok(per_instance) => per_instance,
+            Err(err) => {
+                log::warn!("failed to create instance positions: {}", err);
+                return;
+            }
+        };
+        let model: [[f32; 4]; 4] = model.isometry().to_homogeneous().into();
+        let vao = &shape.positions.vao;
+        let program = &self.program_instancing;
+        program.set_uniform("model", model);
+        program.set_uniform("view", view.view);
+        program.set_uniform("perspective", view.perspective);
+        program.set_uniform("transparency", 1f32);
+        program.set_layout(gl, vao, &[&shape.positions, per_instance]);
+        let draw_params = red::DrawParams {
+            stencil: None,
+            draw_type: red::DrawType::Instancing(positions.len()),
+            ..Default::default()
+        };
+        frame.draw(vao, Some(&shape.index_buffer), program, &draw_params);
+    }
+
+    fn draw_trails(
+        &mut self,
+        frame: &mut red::Frame,
+        view: &FrameView,
+        ribbons: &[Vec<TrailPoint>],
+    ) {
+        if ribbons.iter().all(|ribbon| ribbon.len() < 2) {
+            return;
+        }
+        // ribbon lengths are hashed too, so points can't move between them
+        let handle = MeshHandle::new(
+            ribbons.iter().flat_map(|ribbon| {
+                std::iter::once(ribbon.len() as f32).chain(
+                    ribbon.iter().flat_map(|point| {
+                        let (x, y) = point.position;
+                        let (r, g, b, a) = point.color;
+                        vec![x, y, point.half_width, r, g, b, a]
+                    }),
+                )
+            }),
+            &[],
+        );
+        let gl = &self.gl;
+        let trail_buffers = self.trails.get_or_create(handle, || {
+            let mut trail_mesh = TrailMesh::default();
+            for ribbon in ribbons.iter() {
+                trail_mesh.push_ribbon(ribbon.iter().map(|point| {
+                    let (x, y) = point.position;
+                    (Point2::new(x, y), point.half_width, point.color)
+                }));
+            }
+            let vertex_buffer =
+                TrailVertexBuffer::new(gl, trail_mesh.vertices())?;
+            let index_buffer =
+                red::buffer::IndexBuffer::new(gl, trail_mesh.indices())?;
+            Ok((vertex_buffer, index_buffer))
+        });
+        let (vertex_buffer, index_buffer) = match trail_buffers {
+            Ok((vertex_buffer, index_buffer)) => (vertex_buffer, index_buffer),
+            Err(err) => {
+                log::warn!("failed to create trail buffers: {}", err);
+                return;
+            }
+        };
+        let vao = &vertex_buffer.vao;
+        let program = &self.program_trail;
+        program.set_uniform("view", view.view);
+        program.set_uniform("perspective", view.perspective);
+        program.set_layout(gl, vao, &[vertex_buffer]);
+        let draw_params = DrawParams {
+            blend: Some(red::Blend),
+            ..Default::default()
+        };
+        frame.draw(vao, Some(index_buffer), program, &draw_params);
+    }
+
+    fn draw_text(
+        &mut self,
+        frame: &mut red::Frame,
+        view: &FrameView,
+        world: bool,
+        runs: &[TextRun],
+    ) {
+        let text_data = if world {
+            &mut self.world_text_data
+        } else {
+            &mut self.text_data
+        };
+        for run in runs.iter() {
+            text_data.glyph_brush.queue(Section {
+                text: &run.text,
+                scale: Scale::uniform(run.scale),
+                screen_position: run.position,
+                color: [run.color.0, run.color.1, run.color.2, run.color.3],
+                layout: Layout::default()
+                    .h_align(HorizontalAlign::Center)
+                    .v_align(VerticalAlign::Center),
+                ..Section::default()
+            });
+        }
+        // TODO move to resource
+        let max_image_dimension = {
+            let value =
+                unsafe { frame.gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) };
+            value as u32
+        };
+        let mut brush_action;
+        loop {
+            let current_texture = text_data.glyph_texture.texture.clone();
+            brush_action = text_data.glyph_brush.process_queued(
+                |rect, tex_data| unsafe {
+                    // Update part of gpu texture with new glyph alpha values
+                    frame
+                        .gl
+                        .bind_texture(glow::TEXTURE_2D, Some(current_texture));
+                    frame.gl.tex_sub_image_2d_u8_slice(
+                        glow::TEXTURE_2D,
+                        -0,
+                        rect.min.x as _,
+                        rect.min.y as _,
+                        rect.width() as _,
+                        rect.height() as _,
+                        glow::RED,
+                        glow::UNSIGNED_BYTE,
+                        Some(tex_data),
+                    );
+                    gl_assert_ok(&frame.gl)
+                },
+                to_vertex,
+            );
+            match brush_action {
+                Ok(_) => break,
+                Err(BrushError::TextureTooSmall { suggested, .. }) => {
+                    let (new_width, new_height) = if (suggested.0
+                        > max_image_dimension
+                        || suggested.1 > max_image_dimension)
+                        && (text_data.glyph_brush.texture_dimensions().0
+                            < max_image_dimension
+                            || text_data.glyph_brush.texture_dimensions().1
+                                < max_image_dimension)
+                    {
+                        (max_image_dimension, max_image_dimension)
+                    } else {
+                        suggested
+                    };
+                    // Recreate texture as a larger size to fit more
+                    text_data.glyph_texture =
+                        Texture::new(&frame.gl, (new_width, new_height));
+                    text_data.glyph_brush.resize_texture(new_width, new_height);
+                }
+            }
+        }
+        match brush_action.unwrap() {
+            BrushAction::Draw(vertices) => {
+                text_data.vertex_num = vertices.len() as i32;
+                unsafe {
+                    text_data.vertex_buffer.dynamic_draw_data(
+                        std::slice::from_raw_parts(
+                            vertices.as_ptr() as *const TextVertex,
+                            vertices.len(),
+                        ),
+                    );
+                }
+            }
+            BrushAction::ReDraw => {}
+        }
+        // world text is already projected on cpu
+        let (transform, view) = view.matrices(false);
+        let program = &self.program_glyph;
+        program.set_uniform("transform", transform);
+        program.set_uniform("view", view);
+        program.set_uniform("font_tex", text_data.glyph_texture.clone());
+        let text_vb: &TextVertexBuffer<TextVertex> = &text_data.vertex_buffer;
+        program.set_layout(&frame.gl, &text_vb.vao, &[text_vb]);
+        let vao = &text_vb.vao;
+        unsafe {
+            vao.bind();
+            program.set_used();
+            frame.gl.draw_arrays_instanced(
+                glow::TRIANGLE_STRIP,
+                0,
+                4,
+                text_data.vertex_num,
+            );
+            vao.unbind()
+        }
+    }
+}
+
+impl Backend for GlBackend {
+    fn execute(&mut self, viewport: &red::Viewport, commands: CommandList) {
+        let mut frame = red::Frame::new(&self.gl);
+        let (x, y, z) = commands.observer;
+        let view = FrameView::new(viewport, Point3::new(x, y, z));
+        let mut batch_id = 0;
+        for command in commands.commands.iter() {
+            match command {
+                DrawCommand::Clear { color } => {
+                    frame.set_clear_color(color.0, color.1, color.2, color.3);
+                    frame.set_clear_stencil(0);
+                    frame.clear_color_and_stencil();
+                }
+                DrawCommand::BeginPostProcess { settings } => {
+                    if let Some(post_process) = &mut self.post_process {
+                        post_process.begin(&self.gl, viewport, settings);
+                    }
+                }
+                DrawCommand::EndPostProcess { settings, params } => {
+                    if let Some(post_process) = &self.post_process {
+                        post_process.end(
+                            &self.gl, &mut frame, viewport, settings, params,
+                        );
+                    }
+                }
+                DrawCommand::Geometry {
+                    program,
+                    model,
+                    mesh,
+                    color,
+                    with_projection,
+                    stencil,
+                } => self.draw_geometry(
+                    &mut frame,
+                    &view,
+                    *program,
+                    model,
+                    mesh,
+                    *color,
+                    *with_projection,
+                    *stencil,
+                ),
+                DrawCommand::Image {
+                    program,
+                    model,
+                    region,
+                    scale,
+                    with_projection,
+                    stencil,
+                    blend,
+                } => self.draw_image(
+                    &mut frame,
+                    &view,
+                    *program,
+                    model,
+                    region,
+                    *scale,
+                    *with_projection,
+                    *stencil,
+                    *blend,
+                ),
+                DrawCommand::Batch {
+                    sprites,
+                    stencil,
+                    blend,
+                } => {
+                    self.draw_batch(
+                        &mut frame, &view, batch_id, sprites, *stencil, *blend,
+                    );
+                    batch_id += 1;
+                }
+                DrawCommand::Instances {
+                    model,
+                    mesh,
+                    positions,
+                } => self
+                    .draw_instances(&mut frame, &view, model, mesh, positions),
+                DrawCommand::Text { world, runs } => {
+                    self.draw_text(&mut frame, &view, *world, runs)
+                }
+                DrawCommand::Trails { ribbons } => {
+                    self.draw_trails(&mut frame, &view, ribbons)
+                }
+            }
+        }
+        self.meshes.end_frame();
+        self.instances.end_frame();
+        self.trails.end_frame();
+    }
+}
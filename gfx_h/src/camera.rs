@@ -0,0 +1,278 @@
+use super::*;
+use noise::{NoiseFn, Perlin, Seedable};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Half of the vertical field of view from `perspective`
+const HALF_FOV_TAN: f32 = 0.577;
+
+/// Perlin wobble of the observer, amplitude decays exponentially
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ShakeProfile {
+    pub amplitude: f32,
+    /// noise steps per second
+    pub frequency: f32,
+    /// part of amplitude lost per second, as in exp(-decay * t)
+    pub decay: f32,
+}
+
+/// Pan to a point of interest and back, e.g. for boss intros
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CinematicDesc {
+    /// seconds the camera stays on the target
+    pub duration: f32,
+    /// seconds to pan there and back
+    pub blend: f32,
+    /// extra z while on the target
+    pub zoom: f32,
+}
+
+/// Content of rons/camera.ron
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraConfig {
+    /// z distances switched with zoom in/out
+    pub zoom_levels: Vec<f32>,
+    pub default_zoom: usize,
+    /// extra z at max speed
+    pub speed_zoom: f32,
+    /// part of the z change taken every second
+    pub zoom_smoothing: f32,
+    /// the observer is shifted by velocity times that
+    pub look_ahead: f32,
+    pub look_ahead_smoothing: f32,
+    /// shift towards the mouse direction
+    pub mouse_offset: f32,
+    pub mouse_smoothing: f32,
+    /// targets further than that from the player aren't framed
+    pub framing_radius: f32,
+    /// free space around framed targets, 1.0 is none
+    pub framing_margin: f32,
+    pub cinematic: CinematicDesc,
+    pub shakes: HashMap<String, ShakeProfile>,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            zoom_levels: vec![15.0],
+            default_zoom: 0,
+            speed_zoom: 10.0,
+            zoom_smoothing: 2.4,
+            look_ahead: 0.0,
+            look_ahead_smoothing: 1.0,
+            mouse_offset: 2.0,
+            mouse_smoothing: 0.6,
+            framing_radius: 0.0,
+            framing_margin: 1.0,
+            cinematic: CinematicDesc {
+                duration: 0.0,
+                blend: 1.0,
+                zoom: 0.0,
+            },
+            shakes: HashMap::new(),
+        }
+    }
+}
+
+impl CameraConfig {
+    /// Camera indexes zoom levels, so there has to be at least one
+    pub fn validate(&self) -> Result<(), String> {
+        if self.zoom_levels.is_empty() {
+            return Err("zoom_levels of the camera are empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Shake {
+    profile: ShakeProfile,
+    age: f32,
+}
+
+impl Shake {
+    fn amplitude(&self) -> f32 {
+        self.profile.amplitude * (-self.profile.decay * self.age).exp()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cinematic {
+    target: Point2,
+    age: f32,
+}
+
+/// Where the world is looked from. Updated by CameraSystem,
+/// rendering hands the observer to the Canvas
+pub struct Camera {
+    config: CameraConfig,
+    zoom: usize,
+    center: Point2,
+    z: f32,
+    look_ahead: Vector2,
+    direction_offset: Vector2,
+    shakes: Vec<Shake>,
+    perlin_x: Perlin,
+    perlin_y: Perlin,
+    // seconds since creation, phase of the shake noise
+    time: f32,
+    cinematic: Option<Cinematic>,
+    // kept after the cinematic ends to pan back from it
+    cinematic_target: Point2,
+    // 0 is the player, 1 is the cinematic target
+    cinematic_blend: f32,
+}
+
+/// Half width and height of the game plane seen from the observer
+pub fn visible_half_extents(observer: Point3, aspect: f32) -> Vector2 {
+    let half_height = observer.z * HALF_FOV_TAN;
+    Vector2::new(aspect * half_height, half_height)
+}
+
+fn smooth(rate: f32, delta: f32) -> f32 {
+    (rate * delta).min(1.0)
+}
+
+impl Camera {
+    pub fn new(config: CameraConfig) -> Self {
+        let zoom = config.default_zoom.min(config.zoom_levels.len() - 1);
+        let z = config.zoom_levels[zoom];
+        Camera {
+            config,
+            zoom,
+            center: Point2::new(0.0, 0.0),
+            z,
+            look_ahead: Vector2::new(0.0, 0.0),
+            direction_offset: Vector2::new(0.0, 0.0),
+            shakes: vec![],
+            perlin_x: Perlin::new().set_seed(0),
+            perlin_y: Perlin::new().set_seed(1),
+            time: 0.0,
+            cinematic: None,
+            cinematic_target: Point2::new(0.0, 0.0),
+            cinematic_blend: 0.0,
+        }
+    }
+
+    /// z of the game plane without speed and framing zoom
+    pub fn z_far(&self) -> f32 {
+        self.config.zoom_levels[self.zoom]
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom = self.zoom.saturating_sub(1);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = (self.zoom + 1).min(self.config.zoom_levels.len() - 1);
+    }
+
+    /// Start the shake profile by name, unknown names are ignored
+    pub fn shake(&mut self, name: &str) -> bool {
+        if let Some(profile) = self.config.shakes.get(name) {
+            self.shakes.push(Shake {
+                profile: *profile,
+                age: 0.0,
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Pan to the target for the configured duration
+    pub fn cinematic(&mut self, target: Point2) {
+        self.cinematic = Some(Cinematic { target, age: 0.0 });
+    }
+
+    pub fn is_cinematic(&self) -> bool {
+        self.cinematic.is_some()
+    }
+
+    /// delta is in seconds, speed_ratio is in [0, 1] at normal speeds,
+    /// targets are framed together with the player
+    pub fn update(
+        &mut self,
+        delta: f32,
+        player: Point2,
+        velocity: Vector2,
+        speed_ratio: f32,
+        direction: Vector2,
+        targets: &[Point2],
+        aspect: f32,
+    ) {
+        let config = &self.config;
+        self.look_ahead += smooth(config.look_ahead_smoothing, delta)
+            * (config.look_ahead * velocity - self.look_ahead);
+        self.direction_offset += smooth(config.mouse_smoothing, delta)
+            * (direction - self.direction_offset);
+        let mut center = player + self.look_ahead;
+        let mut z = self.z_far() + config.speed_zoom * speed_ratio;
+        let targets: Vec<Point2> = targets
+            .iter()
+            .filter(|target| (**target - player).norm() < config.framing_radius)
+            .cloned()
+            .collect();
+        if !targets.is_empty() {
+            let mut min = center;
+            let mut max = center;
+            for target in targets.iter() {
+                min = Point2::new(min.x.min(target.x), min.y.min(target.y));
+                max = Point2::new(max.x.max(target.x), max.y.max(target.y));
+            }
+            center = Point2::from((min.coords + max.coords) / 2.0);
+            let half = (max - min) / 2.0;
+            let framed_z = config.framing_margin * half.y.max(half.x / aspect)
+                / HALF_FOV_TAN;
+            z = z.max(framed_z);
+        }
+        let blend_step = delta / config.cinematic.blend.max(1e-3);
+        let cinematic_end = config.cinematic.duration + config.cinematic.blend;
+        let mut cinematic_target = None;
+        if let Some(ref mut cinematic) = self.cinematic {
+            cinematic.age += delta;
+            self.cinematic_blend = (self.cinematic_blend + blend_step).min(1.0);
+            cinematic_target = Some(cinematic.target);
+        } else {
+            self.cinematic_blend = (self.cinematic_blend - blend_step).max(0.0);
+        }
+        if self.cinematic.map_or(false, |c| c.age > cinematic_end) {
+            self.cinematic = None;
+        }
+        if let Some(target) = cinematic_target {
+            self.cinematic_target = target;
+        }
+        if self.cinematic_blend > 0.0 {
+            // smoothstep, so the pan eases in and out
+            let t = self.cinematic_blend;
+            let t = t * t * (3.0 - 2.0 * t);
+            center += t * (self.cinematic_target - center);
+            z += t * config.cinematic.zoom;
+        }
+        self.center = center;
+        self.z += smooth(config.zoom_smoothing, delta) * (z - self.z);
+        self.time += delta;
+        for shake in self.shakes.iter_mut() {
+            shake.age += delta;
+        }
+        self.shakes.retain(|shake| shake.amplitude() > 1e-3);
+    }
+
+    pub fn observer(&self) -> Point3 {
+        let mut noise = Vector3::new(0.0, 0.0, 0.0);
+        for shake in self.shakes.iter() {
+            let time = (self.time * shake.profile.frequency) as f64;
+            let x = self.perlin_x.get([time, time]) as f32;
+            let y = self.perlin_y.get([time, time]) as f32;
+            noise += shake.amplitude() * Vector3::new(x, y, 0.0);
+        }
+        let offset = if self.is_cinematic() {
+            Vector2::new(0.0, 0.0)
+        } else {
+            self.config.mouse_offset * self.direction_offset
+        };
+        Point3::new(self.center.x, self.center.y, self.z)
+            + noise
+            + Vector3::new(offset.x, offset.y, 0.0)
+    }
+}
@@ -0,0 +1,289 @@
+use super::{AtlasImage, PostProcessParams, PostProcessSettings, RenderMode};
+use common::*;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use specs_derive::Component;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// shader program of the backend the command is executed with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ProgramKind {
+    Light,
+    Primitive,
+    PrimitiveTexture,
+    Atlas,
+}
+
+/// stencil usage of the draw call, see `RenderMode`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StencilMode {
+    Write,
+    Check,
+    Off,
+    ShadowMark,
+    LightReveal,
+    ShadowClear,
+}
+
+impl From<&RenderMode> for StencilMode {
+    fn from(render_mode: &RenderMode) -> Self {
+        match render_mode {
+            RenderMode::StencilWrite => StencilMode::Write,
+            RenderMode::StencilCheck => StencilMode::Check,
+            RenderMode::Draw => StencilMode::Off,
+            RenderMode::ShadowMark => StencilMode::ShadowMark,
+            RenderMode::LightReveal => StencilMode::LightReveal,
+            RenderMode::ShadowClear => StencilMode::ShadowClear,
+        }
+    }
+}
+
+impl From<StencilMode> for RenderMode {
+    fn from(stencil: StencilMode) -> Self {
+        match stencil {
+            StencilMode::Write => RenderMode::StencilWrite,
+            StencilMode::Check => RenderMode::StencilCheck,
+            StencilMode::Off => RenderMode::Draw,
+            StencilMode::ShadowMark => RenderMode::ShadowMark,
+            StencilMode::LightReveal => RenderMode::LightReveal,
+            StencilMode::ShadowClear => RenderMode::ShadowClear,
+        }
+    }
+}
+
+impl StencilMode {
+    pub fn with_lights(with_lights: bool) -> Self {
+        if with_lights {
+            StencilMode::Check
+        } else {
+            StencilMode::Off
+        }
+    }
+}
+
+/// position and z-rotation of the model
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub position: (f32, f32, f32),
+    pub angle: f32,
+}
+
+impl From<&Isometry3> for Transform {
+    fn from(iso: &Isometry3) -> Self {
+        let position = iso.translation.vector;
+        Transform {
+            position: (position.x, position.y, position.z),
+            angle: iso.rotation.euler_angles().2,
+        }
+    }
+}
+
+impl Transform {
+    pub fn isometry(&self) -> Isometry3 {
+        let (x, y, z) = self.position;
+        Isometry3::new(
+            Vector3::new(x, y, z),
+            Vector3::new(0.0, 0.0, self.angle),
+        )
+    }
+}
+
+/// part of the atlas texture used by the image
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub offset: (f32, f32),
+    pub fraction_wh: (f32, f32),
+    pub dim_scales: (f32, f32),
+    pub transparency: f32,
+    pub color: (f32, f32, f32, f32),
+}
+
+impl From<&AtlasImage> for Region {
+    fn from(image: &AtlasImage) -> Self {
+        Region {
+            offset: image.offset,
+            fraction_wh: image.fraction_wh,
+            dim_scales: image.dim_scales,
+            transparency: image.transparency,
+            color: image.color,
+        }
+    }
+}
+
+impl From<&Region> for AtlasImage {
+    fn from(region: &Region) -> Self {
+        AtlasImage {
+            offset: region.offset,
+            fraction_wh: region.fraction_wh,
+            dim_scales: region.dim_scales,
+            transparency: region.transparency,
+            color: region.color,
+        }
+    }
+}
+
+/// Triangles in model space, uploaded by the backend when drawn
+#[derive(
+    Debug, Clone, Default, PartialEq, Component, Serialize, Deserialize,
+)]
+pub struct Mesh {
+    pub positions: Vec<(f32, f32)>,
+    pub indices: Vec<u16>,
+}
+
+impl Mesh {
+    pub fn new(points: &[Point2], indices: &[u16]) -> Self {
+        Mesh {
+            positions: points.iter().map(|p| (p.x, p.y)).collect(),
+            indices: indices.to_vec(),
+        }
+    }
+
+    /// quads of `line_width` from a to b, all in one mesh
+    pub fn lines(lines: &[(Point2, Point2)], line_width: f32) -> Self {
+        let mut positions = vec![];
+        let mut indices = vec![];
+        for (a, b) in lines.iter() {
+            let line_length = (b.coords - a.coords).norm();
+            let up = Vector2::new(0.0, -line_length);
+            let rotation =
+                Rotation2::rotation_between(&up, &(&b.coords - a.coords));
+            let iso = Isometry2::new(a.coords, rotation.angle());
+            let base = positions.len() as u16;
+            for corner in [
+                Point2::new(-line_width / 2.0, 0f32),
+                Point2::new(line_width / 2.0, 0f32),
+                Point2::new(-line_width / 2.0, -line_length),
+                Point2::new(line_width / 2.0, -line_length),
+            ]
+            .iter()
+            {
+                let corner = iso * corner;
+                positions.push((corner.x, corner.y));
+            }
+            indices.extend([0u16, 1, 2, 1, 3, 2].iter().map(|i| base + i));
+        }
+        Mesh { positions, indices }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn handle(&self) -> MeshHandle {
+        MeshHandle::new(
+            self.positions
+                .iter()
+                .flat_map(|&(x, y)| std::iter::once(x).chain(Some(y))),
+            &self.indices,
+        )
+    }
+}
+
+/// Hash of the mesh contents, backend keeps GPU buffers by it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshHandle(u64);
+
+impl MeshHandle {
+    /// equal values and indices give equal handles
+    pub fn new(values: impl Iterator<Item = f32>, indices: &[u16]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        for value in values {
+            value.to_bits().hash(&mut hasher);
+        }
+        indices.hash(&mut hasher);
+        MeshHandle(hasher.finish())
+    }
+}
+
+/// instance of a sprite batch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sprite {
+    pub region: Region,
+    pub model: Transform,
+    pub size: f32,
+}
+
+/// point of a trail ribbon, from the head to the tail
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrailPoint {
+    pub position: (f32, f32),
+    pub half_width: f32,
+    pub color: (f32, f32, f32, f32),
+}
+
+/// text queued into glyph brush, `position` is in screen coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextRun {
+    pub text: String,
+    pub position: (f32, f32),
+    pub scale: f32,
+    pub color: (f32, f32, f32, f32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DrawCommand {
+    /// color and stencil of the frame
+    Clear {
+        color: (f32, f32, f32, f32),
+    },
+    /// following commands are drawn into the offscreen target
+    BeginPostProcess {
+        settings: PostProcessSettings,
+    },
+    /// passes over the offscreen target, drawn to the screen
+    EndPostProcess {
+        settings: PostProcessSettings,
+        params: PostProcessParams,
+    },
+    /// colored mesh, also used for stencil writes of light and shadows
+    Geometry {
+        program: ProgramKind,
+        model: Transform,
+        mesh: Mesh,
+        color: (f32, f32, f32),
+        with_projection: bool,
+        stencil: StencilMode,
+    },
+    /// single textured quad
+    Image {
+        program: ProgramKind,
+        model: Transform,
+        region: Region,
+        scale: f32,
+        with_projection: bool,
+        stencil: StencilMode,
+        blend: bool,
+    },
+    /// atlas sprites in one instanced draw
+    Batch {
+        sprites: Vec<Sprite>,
+        stencil: StencilMode,
+        blend: bool,
+    },
+    /// copies of the mesh at `positions`
+    Instances {
+        model: Transform,
+        mesh: Mesh,
+        positions: Vec<(f32, f32, f32)>,
+    },
+    Text {
+        world: bool,
+        runs: Vec<TextRun>,
+    },
+    /// ribbons of all trails batched into one mesh
+    Trails {
+        ribbons: Vec<Vec<TrailPoint>>,
+    },
+}
+
+/// Everything `Canvas` was asked to draw during the frame,
+/// executed by a `Backend`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandList {
+    /// camera of the frame
+    pub observer: (f32, f32, f32),
+    pub z_far: f32,
+    pub commands: Vec<DrawCommand>,
+}
@@ -5,13 +5,12 @@ extern crate log;
 extern crate android_log;
 
 pub use common;
-use noise::{NoiseFn, Perlin, Seedable};
 pub use rand;
 use rand::prelude::*;
 use specs::prelude::*;
 use specs_derive::Component;
 
-use std::io::{BufReader, Error as IOError, Read};
+use std::io::{BufReader, Error as IOError, ErrorKind, Read};
 
 use nalgebra::geometry::Orthographic3;
 
@@ -25,6 +24,7 @@ use red::VertexAttribPointers;
 use glyph_brush::{
     rusttype::{point, Rect},
     BrushAction, BrushError, DefaultSectionHasher, GlyphBrush,
+    GlyphBrushBuilder,
 };
 use packer::SerializedSpriteSheet;
 use red::data::*;
@@ -33,15 +33,25 @@ use red::{DrawParams, DrawType, Operation, Stencil, StencilTest};
 use sdl2::rwops::RWops;
 use std::path::Path;
 
+pub mod backend;
+pub use backend::*;
 pub mod effects;
 pub use effects::*;
 pub mod animation;
 pub use animation::*;
+pub mod commands;
+pub use commands::*;
+pub mod postprocess;
+pub use postprocess::*;
+pub mod particles;
+pub use particles::*;
+pub mod trails;
+pub use trails::*;
+pub mod camera;
+pub use camera::*;
 
 const Z_CANVAS: f32 = 0f32;
 const Z_FAR: f32 = 15f32;
-const MAX_ADD_SPEED_Z: f32 = 10f32;
-const SPEED_EMA: f32 = 0.04f32; // new value will be taken with with that coef
 pub const _BACKGROUND_SIZE: f32 = 20f32;
 
 pub fn iso3_iso2(iso3: &Isometry3) -> Isometry2 {
@@ -132,6 +142,22 @@ impl GeometryData {
             index_buffer,
         })
     }
+
+    pub fn from_mesh(gl: &red::GL, mesh: &Mesh) -> Result<Self, String> {
+        let shape: Vec<GeometryVertex> = mesh
+            .positions
+            .iter()
+            .map(|&(x, y)| GeometryVertex {
+                position: red::data::f32_f32::new(x, y),
+            })
+            .collect();
+        let vertex_buffer = GeometryVertexBuffer::new(gl, &shape)?;
+        let index_buffer = red::buffer::IndexBuffer::new(gl, &mesh.indices)?;
+        Ok(GeometryData {
+            positions: vertex_buffer,
+            index_buffer,
+        })
+    }
 }
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -198,11 +224,18 @@ pub struct TextData<'a> {
     pub glyph_brush: GlyphBrush<'a, GlyphVertex, DefaultSectionHasher>,
 }
 
-pub struct WorldTextData<'a> {
-    pub vertex_buffer: TextVertexBuffer<TextVertex>,
-    pub vertex_num: i32,
-    pub glyph_texture: red::Texture,
-    pub glyph_brush: GlyphBrush<'a, GlyphVertex, DefaultSectionHasher>,
+impl TextData<'static> {
+    pub fn new(gl: &red::GL, font: &'static [u8]) -> Result<Self, String> {
+        let glyph_brush = GlyphBrushBuilder::using_font_bytes(font).build();
+        let glyph_texture =
+            red::Texture::new(gl, glyph_brush.texture_dimensions());
+        Ok(TextData {
+            vertex_buffer: TextVertexBuffer::empty_new(gl)?,
+            vertex_num: 0,
+            glyph_texture,
+            glyph_brush,
+        })
+    }
 }
 
 pub struct ImageModel {
@@ -343,7 +376,7 @@ impl ImageData {
 pub fn read_file(filename: &str) -> Result<String, IOError> {
     let mut result_str = String::new();
     let mut rw = RWops::from_file(Path::new(filename), "r")
-        .expect(&format!("failed to load {}", filename).to_string());
+        .map_err(|err| IOError::new(ErrorKind::NotFound, err))?;
     rw.read_to_string(&mut result_str)?;
     Ok(result_str)
 }
@@ -393,10 +426,34 @@ pub fn create_shader_program(
     Ok(program)
 }
 
+/// Shadows are kept in the stencil buffer: 1 is for the dark area.
+/// Extra lights are applied one by one with
+/// ShadowMark -> LightReveal -> ShadowClear
 pub enum RenderMode {
     StencilWrite,
     StencilCheck,
     Draw,
+    /// dark pixels in the shadow of the current light: 1 -> 2
+    ShadowMark,
+    /// dark pixels lit by the current light: 1 -> 0
+    LightReveal,
+    /// still dark after the current light: 2 -> 1
+    ShadowClear,
+}
+
+/// replace stencil with `ref_value` where (stencil & mask) != (ref_value & mask)
+fn stencil_replace(ref_value: i32, mask: u32) -> DrawParams {
+    red::DrawParams {
+        draw_type: DrawType::Standart,
+        stencil: Some(Stencil {
+            ref_value: ref_value as _,
+            mask: mask as _,
+            test: StencilTest::NotEqual,
+            pass_operation: Some(Operation::Replace),
+        }),
+        color_mask: (false, false, false, false),
+        ..Default::default()
+    }
 }
 
 impl Into<DrawParams> for RenderMode {
@@ -428,773 +485,247 @@ impl Into<DrawParams> for RenderMode {
                 blend: Some(red::Blend),
                 ..Default::default()
             },
+            RenderMode::ShadowMark => stencil_replace(2, 1),
+            RenderMode::LightReveal => stencil_replace(0, 1),
+            RenderMode::ShadowClear => stencil_replace(1, 2),
         }
     }
 }
 
-/// 2D graphics
+/// 2D graphics. Draw calls are recorded into `CommandList`,
+/// a `Backend` executes them at the end of the frame
 pub struct Canvas {
-    program: red::Program, // @vlad TODO: we want to use many programs
-    program_light: red::Program, // but for now simpler=better
-    program_instancing: red::Program,
-    program_primitive: red::Program,
-    program_primitive_texture: red::Program,
-    program_sprite_batch: red::Program,
-    pub program_glyph: red::Program,
-    program_atlas: red::Program,
+    // set from the Camera every frame
     observer: Point3,
-    perlin_x: Perlin,
-    perlin_y: Perlin,
-    perlin_time: f32,
-    camera_wobble: f32,
-    direction: Vector2,
-    direction_offset: Vector2,
     pub z_far: f32,
-    atlas: red::shader::Texture,
-    image_model: ImageModel,
-    // default_params: glium::DrawParameters<'a>,
-    // stencil_check_params: glium::DrawParameters<'a>,
-    // stencil_write_params: glium::DrawParameters<'a>,
+    commands: Vec<DrawCommand>,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Canvas {
-    pub fn new(
-        gl: &red::GL,
-        pref: &str,
-        atlas: &str,
-        glsl_version: &str,
-    ) -> Result<Self, String> {
-        let program = create_shader_program(gl, pref, "", glsl_version)?;
-        let program_primitive =
-            create_shader_program(gl, pref, "primitive", glsl_version)?;
-        let program_primitive_texture =
-            create_shader_program(gl, pref, "primitive_texture", glsl_version)?;
-        let program_light =
-            create_shader_program(gl, pref, "light", glsl_version)?;
-        let program_instancing =
-            create_shader_program(gl, pref, "instancing", glsl_version)?;
-        let program_glyph =
-            create_shader_program(gl, pref, "text", &glsl_version)?;
-        let program_atlas =
-            create_shader_program(gl, pref, "atlas", &glsl_version)?;
-        let program_sprite_batch =
-            create_shader_program(gl, pref, "spritebatch", &glsl_version)?;
+    pub fn new() -> Self {
         let z_far = Z_FAR;
-        let atlas = load_texture(gl, atlas);
-        let image_model = ImageModel::new(gl).expect("failed image model");
-        Ok(Canvas {
-            program,
-            program_primitive,
-            program_primitive_texture,
-            program_light,
-            program_instancing,
-            program_atlas,
-            program_sprite_batch,
+        Canvas {
             observer: Point3::new(0f32, 0f32, z_far),
-            program_glyph,
-            perlin_x: Perlin::new().set_seed(0),
-            perlin_y: Perlin::new().set_seed(1),
-            perlin_time: 0.1f32,
-            camera_wobble: 0f32,
-            direction: Vector2::new(0f32, 0f32),
-            direction_offset: Vector2::new(0f32, 0f32),
             z_far,
-            atlas,
-            image_model,
-        })
+            commands: vec![],
+        }
+    }
+
+    /// what was drawn since the last call
+    pub fn take_commands(&mut self) -> CommandList {
+        let observer = self.observer;
+        CommandList {
+            observer: (observer.x, observer.y, observer.z),
+            z_far: self.z_far,
+            commands: std::mem::take(&mut self.commands),
+        }
+    }
+
+    fn record(&mut self, command: DrawCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn clear(&mut self, color: (f32, f32, f32, f32)) {
+        self.record(DrawCommand::Clear { color });
+    }
+
+    pub fn begin_post_process(&mut self, settings: &PostProcessSettings) {
+        self.record(DrawCommand::BeginPostProcess {
+            settings: *settings,
+        });
+    }
+
+    pub fn end_post_process(
+        &mut self,
+        settings: &PostProcessSettings,
+        params: &PostProcessParams,
+    ) {
+        self.record(DrawCommand::EndPostProcess {
+            settings: *settings,
+            params: *params,
+        });
     }
 
     /// draw lines with only one draw call
     pub fn draw_lines(
-        &self,
+        &mut self,
         lines: &[(Point2, Point2)],
-        context: &red::GL,
-        frame: &mut red::Frame,
-        viewport: &red::Viewport,
         color: Point3,
         line_width: f32,
     ) {
-        let mut positions = vec![];
-        let mut indices = vec![];
-        let mut cur_id = 0u16;
-        for (a, b) in lines.iter() {
-            let line_length = (b.coords - a.coords).norm();
-            let current_positions = vec![
-                Point2::new(-line_width / 2.0, 0f32),
-                Point2::new(line_width / 2.0, 0f32),
-                Point2::new(-line_width / 2.0, -line_length),
-                Point2::new(line_width / 2.0, -line_length),
-            ];
-            let up = Vector2::new(0.0, -line_length);
-            let rotation =
-                Rotation2::rotation_between(&up, &(&b.coords - a.coords));
-            let iso = Isometry3::new(
-                Vector3::new(a.x, a.y, 0f32),
-                Vector3::new(0f32, 0f32, rotation.angle()),
-            );
-            let current_indices = [0u16, 1, 2, 1, 3, 2];
-            positions
-                .extend(current_positions.iter().map(|x| iso3_iso2(&iso) * x));
-            indices.extend(current_indices.iter().map(|x| cur_id + x));
-            cur_id += current_indices.len() as u16;
-        }
-        let geometry_data =
-            GeometryData::new(&context, &positions, &indices).unwrap();
         self.render_geometry(
-            &context,
-            &viewport,
-            frame,
-            &geometry_data,
-            &Isometry3::new(
-                Vector3::new(0.0, 0.0, 0.0),
-                Vector3::new(0f32, 0f32, 0f32),
-            ),
+            Mesh::lines(lines, line_width),
+            &Isometry3::identity(),
             RenderMode::Draw,
             color,
         );
     }
 
     pub fn draw_line(
-        &self,
+        &mut self,
         a: Point2,
         b: Point2,
-        context: &red::GL,
-        frame: &mut red::Frame,
-        viewport: &red::Viewport,
         color: Point3,
         line_width: f32,
     ) {
-        let line_length = (b.coords - a.coords).norm();
-        let positions = vec![
-            Point2::new(-line_width / 2.0, 0f32),
-            Point2::new(line_width / 2.0, 0f32),
-            Point2::new(-line_width / 2.0, -line_length),
-            Point2::new(line_width / 2.0, -line_length),
-        ];
-        let up = Vector2::new(0.0, -line_length);
-        let rotation =
-            Rotation2::rotation_between(&up, &(&b.coords - a.coords));
-        let iso = Isometry3::new(
-            Vector3::new(a.x, a.y, 0f32),
-            Vector3::new(0f32, 0f32, rotation.angle()),
-        );
-        let indices = [0u16, 1, 2, 1, 3, 2];
-        let geometry_data =
-            GeometryData::new(&context, &positions, &indices).unwrap();
-        self.render_geometry(
-            &context,
-            &viewport,
-            frame,
-            &geometry_data,
-            &iso,
-            RenderMode::Draw,
-            color,
-        );
+        self.draw_lines(&[(a, b)], color, line_width);
     }
 
     pub fn observer(&self) -> Point3 {
-        // let mut rng = rand::thread_rng();
-        // let noise: Vector3 = 0.1 * Vector3::new(rng.gen(), rng.gen(), 0f32).normalize();
-        let time = self.perlin_time as f64;
-        let x = self.perlin_x.get([time, time]);
-        let y = self.perlin_y.get([time, time]);
-        let noise: Vector3 =
-            self.camera_wobble * Vector3::new(x as f32, y as f32, 0f32);
         self.observer
-            + noise
-            + 2f32
-                * Vector3::new(
-                    self.direction_offset.x,
-                    self.direction_offset.y,
-                    0f32,
-                )
     }
 
-    pub fn add_wobble(&mut self, wobble: f32) {
-        self.camera_wobble += wobble;
-    }
-
-    pub fn update_observer(
-        &mut self,
-        pos: Point2,
-        speed_ratio: f32,
-        direction: Vector2,
-    ) {
-        self.observer.x = pos.x;
-        self.observer.y = pos.y;
-        self.observer.z = (1.0 - SPEED_EMA) * self.observer.z
-            + SPEED_EMA * (self.z_far + MAX_ADD_SPEED_Z * speed_ratio);
-        self.perlin_time += 0.1;
-        self.camera_wobble /= 2.0;
-        self.direction = direction;
-        self.direction_offset = self.direction_offset * 0.99 + 0.01 * direction;
+    pub fn set_observer(&mut self, observer: Point3, z_far: f32) {
+        self.observer = observer;
+        self.z_far = z_far;
     }
 
     pub fn get_z_shift(&self) -> f32 {
         self.observer.z - self.z_far
     }
 
-    pub fn render_text(
-        &self,
-        text_data: &mut TextData,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
-        persp: bool,
-    ) {
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let program = &self.program_glyph;
-        let transform: [[f32; 4]; 4] = if persp {
-            perspective(dims.0, dims.1).to_homogeneous().into()
-        } else {
-            orthographic(dims.0, dims.1).to_homogeneous().into()
-        };
-        let view: [[f32; 4]; 4] = if persp {
-            get_view(self.observer()).to_homogeneous().into()
-        } else {
-            Matrix4::identity().into()
-        };
-        // TODO move to resource
-        let max_image_dimension = {
-            let value =
-                unsafe { frame.gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) };
-            value as u32
-        };
-        let mut brush_action;
-        loop {
-            let current_texture = text_data.glyph_texture.texture.clone();
-            brush_action = text_data.glyph_brush.process_queued(
-                |rect, tex_data| unsafe {
-                    // eprintln!("{:?}, {:?}", rect, tex_data);
-                    // Update part of gpu texture with new glyph alpha values
-                    frame
-                        .gl
-                        .bind_texture(glow::TEXTURE_2D, Some(current_texture));
-                    frame.gl.tex_sub_image_2d_u8_slice(
-                        glow::TEXTURE_2D,
-                        -0,
-                        rect.min.x as _,
-                        rect.min.y as _,
-                        rect.width() as _,
-                        rect.height() as _,
-                        glow::RED,
-                        glow::UNSIGNED_BYTE,
-                        Some(tex_data),
-                    );
-                    gl_assert_ok(&frame.gl)
-                },
-                to_vertex,
-            );
-            match brush_action {
-                Ok(_) => break,
-                Err(BrushError::TextureTooSmall { suggested, .. }) => {
-                    let (new_width, new_height) = if (suggested.0
-                        > max_image_dimension
-                        || suggested.1 > max_image_dimension)
-                        && (text_data.glyph_brush.texture_dimensions().0
-                            < max_image_dimension
-                            || text_data.glyph_brush.texture_dimensions().1
-                                < max_image_dimension)
-                    {
-                        (max_image_dimension, max_image_dimension)
-                    } else {
-                        suggested
-                    };
-
-                    // Recreate texture as a larger size to fit more
-                    text_data.glyph_texture =
-                        Texture::new(&frame.gl, (new_width, new_height)); //GlGlyphTexture::new((new_width, new_height));
-
-                    text_data.glyph_brush.resize_texture(new_width, new_height);
-                }
-            }
-        }
-
-        match brush_action.unwrap() {
-            BrushAction::Draw(vertices) => {
-                text_data.vertex_num = vertices.len() as i32;
-                unsafe {
-                    text_data.vertex_buffer.dynamic_draw_data(
-                        std::slice::from_raw_parts(
-                            vertices.as_ptr() as *const TextVertex,
-                            vertices.len(),
-                        ),
-                    );
-                }
-            }
-            // vertex_max = vertex_max.max(vertex_count);
-            // }
-            BrushAction::ReDraw => {}
-        }
-        program.set_uniform("transform", transform);
-        program.set_uniform("font_tex", text_data.glyph_texture.clone());
-        program.set_uniform("view", view);
-        let text_vb: &TextVertexBuffer<TextVertex> = &text_data.vertex_buffer;
-        program.set_layout(&frame.gl, &text_vb.vao, &[text_vb]);
-        let vao = &text_vb.vao;
-        unsafe {
-            vao.bind();
-            program.set_used();
-            frame.gl.draw_arrays_instanced(
-                glow::TRIANGLE_STRIP,
-                0,
-                4,
-                text_data.vertex_num,
-            );
-            vao.unbind()
-        }
-    }
-
-    // copy paste (TODO. trait for text data...)
-    pub fn render_world_text(
-        &self,
-        text_data: &mut WorldTextData,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
-        persp: bool,
-    ) {
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let program = &self.program_glyph;
-        let view: [[f32; 4]; 4] = if persp {
-            get_view(self.observer()).to_homogeneous().into()
-        } else {
-            Matrix4::identity().into()
-        };
-        let transform: [[f32; 4]; 4] = if persp {
-            perspective(dims.0, dims.1).to_homogeneous().into()
-        // _orthographic_from_zero(dims.0, dims.1).to_homogeneous().into()
-        } else {
-            orthographic(dims.0, dims.1).to_homogeneous().into()
-        };
-
-        // TODO move to resource
-        let max_image_dimension = {
-            let value =
-                unsafe { frame.gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) };
-            value as u32
-        };
-        let mut brush_action;
-        loop {
-            let current_texture = text_data.glyph_texture.texture.clone();
-            brush_action = text_data.glyph_brush.process_queued(
-                |rect, tex_data| unsafe {
-                    // eprintln!("{:?}, {:?}", rect, tex_data);
-                    // Update part of gpu texture with new glyph alpha values
-                    frame
-                        .gl
-                        .bind_texture(glow::TEXTURE_2D, Some(current_texture));
-                    frame.gl.tex_sub_image_2d_u8_slice(
-                        glow::TEXTURE_2D,
-                        -0,
-                        rect.min.x as _,
-                        rect.min.y as _,
-                        rect.width() as _,
-                        rect.height() as _,
-                        glow::RED,
-                        glow::UNSIGNED_BYTE,
-                        Some(tex_data),
-                    );
-                    gl_assert_ok(&frame.gl)
-                },
-                to_vertex,
-            );
-            match brush_action {
-                Ok(_) => break,
-                Err(BrushError::TextureTooSmall { suggested, .. }) => {
-                    let (new_width, new_height) = if (suggested.0
-                        > max_image_dimension
-                        || suggested.1 > max_image_dimension)
-                        && (text_data.glyph_brush.texture_dimensions().0
-                            < max_image_dimension
-                            || text_data.glyph_brush.texture_dimensions().1
-                                < max_image_dimension)
-                    {
-                        (max_image_dimension, max_image_dimension)
-                    } else {
-                        suggested
-                    };
-                    // let (new_width, new_height) = (new_width / 50, new_height / 50);
-                    // Recreate texture as a larger size to fit more
-                    text_data.glyph_texture =
-                        Texture::new(&frame.gl, (new_width, new_height)); //GlGlyphTexture::new((new_width, new_height));
-
-                    text_data.glyph_brush.resize_texture(new_width, new_height);
-                }
-            }
-        }
-
-        match brush_action.unwrap() {
-            BrushAction::Draw(vertices) => {
-                text_data.vertex_num = vertices.len() as i32;
-                unsafe {
-                    text_data.vertex_buffer.dynamic_draw_data(
-                        std::slice::from_raw_parts(
-                            vertices.as_ptr() as *const TextVertex,
-                            vertices.len(),
-                        ),
-                    );
-                }
-            }
-            // vertex_max = vertex_max.max(vertex_count);
-            // }
-            BrushAction::ReDraw => {}
-        }
-        program.set_uniform("transform", transform);
-        program.set_uniform("view", view);
-        program.set_uniform("font_tex", text_data.glyph_texture.clone());
-        let text_vb: &TextVertexBuffer<TextVertex> = &text_data.vertex_buffer;
-        program.set_layout(&frame.gl, &text_vb.vao, &[text_vb]);
-        let vao = &text_vb.vao;
-        unsafe {
-            vao.bind();
-            program.set_used();
-            frame.gl.draw_arrays_instanced(
-                glow::TRIANGLE_STRIP,
-                0,
-                4,
-                text_data.vertex_num,
-            );
-            vao.unbind()
+    /// `world` text is projected on cpu, both are drawn in screen space
+    pub fn render_text(&mut self, runs: Vec<TextRun>, world: bool) {
+        if runs.is_empty() {
+            return;
         }
+        self.record(DrawCommand::Text { world, runs });
     }
 
     pub fn render_geometry(
-        &self,
-        gl: &red::GL,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
-        geometry_data: &GeometryData,
+        &mut self,
+        mesh: Mesh,
         model: &Isometry3,
         render_mode: RenderMode,
         color: Point3,
     ) {
-        let model: [[f32; 4]; 4] = model.to_homogeneous().into();
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let perspective: [[f32; 4]; 4] =
-            perspective(dims.0, dims.1).to_homogeneous().into();
-        let view: [[f32; 4]; 4] =
-            get_view(self.observer()).to_homogeneous().into();
-        let vao = &geometry_data.positions.vao;
-        let program = &self.program_light;
-        program.set_uniform("model", model);
-        program.set_uniform("view", view);
-        program.set_uniform("perspective", perspective);
-        program.set_uniform("color", (color.x, color.y, color.z));
-        program.set_layout(&gl, vao, &[&geometry_data.positions]);
-        let draw_params = render_mode.into();
-        frame.draw(
-            vao,
-            Some(&geometry_data.index_buffer),
-            &program,
-            &draw_params,
-        );
+        if mesh.is_empty() {
+            return;
+        }
+        self.record(DrawCommand::Geometry {
+            program: ProgramKind::Light,
+            model: model.into(),
+            mesh,
+            color: (color.x, color.y, color.z),
+            with_projection: true,
+            stencil: StencilMode::from(&render_mode),
+        });
     }
 
     pub fn render_primitive(
-        &self,
-        gl: &red::GL,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
-        geometry_data: &GeometryData,
+        &mut self,
+        mesh: Mesh,
         model: &Isometry3,
         fill_color: (f32, f32, f32),
         with_projection: bool,
         render_mode: RenderMode,
     ) {
-        let model: [[f32; 4]; 4] = model.to_homogeneous().into();
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let (projection, view) = if with_projection {
-            let perspective: [[f32; 4]; 4] =
-                perspective(dims.0, dims.1).to_homogeneous().into();
-            let view: [[f32; 4]; 4] =
-                get_view(self.observer()).to_homogeneous().into();
-            (perspective, view)
-        } else {
-            let orthographic: [[f32; 4]; 4] =
-                orthographic(dims.0, dims.1).to_homogeneous().into();
-            let view: [[f32; 4]; 4] = Matrix4::identity().into();
-            (orthographic, view)
-        };
-        let vao = &geometry_data.positions.vao;
-        let program = &self.program_primitive;
-        program.set_uniform("model", model);
-        program.set_uniform("view", view);
-        program.set_uniform("projection", projection);
-        program.set_uniform("fill_color", fill_color);
-        program.set_layout(&gl, vao, &[&geometry_data.positions]);
-        let draw_params = render_mode.into();
-        frame.draw(
-            vao,
-            Some(&geometry_data.index_buffer),
-            program,
-            &draw_params,
-        );
+        if mesh.is_empty() {
+            return;
+        }
+        self.record(DrawCommand::Geometry {
+            program: ProgramKind::Primitive,
+            model: model.into(),
+            mesh,
+            color: fill_color,
+            with_projection,
+            stencil: StencilMode::from(&render_mode),
+        });
     }
 
     pub fn render_atlas(
-        &self,
-        gl: &red::GL,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
+        &mut self,
         atlas_image: &AtlasImage,
         model: &Isometry3,
         scale: f32,
         with_lights: bool,
         blend: Option<red::Blend>,
     ) {
-        let model: [[f32; 4]; 4] = model.to_homogeneous().into();
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        // let draw_params = if with_lights {
-        //     &self.stencil_check_params
-        // } else {
-        //     &self.default_params
-        // };
-        let perspective: [[f32; 4]; 4] =
-            perspective(dims.0, dims.1).to_homogeneous().into();
-        let view: [[f32; 4]; 4] =
-            get_view(self.observer()).to_homogeneous().into();
-        let vao = &self.image_model.positions.vao;
-        let program = &self.program_atlas;
-        program.set_uniform("model", model);
-        program.set_uniform("view", view);
-        program.set_uniform("perspective", perspective);
-        program.set_uniform("dim_scales", atlas_image.dim_scales);
-        program.set_uniform("tex", self.atlas.clone()); // this is just shallow copy if idx
-        program.set_uniform("scale", scale);
-        program.set_uniform("offset", atlas_image.offset);
-        program.set_uniform("fraction_wh", atlas_image.fraction_wh);
-        program.set_layout(&gl, vao, &[&self.image_model.positions]);
-        let draw_params = if with_lights {
-            red::DrawParams {
-                draw_type: DrawType::Standart,
-                stencil: Some(Stencil {
-                    ref_value: 1,
-                    mask: 0xFF,
-                    test: StencilTest::NotEqual,
-                    pass_operation: None,
-                }),
-                blend,
-                ..Default::default()
-            }
-        } else {
-            DrawParams {
-                blend,
-                ..Default::default()
-            }
-        };
-        frame.draw(
-            vao,
-            Some(&self.image_model.indices),
-            &program,
-            &draw_params,
-        );
+        self.record(DrawCommand::Image {
+            program: ProgramKind::Atlas,
+            model: model.into(),
+            region: atlas_image.into(),
+            scale,
+            with_projection: true,
+            stencil: StencilMode::with_lights(with_lights),
+            blend: blend.is_some(),
+        });
     }
 
-    pub fn render(
-        &self,
-        gl: &red::GL,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
-        image_data: &ImageData,
-        model: &Isometry3,
-        scale: f32,
-        with_lights: bool,
-        blend: Option<red::Blend>,
-    ) {
-        let model: [[f32; 4]; 4] = model.to_homogeneous().into();
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let texture = &image_data.texture;
-        // let draw_params = if with_lights {
-        //     &self.stencil_check_params
-        // } else {
-        //     &self.default_params
-        // };
-        let scales = image_data.dim_scales;
-        let perspective: [[f32; 4]; 4] =
-            perspective(dims.0, dims.1).to_homogeneous().into();
-        let view: [[f32; 4]; 4] =
-            get_view(self.observer()).to_homogeneous().into();
-        let vao = &image_data.positions.vao;
-        let program = &self.program;
-        program.set_uniform("model", model);
-        program.set_uniform("view", view);
-        program.set_uniform("perspective", perspective);
-        program.set_uniform("dim_scales", (scales.x, scales.y));
-        program.set_uniform("tex", texture.clone());
-        program.set_uniform("scale", scale);
-        program.set_layout(&gl, vao, &[&image_data.positions]);
-        let draw_params = if with_lights {
-            red::DrawParams {
-                draw_type: DrawType::Standart,
-                stencil: Some(Stencil {
-                    ref_value: 1,
-                    mask: 0xFF,
-                    test: StencilTest::NotEqual,
-                    pass_operation: None,
-                }),
-                blend,
-                ..Default::default()
-            }
-        } else {
-            DrawParams {
-                blend,
-                ..Default::default()
-            }
-        };
-        frame.draw(vao, Some(&image_data.indices), &program, &draw_params);
+    pub fn render_trails(&mut self, ribbons: Vec<Vec<TrailPoint>>) {
+        if ribbons.is_empty() {
+            return;
+        }
+        self.record(DrawCommand::Trails { ribbons });
     }
 
     pub fn render_sprite_batch(
-        &self,
-        gl: &red::GL,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
-        sprite_batch: &SpriteBatch,
+        &mut self,
+        images: &[AtlasImage],
+        isometries: &[Isometry3],
+        sizes: &[f32],
         with_lights: bool,
         blend: Option<red::Blend>,
     ) {
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let perspective: [[f32; 4]; 4] =
-            perspective(dims.0, dims.1).to_homogeneous().into();
-        let view: [[f32; 4]; 4] =
-            get_view(self.observer()).to_homogeneous().into();
-        let vao = &sprite_batch.instancing_data.image_model.positions.vao;
-        let program = &self.program_sprite_batch;
-        program.set_uniform("view", view);
-        program.set_uniform("perspective", perspective);
-        // program.set_uniform("dim_scales", atlas_image.dim_scales);
-        program.set_uniform("tex", self.atlas.clone()); // this is just shallow copy if idx
-                                                        // program.set_uniform("scale", 1.0);
-        program.set_layout(
-            &gl,
-            vao,
-            &[
-                &sprite_batch.instancing_data.image_model.positions,
-                &sprite_batch.instancing_data.regions,
-                &sprite_batch.instancing_data.isometries,
-            ],
-        );
-        let draw_type = red::DrawType::Instancing(sprite_batch.len);
-        let draw_params = if with_lights {
-            red::DrawParams {
-                draw_type,
-                stencil: Some(Stencil {
-                    ref_value: 1,
-                    mask: 0xFF,
-                    test: StencilTest::NotEqual,
-                    pass_operation: None,
-                }),
-                blend,
-                ..Default::default()
-            }
-        } else {
-            DrawParams {
-                draw_type,
-                blend,
-                ..Default::default()
-            }
-        };
-        frame.draw(
-            vao,
-            Some(&sprite_batch.instancing_data.image_model.indices),
-            &program,
-            &draw_params,
-        );
+        if images.is_empty() {
+            return;
+        }
+        let sprites = images
+            .iter()
+            .zip(isometries.iter().zip(sizes.iter()))
+            .map(|(image, (iso, size))| Sprite {
+                region: image.into(),
+                model: iso.into(),
+                size: *size,
+            })
+            .collect();
+        self.record(DrawCommand::Batch {
+            sprites,
+            stencil: StencilMode::with_lights(with_lights),
+            blend: blend.is_some(),
+        });
     }
 
     pub fn render_instancing(
-        &self,
-        gl: &red::GL,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
-        instancing_data: &InstancingData,
+        &mut self,
+        mesh: &Mesh,
+        positions: &[Point3],
         model: &Isometry3,
-        // transparency: f32,
     ) {
-        let model: [[f32; 4]; 4] = model.to_homogeneous().into();
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let perspective: [[f32; 4]; 4] =
-            perspective(dims.0, dims.1).to_homogeneous().into();
-        let view: [[f32; 4]; 4] =
-            get_view(self.observer()).to_homogeneous().into();
-        let vao = &instancing_data.vertex_buffer.vao;
-        let program = &self.program_instancing;
-        program.set_uniform("model", model);
-        program.set_uniform("view", view);
-        program.set_uniform("perspective", perspective);
-        program.set_uniform("transparency", 1f32);
-        program.set_layout(
-            &gl,
-            vao,
-            &[
-                &instancing_data.vertex_buffer,
-                &instancing_data.per_instance,
-            ],
-        );
-        let draw_type = red::DrawType::Instancing(
-            instancing_data.per_instance.len.unwrap(),
-        );
-        let draw_params = red::DrawParams {
-            stencil: None,
-            draw_type,
-            ..Default::default()
-        };
-        frame.draw(vao, Some(&instancing_data.indices), &program, &draw_params);
+        if positions.is_empty() {
+            return;
+        }
+        self.record(DrawCommand::Instances {
+            model: model.into(),
+            mesh: mesh.clone(),
+            positions: positions.iter().map(|p| (p.x, p.y, p.z)).collect(),
+        });
     }
 
     pub fn render_primitive_texture(
-        &self,
-        gl: &red::GL,
-        viewport: &red::Viewport,
-        frame: &mut red::Frame,
+        &mut self,
         atlas_image: &AtlasImage,
         model: &Isometry3,
         with_projection: bool,
         dim_scales: (f32, f32),
     ) {
-        let model: [[f32; 4]; 4] = model.to_homogeneous().into();
-        let dims = viewport.dimensions();
-        let dims = (dims.0 as u32, dims.1 as u32);
-        let vao = &self.image_model.positions.vao;
-        let program = &self.program_primitive_texture;
-        let (projection, view) = if with_projection {
-            let perspective: [[f32; 4]; 4] =
-                perspective(dims.0, dims.1).to_homogeneous().into();
-            let view: [[f32; 4]; 4] =
-                get_view(self.observer()).to_homogeneous().into();
-            (perspective, view)
-        } else {
-            let orthographic: [[f32; 4]; 4] =
-                orthographic(dims.0, dims.1).to_homogeneous().into();
-            let view: [[f32; 4]; 4] = Matrix4::identity().into();
-            (orthographic, view)
-        };
-        // let scales = image_data.dim_scales;
-        program.set_uniform("model", model);
-        program.set_uniform("view", view);
-        program.set_uniform("projection", projection);
-        program.set_uniform("tex", self.atlas.clone());
-        program.set_uniform("dim_scales", dim_scales);
-        // program.set_uniform("size", size);
-        // program.set_uniform("dim_scales", atlas_image.dim_scales);
-        program.set_uniform("offset", atlas_image.offset);
-        program.set_uniform("fraction_wh", atlas_image.fraction_wh);
-        program.set_layout(&gl, vao, &[&self.image_model.positions]);
-
-        let draw_params = DrawParams::default();
-        frame.draw(
-            vao,
-            Some(&self.image_model.indices),
-            &program,
-            &draw_params,
-        );
+        let mut region: Region = atlas_image.into();
+        region.dim_scales = dim_scales;
+        self.record(DrawCommand::Image {
+            program: ProgramKind::PrimitiveTexture,
+            model: model.into(),
+            region,
+            scale: 1.0,
+            with_projection,
+            stencil: StencilMode::Off,
+            blend: false,
+        });
     }
 }
 
@@ -1331,6 +862,41 @@ pub fn to_vertex(
 pub struct SpriteBatch {
     pub instancing_data: ImageInstancingData,
     pub len: usize,
+    capacity: usize,
+}
+
+fn atlas_region(image: &AtlasImage) -> AtlasRegion {
+    AtlasRegion {
+        offset: red::data::f32_f32 {
+            d0: image.offset.0,
+            d1: image.offset.1,
+        },
+        fraction_wh: red::data::f32_f32::new(
+            image.fraction_wh.0,
+            image.fraction_wh.1,
+        ),
+        dim_scales: red::data::f32_f32::new(
+            image.dim_scales.0,
+            image.dim_scales.1,
+        ),
+        transparency: red::data::f32_::new(image.transparency),
+        color: red::data::f32_f32_f32_f32(
+            image.color.0,
+            image.color.1,
+            image.color.2,
+            image.color.3,
+        ),
+    }
+}
+
+fn world_isometry(iso: &Isometry3, size: f32) -> WorldIsometry {
+    let pos = iso.translation.vector;
+    let angle = iso.rotation.euler_angles().2;
+    WorldIsometry {
+        world_position: red::data::f32_f32_f32::new(pos.x, pos.y, pos.z),
+        angle: red::data::f32_::new(angle),
+        scale: red::data::f32_::new(size),
+    }
 }
 
 impl SpriteBatch {
@@ -1341,44 +907,12 @@ impl SpriteBatch {
         sizes: &[f32],
     ) -> Self {
         let image_model = ImageModel::new(gl).expect("failed image model");
-        let regions: Vec<AtlasRegion> = images
-            .iter()
-            .map(|image| AtlasRegion {
-                offset: red::data::f32_f32 {
-                    d0: image.offset.0,
-                    d1: image.offset.1,
-                },
-                fraction_wh: red::data::f32_f32::new(
-                    image.fraction_wh.0,
-                    image.fraction_wh.1,
-                ),
-                dim_scales: red::data::f32_f32::new(
-                    image.dim_scales.0,
-                    image.dim_scales.1,
-                ),
-                transparency: red::data::f32_::new(image.transparency),
-                color: red::data::f32_f32_f32_f32(
-                    image.color.0,
-                    image.color.1,
-                    image.color.2,
-                    image.color.3,
-                ),
-            })
-            .collect();
+        let regions: Vec<AtlasRegion> =
+            images.iter().map(atlas_region).collect();
         let isometries: Vec<WorldIsometry> = isometries
             .iter()
             .zip(sizes.iter())
-            .map(|(iso, size)| {
-                let pos = iso.translation.vector;
-                let angle = iso.rotation.euler_angles().2;
-                WorldIsometry {
-                    world_position: red::data::f32_f32_f32::new(
-                        pos.x, pos.y, pos.z,
-                    ),
-                    angle: red::data::f32_::new(angle),
-                    scale: red::data::f32_::new(*size),
-                }
-            })
+            .map(|(iso, size)| world_isometry(iso, *size))
             .collect();
         let regions = AtlasRegionBuffer::new(gl, &regions)
             .expect("failed to create regions buffer");
@@ -1392,6 +926,53 @@ impl SpriteBatch {
         SpriteBatch {
             instancing_data,
             len: images.len(),
+            capacity: images.len(),
+        }
+    }
+
+    /// instances the buffers are allocated for
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Empty batch with buffers for `capacity` instances, filled by `update`
+    pub fn with_capacity(gl: &red::GL, capacity: usize) -> Self {
+        let image = AtlasImage {
+            offset: (0.0, 0.0),
+            fraction_wh: (0.0, 0.0),
+            dim_scales: (0.0, 0.0),
+            transparency: 0.0,
+            color: (0.0, 0.0, 0.0, 0.0),
+        };
+        let images = vec![image; capacity];
+        let isometries = vec![Isometry3::identity(); capacity];
+        let sizes = vec![0f32; capacity];
+        let mut sprite_batch = Self::new(gl, &images, &isometries, &sizes);
+        sprite_batch.len = 0;
+        sprite_batch
+    }
+
+    /// Overwrite instances in place. Buffers are not reallocated,
+    /// instances over the initial size are dropped
+    pub fn update(
+        &mut self,
+        images: &[AtlasImage],
+        isometries: &[Isometry3],
+        sizes: &[f32],
+    ) {
+        let regions = self.instancing_data.regions.map_array().unwrap();
+        for (region, image) in regions.slice.iter_mut().zip(images.iter()) {
+            *region = atlas_region(image);
+        }
+        let capacity = regions.slice.len();
+        let instances = self.instancing_data.isometries.map_array().unwrap();
+        for (instance, (iso, size)) in instances
+            .slice
+            .iter_mut()
+            .zip(isometries.iter().zip(sizes.iter()))
+        {
+            *instance = world_isometry(iso, *size);
         }
+        self.len = images.len().min(capacity);
     }
 }
@@ -0,0 +1,318 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Particles over that number are not drawn
+pub const MAX_PARTICLES: usize = 2048;
+
+/// Content of rons/particles.ron
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticlesConfig {
+    pub emitters: Vec<EmitterDesc>,
+}
+
+/// Emitter tweaks, times are in seconds and ranges are (min, max)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmitterDesc {
+    pub name: String,
+    /// atlas image of a particle
+    pub image: String,
+    /// particles spawned at once on creation
+    #[serde(default)]
+    pub burst: usize,
+    /// particles per second while the emitter is alive
+    #[serde(default)]
+    pub spawn_rate: f32,
+    /// how long spawn_rate works
+    #[serde(default)]
+    pub duration: f32,
+    pub lifetime: (f32, f32),
+    pub speed: (f32, f32),
+    /// spread of velocity around `direction` in radians, 2 pi for a circle
+    pub cone: f32,
+    #[serde(default)]
+    pub direction: f32,
+    /// (min, max) z of spawned particles
+    #[serde(default)]
+    pub depth: (f32, f32),
+    /// half size of the box around the emitter particles spawn in
+    #[serde(default)]
+    pub area: (f32, f32),
+    /// particles never die and leave the area or the depth range
+    /// to come back from the other side, for ambient fields
+    #[serde(default)]
+    pub wrap: bool,
+    /// r, g, b, alpha at birth
+    pub color_start: (f32, f32, f32, f32),
+    /// r, g, b, alpha at death
+    pub color_end: (f32, f32, f32, f32),
+    pub size_start: f32,
+    pub size_end: f32,
+    #[serde(default)]
+    pub gravity: (f32, f32),
+    /// part of velocity lost per second
+    #[serde(default)]
+    pub drag: f32,
+}
+
+fn sample(rng: &mut ThreadRng, range: (f32, f32)) -> f32 {
+    if range.0 < range.1 {
+        rng.gen_range(range.0, range.1)
+    } else {
+        range.0
+    }
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + t * (to - from)
+}
+
+/// x moved into [min, max) by a multiple of the range
+fn wrap(x: f32, min: f32, max: f32) -> f32 {
+    if min < max {
+        min + (x - min).rem_euclid(max - min)
+    } else {
+        x
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Particle {
+    position: Vector3,
+    velocity: Vector2,
+    /// game time since spawn
+    age: f32,
+    lifetime: f32,
+}
+
+/// Simulated on cpu, drawn through `ParticlePool`
+#[derive(Debug, Component, Clone)]
+pub struct Emitter {
+    desc: Arc<EmitterDesc>,
+    image: AtlasImage,
+    position: Point2,
+    particles: Vec<Particle>,
+    /// game time since creation
+    age: f32,
+    /// fraction of a particle carried over to the next frame
+    spawn_debt: f32,
+}
+
+impl Emitter {
+    pub fn new(
+        desc: Arc<EmitterDesc>,
+        image: AtlasImage,
+        position: Point2,
+    ) -> Self {
+        let burst = desc.burst;
+        let mut emitter = Emitter {
+            desc,
+            image,
+            position,
+            particles: Vec::with_capacity(burst),
+            age: 0.0,
+            spawn_debt: 0.0,
+        };
+        emitter.spawn(burst);
+        emitter
+    }
+
+    pub fn set_position(&mut self, position: Point2) {
+        self.position = position;
+    }
+
+    pub fn desc(&self) -> &EmitterDesc {
+        &self.desc
+    }
+
+    fn spawn(&mut self, num: usize) {
+        let mut rng = thread_rng();
+        let desc = &self.desc;
+        for _ in 0..num {
+            let half_cone = desc.cone / 2.0;
+            let angle =
+                desc.direction + sample(&mut rng, (-half_cone, half_cone));
+            let speed = sample(&mut rng, desc.speed);
+            let x = sample(&mut rng, (-desc.area.0, desc.area.0));
+            let y = sample(&mut rng, (-desc.area.1, desc.area.1));
+            let z = sample(&mut rng, desc.depth);
+            self.particles.push(Particle {
+                position: Vector3::new(
+                    self.position.x + x,
+                    self.position.y + y,
+                    z,
+                ),
+                velocity: speed * Vector2::new(angle.cos(), angle.sin()),
+                age: 0.0,
+                lifetime: sample(&mut rng, desc.lifetime),
+            });
+        }
+    }
+
+    /// delta is the game time of the frame in seconds.
+    /// Returns false when the emitter is done and has nothing to draw
+    pub fn update(&mut self, delta: f32) -> bool {
+        if self.age < self.desc.duration {
+            self.spawn_debt += self.desc.spawn_rate * delta;
+            let num = self.spawn_debt as usize;
+            self.spawn_debt -= num as f32;
+            self.spawn(num);
+        }
+        self.age += delta;
+        let gravity = Vector2::new(self.desc.gravity.0, self.desc.gravity.1);
+        let drag = (1.0 - self.desc.drag * delta).max(0.0);
+        for particle in self.particles.iter_mut() {
+            particle.velocity = drag * (particle.velocity + delta * gravity);
+            particle.position.x += delta * particle.velocity.x;
+            particle.position.y += delta * particle.velocity.y;
+            particle.age += delta;
+        }
+        if self.desc.wrap {
+            self.wrap_particles();
+            return true;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+        self.age < self.desc.duration || !self.particles.is_empty()
+    }
+
+    /// Move all particles, e.g. against the motion of the observer
+    pub fn shift(&mut self, offset: Vector3) {
+        for particle in self.particles.iter_mut() {
+            particle.position += offset;
+        }
+        if self.desc.wrap {
+            self.wrap_particles();
+        }
+    }
+
+    fn wrap_particles(&mut self) {
+        let (w, h) = self.desc.area;
+        let (z_min, z_max) = self.desc.depth;
+        let center = self.position;
+        for particle in self.particles.iter_mut() {
+            let position = &mut particle.position;
+            position.x = wrap(position.x, center.x - w, center.x + w);
+            position.y = wrap(position.y, center.y - h, center.y + h);
+            position.z = wrap(position.z, z_min, z_max);
+        }
+    }
+
+    pub fn positions<'a>(&'a self) -> impl Iterator<Item = Point3> + 'a {
+        self.particles
+            .iter()
+            .map(|particle| Point3::from(particle.position))
+    }
+
+    /// Alive particles with color and size interpolated over their life
+    pub fn instances<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (AtlasImage, Isometry3, f32)> + 'a {
+        let desc = &self.desc;
+        let image = self.image;
+        self.particles.iter().map(move |particle| {
+            // wrapping particles do not age
+            let t = if desc.wrap {
+                0.0
+            } else if particle.lifetime > 0.0 {
+                (particle.age / particle.lifetime).min(1.0)
+            } else {
+                1.0
+            };
+            let (from, to) = (desc.color_start, desc.color_end);
+            let mut image = image;
+            image.color = (
+                lerp(from.0, to.0, t),
+                lerp(from.1, to.1, t),
+                lerp(from.2, to.2, t),
+                1.0,
+            );
+            image.transparency = lerp(from.3, to.3, t);
+            (
+                image,
+                Isometry3::new(particle.position, Vector3::zeros()),
+                lerp(desc.size_start, desc.size_end, t),
+            )
+        })
+    }
+}
+
+/// Emitter descriptions by name with resolved atlas images
+#[derive(Default)]
+pub struct ParticleEffects {
+    emitters: HashMap<String, (Arc<EmitterDesc>, AtlasImage)>,
+}
+
+impl ParticleEffects {
+    pub fn new(
+        descs: &[EmitterDesc],
+        name_to_atlas: &HashMap<String, AtlasImage>,
+    ) -> Result<Self, String> {
+        let mut emitters = HashMap::new();
+        for desc in descs.iter() {
+            let image = name_to_atlas.get(&desc.image).ok_or_else(|| {
+                format!(
+                    "emitter {} has unknown image {}",
+                    desc.name, desc.image
+                )
+            })?;
+            emitters
+                .insert(desc.name.clone(), (Arc::new(desc.clone()), *image));
+        }
+        Ok(ParticleEffects { emitters })
+    }
+
+    pub fn emitter(&self, name: &str, position: Point2) -> Option<Emitter> {
+        self.emitters
+            .get(name)
+            .map(|(desc, image)| Emitter::new(desc.clone(), *image, position))
+    }
+}
+
+/// Particles of all emitters, drawn as one sprite batch.
+/// Vectors are refilled every frame instead of allocated per effect
+pub struct ParticlePool {
+    capacity: usize,
+    images: Vec<AtlasImage>,
+    isometries: Vec<Isometry3>,
+    sizes: Vec<f32>,
+}
+
+impl ParticlePool {
+    pub fn new(capacity: usize) -> Self {
+        ParticlePool {
+            capacity,
+            images: Vec::with_capacity(capacity),
+            isometries: Vec::with_capacity(capacity),
+            sizes: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.isometries.clear();
+        self.sizes.clear();
+    }
+
+    /// Queue particles of the emitter, the ones over capacity are dropped
+    pub fn push(&mut self, emitter: &Emitter) {
+        let free = self.capacity - self.images.len();
+        for (image, isometry, size) in emitter.instances().take(free) {
+            self.images.push(image);
+            self.isometries.push(isometry);
+            self.sizes.push(size);
+        }
+    }
+
+    /// Draw queued particles in one batch
+    pub fn render(&self, canvas: &mut Canvas) {
+        canvas.render_sprite_batch(
+            &self.images,
+            &self.isometries,
+            &self.sizes,
+            false,
+            Some(red::Blend),
+        );
+    }
+}
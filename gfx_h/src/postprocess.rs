@@ -0,0 +1,346 @@
+use super::{read_file, ImageModel};
+use red::glow;
+use red::glow::Context;
+use red::DrawParams;
+use serde::{Deserialize, Serialize};
+
+// bloom is blurred in a smaller target, it's cheaper and blurrier
+const BLOOM_DOWNSCALE: u32 = 2;
+
+type Framebuffer = <glow::native::Context as Context>::Framebuffer;
+type Renderbuffer = <glow::native::Context as Context>::Renderbuffer;
+
+/// Which post-process passes are enabled. Loaded from rons/graphics.ron
+/// and toggled from the menu
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PostProcessSettings {
+    pub bloom: bool,
+    pub chromatic_aberration: bool,
+    pub vignette: bool,
+    pub warp_distortion: bool,
+    /// no offscreen target, only a cheap overlay for hits and low health
+    #[serde(default)]
+    pub fallback: bool,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        PostProcessSettings {
+            bloom: true,
+            chromatic_aberration: true,
+            vignette: true,
+            warp_distortion: true,
+            fallback: cfg!(target_os = "android"),
+        }
+    }
+}
+
+impl PostProcessSettings {
+    pub fn any(&self) -> bool {
+        self.bloom
+            || self.chromatic_aberration
+            || self.vignette
+            || self.warp_distortion
+    }
+}
+
+/// Strength of the effects on the current frame, all in [0, 1]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PostProcessParams {
+    pub aberration: f32,
+    pub vignette: f32,
+    pub warp: f32,
+}
+
+struct RenderTarget {
+    framebuffer: Framebuffer,
+    stencil: Option<Renderbuffer>,
+    texture: red::shader::Texture,
+    dims: (u32, u32),
+}
+
+impl RenderTarget {
+    fn new(
+        gl: &red::GL,
+        dims: (u32, u32),
+        with_stencil: bool,
+    ) -> Result<Self, String> {
+        let pixels = vec![0u8; (dims.0 * dims.1 * 4) as usize];
+        let texture =
+            red::shader::Texture::from_rgba8(gl, dims.0, dims.1, &pixels);
+        unsafe {
+            let framebuffer = gl.create_framebuffer()?;
+            let mut target = RenderTarget {
+                framebuffer,
+                stencil: None,
+                texture,
+                dims,
+            };
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(target.texture.texture.clone()),
+                0,
+            );
+            if with_stencil {
+                let stencil = match gl.create_renderbuffer() {
+                    Ok(stencil) => stencil,
+                    Err(err) => {
+                        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+                        target.delete(gl);
+                        return Err(err);
+                    }
+                };
+                gl.bind_renderbuffer(glow::RENDERBUFFER, Some(stencil));
+                gl.renderbuffer_storage(
+                    glow::RENDERBUFFER,
+                    glow::DEPTH24_STENCIL8,
+                    dims.0 as i32,
+                    dims.1 as i32,
+                );
+                gl.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    glow::DEPTH_STENCIL_ATTACHMENT,
+                    glow::RENDERBUFFER,
+                    Some(stencil),
+                );
+                gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+                target.stencil = Some(stencil);
+            }
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                target.delete(gl);
+                return Err(format!("incomplete framebuffer: {}", status));
+            }
+            Ok(target)
+        }
+    }
+
+    fn bind(&self, gl: &red::GL) {
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+        }
+    }
+
+    fn delete(&self, gl: &red::GL) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            if let Some(stencil) = self.stencil {
+                gl.delete_renderbuffer(stencil);
+            }
+        }
+    }
+}
+
+fn create_fullscreen_program(
+    gl: &red::GL,
+    pref: &str,
+    name: &str,
+    glsl_version: &str,
+) -> Result<red::Program, String> {
+    let vertex = format!("{}gles/v_fullscreen.glsl", pref);
+    let fragment = format!("{}gles/f_{}.glsl", pref, name);
+    let read = |path: &str| {
+        read_file(path)
+            .map(|source| format!("{}\n{}", glsl_version, source))
+            .map_err(|err| format!("failed to read {}: {}", path, err))
+    };
+    let vertex_shader = red::Shader::from_vert_source(&gl, &read(&vertex)?)
+        .map_err(|err| format!("failed to compile {}: {}", vertex, err))?;
+    let fragment_shader = red::Shader::from_frag_source(&gl, &read(&fragment)?)
+        .map_err(|err| format!("failed to compile {}: {}", fragment, err))?;
+    red::Program::from_shaders(&gl, &[vertex_shader, fragment_shader])
+}
+
+/// Offscreen render target of the world and a chain of passes:
+/// bright pass + blur into the bloom target, composite with aberration,
+/// vignette and warp distortion, bloom added on top.
+/// Fallback path only draws the vignette overlay over the frame
+pub struct PostProcess {
+    scene: Option<RenderTarget>,
+    bloom: Option<RenderTarget>,
+    program_bloom: red::Program,
+    program_composite: red::Program,
+    program_overlay: red::Program,
+    program_vignette: red::Program,
+    quad: ImageModel,
+    active: bool,
+    // offscreen targets failed to create, draw straight to the screen
+    unsupported: bool,
+}
+
+impl PostProcess {
+    pub fn new(
+        gl: &red::GL,
+        pref: &str,
+        glsl_version: &str,
+    ) -> Result<Self, String> {
+        Ok(PostProcess {
+            scene: None,
+            bloom: None,
+            program_bloom: create_fullscreen_program(
+                gl,
+                pref,
+                "bloom",
+                glsl_version,
+            )?,
+            program_composite: create_fullscreen_program(
+                gl,
+                pref,
+                "composite",
+                glsl_version,
+            )?,
+            program_overlay: create_fullscreen_program(
+                gl,
+                pref,
+                "bloom_overlay",
+                glsl_version,
+            )?,
+            program_vignette: create_fullscreen_program(
+                gl,
+                pref,
+                "vignette",
+                glsl_version,
+            )?,
+            quad: ImageModel::new(gl)?,
+            active: false,
+            unsupported: false,
+        })
+    }
+
+    fn resize(&mut self, gl: &red::GL, dims: (u32, u32)) -> Result<(), String> {
+        if let Some(ref scene) = self.scene {
+            if scene.dims == dims {
+                return Ok(());
+            }
+        }
+        if let Some(scene) = self.scene.take() {
+            scene.delete(gl);
+        }
+        if let Some(bloom) = self.bloom.take() {
+            bloom.delete(gl);
+        }
+        self.scene = Some(RenderTarget::new(gl, dims, true)?);
+        self.bloom = Some(RenderTarget::new(
+            gl,
+            (dims.0 / BLOOM_DOWNSCALE, dims.1 / BLOOM_DOWNSCALE),
+            false,
+        )?);
+        Ok(())
+    }
+
+    /// Redirect drawing into the offscreen target. Call before clearing
+    pub fn begin(
+        &mut self,
+        gl: &red::GL,
+        viewport: &red::Viewport,
+        settings: &PostProcessSettings,
+    ) {
+        self.active = false;
+        if settings.fallback || !settings.any() || self.unsupported {
+            return;
+        }
+        let dims = viewport.dimensions();
+        if let Err(err) = self.resize(gl, (dims.0 as u32, dims.1 as u32)) {
+            log::warn!("post processing is disabled: {}", err);
+            self.unsupported = true;
+            return;
+        }
+        if let Some(ref scene) = self.scene {
+            scene.bind(gl);
+            self.active = true;
+        }
+    }
+
+    fn draw_quad(
+        &self,
+        gl: &red::GL,
+        frame: &mut red::Frame,
+        program: &red::Program,
+        blend: Option<red::Blend>,
+    ) {
+        let vao = &self.quad.positions.vao;
+        program.set_layout(gl, vao, &[&self.quad.positions]);
+        let draw_params = DrawParams {
+            blend,
+            ..Default::default()
+        };
+        frame.draw(vao, Some(&self.quad.indices), program, &draw_params);
+    }
+
+    /// Run the passes and draw the result to the screen
+    pub fn end(
+        &self,
+        gl: &red::GL,
+        frame: &mut red::Frame,
+        viewport: &red::Viewport,
+        settings: &PostProcessSettings,
+        params: &PostProcessParams,
+    ) {
+        let aberration = if settings.chromatic_aberration {
+            params.aberration
+        } else {
+            0.0
+        };
+        let vignette = if settings.vignette {
+            params.vignette
+        } else {
+            0.0
+        };
+        let warp = if settings.warp_distortion {
+            params.warp
+        } else {
+            0.0
+        };
+        let (scene, bloom) = match (&self.scene, &self.bloom) {
+            (Some(scene), Some(bloom)) if self.active => (scene, bloom),
+            _ => {
+                // cheap path: colored edges instead of the real effects
+                let fallback = settings.fallback || self.unsupported;
+                if fallback && (aberration > 0.0 || vignette > 0.0) {
+                    let program = &self.program_vignette;
+                    program.set_uniform("vignette", vignette);
+                    program.set_uniform("aberration", aberration);
+                    self.draw_quad(gl, frame, program, Some(red::Blend));
+                }
+                return;
+            }
+        };
+        if settings.bloom {
+            bloom.bind(gl);
+            let bloom_viewport = red::Viewport::for_window(
+                bloom.dims.0 as i32,
+                bloom.dims.1 as i32,
+            );
+            bloom_viewport.set_used(gl);
+            frame.set_clear_color(0.0, 0.0, 0.0, 0.0);
+            frame.clear_color();
+            let program = &self.program_bloom;
+            program.set_uniform("tex", scene.texture.clone());
+            program.set_uniform(
+                "texel",
+                (1.0 / scene.dims.0 as f32, 1.0 / scene.dims.1 as f32),
+            );
+            self.draw_quad(gl, frame, program, None);
+            viewport.set_used(gl);
+        }
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+        let program = &self.program_composite;
+        program.set_uniform("tex", scene.texture.clone());
+        program.set_uniform("aberration", aberration);
+        program.set_uniform("vignette", vignette);
+        program.set_uniform("warp", warp);
+        self.draw_quad(gl, frame, program, None);
+        if settings.bloom {
+            let program = &self.program_overlay;
+            program.set_uniform("tex", bloom.texture.clone());
+            program.set_uniform("warp", warp);
+            self.draw_quad(gl, frame, program, Some(red::Blend));
+        }
+    }
+}
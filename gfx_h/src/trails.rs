@@ -0,0 +1,89 @@
+use super::*;
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+#[derive(VertexAttribPointers)]
+pub struct TrailVertex {
+    pub position: red::data::f32_f32,
+    pub color: red::data::f32_f32_f32_f32,
+}
+
+/// Ribbons of all trails in one mesh, so they are drawn with a single call
+#[derive(Default)]
+pub struct TrailMesh {
+    vertices: Vec<TrailVertex>,
+    indices: Vec<u16>,
+    ribbons: usize,
+}
+
+impl TrailMesh {
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.ribbons = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn ribbons(&self) -> usize {
+        self.ribbons
+    }
+
+    /// Points go from the head to the tail: position, half width and color.
+    /// Ribbons which don't fit into u16 indices are skipped
+    pub fn push_ribbon(
+        &mut self,
+        points: impl Iterator<Item = (Point2, f32, (f32, f32, f32, f32))>,
+    ) {
+        let points: Vec<_> = points.collect();
+        if points.len() < 2 {
+            return;
+        }
+        let base = self.vertices.len();
+        if base + 2 * points.len() > std::u16::MAX as usize {
+            return;
+        }
+        let mut normal = Vector2::new(0f32, 0f32);
+        for i in 0..points.len() {
+            let prev = points[i.saturating_sub(1)].0;
+            let next = points[(i + 1).min(points.len() - 1)].0;
+            let direction = prev - next;
+            // keep the last normal when points are on top of each other
+            if direction.norm() > 1e-4 {
+                normal = Vector2::new(-direction.y, direction.x).normalize();
+            }
+            let (position, half_width, color) = points[i];
+            let color =
+                red::data::f32_f32_f32_f32(color.0, color.1, color.2, color.3);
+            for side in [-1f32, 1f32].iter() {
+                let vertex = position + side * half_width * normal;
+                self.vertices.push(TrailVertex {
+                    position: red::data::f32_f32::new(vertex.x, vertex.y),
+                    color,
+                });
+            }
+        }
+        for i in 0..points.len() - 1 {
+            let left = (base + 2 * i) as u16;
+            self.indices.extend_from_slice(&[
+                left,
+                left + 1,
+                left + 2,
+                left + 1,
+                left + 3,
+                left + 2,
+            ]);
+        }
+        self.ribbons += 1;
+    }
+
+    pub fn vertices(&self) -> &[TrailVertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u16] {
+        &self.indices
+    }
+}
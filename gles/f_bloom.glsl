@@ -0,0 +1,29 @@
+in mediump vec2 v_tex_coords;
+out mediump vec4 fragColor;
+
+uniform sampler2D tex;
+uniform mediump vec2 texel;
+
+// only bright things like bullets, lazers and glow sprites bloom
+const mediump float THRESHOLD = 0.6;
+const int RADIUS = 3;
+
+mediump vec3 bright(mediump vec2 uv) {
+    mediump vec3 color = texture(tex, uv).rgb;
+    mediump float luma = max(color.r, max(color.g, color.b));
+    return color * max(luma - THRESHOLD, 0.0) / max(luma, 0.001);
+}
+
+void main() {
+    mediump vec3 sum = vec3(0.0);
+    mediump float weight_sum = 0.0;
+    for (int x = -RADIUS; x <= RADIUS; x++) {
+        for (int y = -RADIUS; y <= RADIUS; y++) {
+            mediump float weight = 1.0 / (1.0 + float(x * x + y * y));
+            mediump vec2 shift = 2.0 * texel * vec2(float(x), float(y));
+            sum += weight * bright(v_tex_coords + shift);
+            weight_sum += weight;
+        }
+    }
+    fragColor = vec4(sum / weight_sum, 1.0);
+}
@@ -0,0 +1,14 @@
+in mediump vec2 v_tex_coords;
+out mediump vec4 fragColor;
+
+uniform sampler2D tex;
+uniform mediump float warp;
+
+const mediump vec2 CENTER = vec2(0.5, 0.5);
+
+void main() {
+    mediump vec2 diff = v_tex_coords - CENTER;
+    mediump vec2 uv = CENTER + diff * (1.0 - 0.6 * warp * dot(diff, diff));
+    mediump vec3 color = texture(tex, uv).rgb;
+    fragColor = vec4(color, max(color.r, max(color.g, color.b)));
+}
@@ -0,0 +1,42 @@
+in mediump vec2 v_tex_coords;
+out mediump vec4 fragColor;
+
+uniform sampler2D tex;
+uniform mediump float aberration;
+uniform mediump float vignette;
+uniform mediump float warp;
+
+const mediump vec2 CENTER = vec2(0.5, 0.5);
+const mediump vec3 VIGNETTE_COLOR = vec3(0.6, 0.0, 0.0);
+const int WARP_SAMPLES = 4;
+
+// pinch towards the center during warp jump
+mediump vec2 warp_uv(mediump vec2 uv) {
+    mediump vec2 diff = uv - CENTER;
+    return CENTER + diff * (1.0 - 0.6 * warp * dot(diff, diff));
+}
+
+mediump vec3 sample_scene(mediump vec2 uv) {
+    mediump vec2 shift = 0.02 * aberration * (uv - CENTER);
+    return vec3(
+        texture(tex, uv + shift).r,
+        texture(tex, uv).g,
+        texture(tex, uv - shift).b
+    );
+}
+
+void main() {
+    mediump vec2 uv = warp_uv(v_tex_coords);
+    mediump vec3 color = sample_scene(uv);
+    if (warp > 0.0) {
+        // radial streaks
+        for (int i = 1; i <= WARP_SAMPLES; i++) {
+            mediump float scale = 1.0 - 0.02 * warp * float(i);
+            color += sample_scene(CENTER + (uv - CENTER) * scale);
+        }
+        color /= float(WARP_SAMPLES + 1);
+    }
+    mediump float edge = smoothstep(0.3, 0.8, length(v_tex_coords - CENTER));
+    color = mix(color, VIGNETTE_COLOR, vignette * edge);
+    fragColor = vec4(color, 1.0);
+}
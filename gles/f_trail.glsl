@@ -0,0 +1,6 @@
+in mediump vec4 v_color;
+out mediump vec4 fragColor;
+
+void main() {
+    fragColor = v_color;
+}
@@ -0,0 +1,13 @@
+in mediump vec2 v_tex_coords;
+out mediump vec4 fragColor;
+
+uniform mediump float aberration;
+uniform mediump float vignette;
+
+const mediump vec3 VIGNETTE_COLOR = vec3(0.6, 0.0, 0.0);
+
+void main() {
+    mediump float edge = smoothstep(0.3, 0.8, length(v_tex_coords - 0.5));
+    mediump float strength = max(vignette, 0.5 * aberration);
+    fragColor = vec4(VIGNETTE_COLOR, strength * edge);
+}
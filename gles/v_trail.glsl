@@ -0,0 +1,18 @@
+const mat4 INVERT_Y_AXIS = mat4(
+    vec4(1.0, 0.0, 0.0, 0.0),
+    vec4(0.0, -1.0, 0.0, 0.0),
+    vec4(0.0, 0.0, 1.0, 0.0),
+    vec4(0.0, 0.0, 0.0, 1.0)
+);
+
+in vec2 position;
+in vec4 color;
+out mediump vec4 v_color;
+
+uniform mediump mat4 perspective;
+uniform mediump mat4 view;
+
+void main() {
+    v_color = color;
+    gl_Position = INVERT_Y_AXIS * perspective * view * vec4(position, 0.0, 1.0);
+}
@@ -12,11 +12,111 @@ pub struct SpritePosition {
     pub offsets: Option<[f32; 2]>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LoopMode {
+    Once,
+    Loop,
+    /// forth and back again
+    PingPong,
+}
+
+impl Default for LoopMode {
+    fn default() -> Self {
+        LoopMode::Once
+    }
+}
+
+fn default_ticks() -> usize {
+    1
+}
+
+/// How frames of the animation directory are picked
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Frames {
+    /// every image of the directory, ordered by name
+    All,
+    /// image names without extension
+    Names(Vec<String>),
+    /// run of `#` in the pattern is replaced by zero padded numbers
+    /// from `first` to `last`, e.g. "blast####" gives "blast0001"
+    Pattern {
+        pattern: String,
+        first: usize,
+        last: usize,
+    },
+}
+
+impl Default for Frames {
+    fn default() -> Self {
+        Frames::All
+    }
+}
+
+impl Frames {
+    /// image names of the frames in order, `images` are all images
+    /// of the directory
+    pub fn names(&self, images: &[String]) -> Vec<String> {
+        match self {
+            Frames::All => {
+                let mut names = images.to_vec();
+                // so 10 goes after 9
+                names.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+                names
+            }
+            Frames::Names(names) => names.clone(),
+            Frames::Pattern {
+                pattern,
+                first,
+                last,
+            } => {
+                let width = pattern.matches('#').count();
+                let template = "#".repeat(width);
+                (*first..=*last)
+                    .map(|i| {
+                        pattern.replace(
+                            &template,
+                            &format!("{:0w$}", i, w = width),
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// animation.ron next to the frames in the animation directory
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnimationSave {
+    #[serde(default)]
+    pub frames: Frames,
+    /// game ticks every frame is shown
+    #[serde(default = "default_ticks")]
+    pub ticks: usize,
+    #[serde(default)]
+    pub mode: LoopMode,
+    /// (frame, event) fired when the animation reaches the frame
+    #[serde(default)]
+    pub events: Vec<(usize, String)>,
+}
+
+/// Animation in the atlas, `frames` are sprite names
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnimationDesc {
+    pub frames: Vec<String>,
+    pub ticks: usize,
+    #[serde(default)]
+    pub mode: LoopMode,
+    #[serde(default)]
+    pub events: Vec<(usize, String)>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SerializedSpriteSheet {
     pub texture_width: f32,
     pub texture_height: f32,
     pub sprites: HashMap<String, SpritePosition>,
+    #[serde(default)]
+    pub animations: HashMap<String, AnimationDesc>,
 }
 
 impl Format for TwentyFormat {
@@ -44,6 +144,7 @@ impl Format for TwentyFormat {
             texture_width: dimensions.0 as f32,
             texture_height: dimensions.1 as f32,
             sprites,
+            animations: HashMap::new(),
         }
     }
 }
@@ -4,4 +4,7 @@ extern crate image;
 extern crate sheep;
 
 mod format;
-pub use format::{SerializedSpriteSheet, SpritePosition, TwentyFormat};
+pub use format::{
+    AnimationDesc, AnimationSave, Frames, LoopMode, SerializedSpriteSheet,
+    SpritePosition, TwentyFormat,
+};
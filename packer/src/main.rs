@@ -5,13 +5,14 @@ extern crate sheep;
 
 mod format;
 
-use format::TwentyFormat;
+use format::{AnimationDesc, AnimationSave, TwentyFormat};
 use image::DynamicImage;
 use sheep::Format;
 use sheep::{
     AmethystFormat, InputSprite, MaxrectsOptions, MaxrectsPacker, SimplePacker,
     SpriteAnchor,
 };
+use std::collections::HashMap;
 use std::fs::{self, DirEntry};
 use std::io::Result;
 use std::path::Path;
@@ -71,11 +72,45 @@ where
     }
 }
 
+/// read animation.ron of the directory, frames are resolved into sprite names
+fn animation_from_dir(
+    dir: &Path,
+    prefix: &str,
+    names: &[String],
+) -> Option<AnimationDesc> {
+    let mut file = File::open(dir.join("animation.ron")).ok()?;
+    let mut save_str = String::new();
+    file.read_to_string(&mut save_str)
+        .expect("Failed to read animation.ron");
+    let save: AnimationSave = ron::de::from_str(&save_str)
+        .unwrap_or_else(|e| panic!("Bad animation.ron in {:?}: {}", dir, e));
+    let images: Vec<String> = names
+        .iter()
+        .map(|name| name.trim_start_matches(prefix).to_string())
+        .collect();
+    let frames: Vec<String> = save
+        .frames
+        .names(&images)
+        .into_iter()
+        .map(|frame| {
+            assert!(images.contains(&frame), "No frame {} in {:?}", frame, dir);
+            format!("{}{}", prefix, frame)
+        })
+        .collect();
+    Some(AnimationDesc {
+        frames,
+        ticks: save.ticks,
+        mode: save.mode,
+        events: save.events,
+    })
+}
+
 fn mimic_sheep(
     atlas_path: &str,
     meta_path: &str,
     sprites_paths: &[String],
     names: &[String],
+    animations: HashMap<String, AnimationDesc>,
 ) {
     let config = TexturePackerConfig {
         max_width: 3000,
@@ -110,7 +145,8 @@ fn mimic_sheep(
         }
         TwentyFormat::encode(dimensions, &sprites, sprite_names)
     };
-    let meta = twenty_format;
+    let mut meta = twenty_format;
+    meta.animations = animations;
     let mut meta_file =
         File::create(meta_path).expect("Failed to create meta file");
     let meta_str =
@@ -126,6 +162,7 @@ fn main() {
     let mut animations = vec![];
     let mut animations_names = vec![];
     let mut animations_paths = vec![];
+    let mut animation_descs = HashMap::new();
     for entry in fs::read_dir(assets).unwrap() {
         let e = entry.unwrap();
         if e.path().is_dir() {
@@ -134,8 +171,14 @@ fn main() {
             if dir_name == "trash" {
                 continue;
             };
+            let prefix = format!("{}_anim_", dir_name);
             let (anim_name, anim_paths, anim) =
-                images_from_dir(&e.path(), &(dir_name + "_anim_")).unwrap();
+                images_from_dir(&e.path(), &prefix).unwrap();
+            if let Some(desc) =
+                animation_from_dir(&e.path(), &prefix, &anim_name)
+            {
+                animation_descs.insert(format!("{}_anim", dir_name), desc);
+            }
             animations.push(anim);
             animations_names.push(anim_name);
             animations_paths.push(anim_paths);
@@ -155,7 +198,13 @@ fn main() {
     write_sprites(&mut sprites, animations.iter().flatten().cloned());
     names.extend(animations_names.iter().flatten().cloned());
     paths.extend(animations_paths.iter().flatten().cloned());
-    mimic_sheep("../assets/atlas.png", "../assets/out.ron", &paths, &names);
+    mimic_sheep(
+        "../assets/atlas.png",
+        "../assets/out.ron",
+        &paths,
+        &names,
+        animation_descs,
+    );
 
     //// real ship
     // {
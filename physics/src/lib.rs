@@ -45,14 +45,26 @@ pub fn calculate_player_ship_spin_for_aim(
     (angle_diff * 10.0 - speed * 55.0)
 }
 
+/// ncollide has 30 collision groups: one goes to asteroids
+/// and every faction takes two (ships and bullets)
+pub const MAX_FACTIONS: usize = 14;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
-#[repr(usize)]
 pub enum CollisionId {
     Asteroid,
-    PlayerShip,
-    EnemyShip,
-    PlayerBullet,
-    EnemyBullet,
+    Ship(usize),
+    Bullet(usize),
+}
+
+impl CollisionId {
+    /// collision group id for ncollide, argument is faction id
+    pub fn group(self) -> usize {
+        match self {
+            CollisionId::Asteroid => 0,
+            CollisionId::Ship(faction) => 1 + 2 * faction,
+            CollisionId::Bullet(faction) => 2 + 2 * faction,
+        }
+    }
 }
 
 #[derive(Deref, DerefMut, Default)]
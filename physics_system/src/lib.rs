@@ -2,7 +2,7 @@ use components::*;
 use log::info;
 use common::*;
 
-use std::time::{Instant, Duration};
+use std::time::Duration;
 use specs::prelude::*;
 use specs::Join;
 use nphysics2d::world::World;
@@ -30,12 +30,13 @@ impl<'a> System<'a> for PhysicsSystem {
         ReadStorage<'a, EnemyMarker>,
         ReadStorage<'a, Rocket>,
         ReadStorage<'a, Charge>,
-        ReadStorage<'a, Chain>,
+        WriteStorage<'a, Chain>,
         WriteStorage<'a, Spin>,
         Write<'a, World<f32>>,
-        WriteExpect<'a, NebulaGrid>,
-        WriteExpect<'a, PlanetGrid>,
         Read<'a, AppState>,
+        Write<'a, WorldOffset>,
+        Read<'a, TimeScale>,
+        Write<'a, ChainJoints>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -48,14 +49,17 @@ impl<'a> System<'a> for PhysicsSystem {
             enemies,
             rockets,
             chargings,
-            chains,
+            mut chains,
             mut spins,
             mut world,
-            mut nebula_grid,
-            mut planet_grid,
             app_state,
+            mut world_offset,
+            time_scale,
+            mut chain_joints,
         ) = data;
-        let time_scaler = normalize_60frame(TRACKER.lock().unwrap().update());
+        let time_scaler = normalize_60frame(
+            TRACKER.lock().unwrap().update(time_scale.scale)
+        );
         world.set_timestep(PHYSICS_SIMULATION_TIME * time_scaler);
         let (character_position, character_prev_position) = {
             if let Some((character, isometry, _)) = (&entities, &isometries, &character_markers).join().next() {
@@ -85,7 +89,7 @@ impl<'a> System<'a> for PhysicsSystem {
                 let near_vel = 0.13 * direct.normalize();
                 let rigid_body = world
                     .rigid_body_mut(phys.body_handle).unwrap();
-                if Instant::now() - rocket.0 > Duration::from_secs(2) {
+                if TRACKER.lock().unwrap().now() - rocket.0 > Duration::from_secs(2) {
                     rigid_body.set_velocity(nphysics2d::math::Velocity::linear(near_vel.x, near_vel.y))
 
                 } else {
@@ -108,6 +112,48 @@ impl<'a> System<'a> for PhysicsSystem {
             }
         }
 
+        {   // cut snakes: link that lost its leader becomes a head of a new snake
+            // runs before the step, bodies of dead links are already removed
+            chain_joints.0.retain(|joint| {
+                let alive = entities.is_alive(joint.leader)
+                    && entities.is_alive(joint.follower);
+                if !alive {
+                    world.remove_constraint(joint.handle);
+                }
+                alive
+            });
+            let heads: Vec<specs::Entity> = (&entities, &chains)
+                .join()
+                .filter(|(_, chain)| !entities.is_alive(chain.follow))
+                .map(|(entity, _)| entity)
+                .collect();
+            for entity in heads {
+                chains.remove(entity);
+            }
+        }
+        {   // revolute constraint is a free hinge, bending is limited here
+            for joint in chain_joints.0.iter() {
+                let (leader, follower) = match (physics.get(joint.leader), physics.get(joint.follower)) {
+                    (Some(leader), Some(follower)) => (leader.body_handle, follower.body_handle),
+                    _ => continue
+                };
+                let (leader_angle, leader_spin) = {
+                    let body = world.rigid_body(leader).unwrap();
+                    (body.position().rotation.angle(), body.velocity().angular)
+                };
+                let follower_body = world.rigid_body_mut(follower).unwrap();
+                let bend = angle_shortest_dist(leader_angle, follower_body.position().rotation.angle());
+                if bend.abs() > joint.max_angle {
+                    let position = follower_body.position().translation.vector;
+                    follower_body.set_position(Isometry2::new(position, leader_angle + bend.signum() * joint.max_angle));
+                    follower_body.set_angular_velocity(leader_spin);
+                }
+                let torque = joint.stiffness * bend;
+                follower_body.apply_force(0, &Force2::new(Vector2::new(0f32, 0f32), -torque), ForceType::Force, true);
+                world.rigid_body_mut(leader).unwrap()
+                    .apply_force(0, &Force2::new(Vector2::new(0f32, 0f32), torque), ForceType::Force, true);
+            }
+        }
         {   // Reactive enemies O(n^2)
             let mut enemies_entities = vec![];
             for (entity, _phys, _enemy) in (&entities, &physics, &enemies).join() {
@@ -171,8 +217,7 @@ impl<'a> System<'a> for PhysicsSystem {
         for (isometry, ()) in (&mut isometries, !&physics).join() {
             isometry.0.translation.vector -= diff;
         }
-        nebula_grid.grid.shift(-diff.x, -diff.y);
-        planet_grid.grid.shift(-diff.x, -diff.y);
+        world_offset.0 += Vector2::new(char_vec.x, char_vec.y);
         for (isometry, velocity, physics_component) in
             (&mut isometries, &mut velocities, &physics).join()
         {
@@ -1,4 +1,5 @@
 use common::*;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
@@ -12,6 +13,9 @@ use sdl2::{AudioSubsystem, TimerSubsystem};
 use specs::prelude::*;
 use specs_derive::Component;
 
+mod pitch;
+pub use pitch::*;
+
 const SOUND_CHANNELS: i32 = 100;
 pub const EFFECT_MAX_VOLUME: i32 = 15;
 pub const MUSIC_MAX_VOLUME: i32 = 100;
@@ -68,6 +72,7 @@ pub struct PreloadedSounds {
     pub play: specs::Entity,
     pub deny: specs::Entity,
     pub buy: specs::Entity,
+    pub windup: specs::Entity,
 }
 
 pub struct MusicData<'a> {
@@ -103,6 +108,9 @@ pub fn init_sound<'a>(
             | InitFlag::MID,
     )?;
     sdl2::mixer::allocate_channels(SOUND_CHANNELS);
+    if let Err(e) = register_pitch_effect() {
+        warn!("time scale won't change the pitch: {}", e);
+    }
     let mut name_to_sound: HashMap<String, specs::Entity> = HashMap::new();
 
     {
@@ -153,11 +161,21 @@ pub fn init_sound<'a>(
         play: name_to_sound["play"],
         deny: name_to_sound["deny"],
         buy: name_to_sound["buy"],
+        // no dedicated sample yet, lazer hum works as a windup
+        windup: name_to_sound["lazer"],
     };
     let mut name_to_music: HashMap<String, Music> = HashMap::new();
     {
         // load music
-        let names = ["menu", "short_bells"];
+        let names = [
+            "menu",
+            "short_bells",
+            "level1",
+            "level2",
+            "level3",
+            "level4",
+            "level5",
+        ];
         for name in names.iter() {
             let file = format!("assets/music/{}.ogg", name);
             let path = Path::new(&file);
@@ -168,7 +186,15 @@ pub fn init_sound<'a>(
     }
     let music_data = MusicData {
         menu_music: name_to_music.remove("menu").unwrap(),
-        battle_music: vec![name_to_music.remove("short_bells").unwrap()],
+        // biomes refer to battle tracks by index
+        battle_music: vec![
+            name_to_music.remove("short_bells").unwrap(),
+            name_to_music.remove("level1").unwrap(),
+            name_to_music.remove("level2").unwrap(),
+            name_to_music.remove("level3").unwrap(),
+            name_to_music.remove("level4").unwrap(),
+            name_to_music.remove("level5").unwrap(),
+        ],
     };
     sdl2::mixer::Channel::all().set_volume(EFFECT_MAX_VOLUME);
     sdl2::mixer::Music::set_volume(MUSIC_MAX_VOLUME);
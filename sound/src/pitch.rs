@@ -0,0 +1,144 @@
+use sdl2::libc::{c_int, c_void};
+use sdl2::mixer::{query_spec, AUDIO_S16LSB};
+use sdl2::sys::mixer::{Mix_RegisterEffect, MIX_CHANNEL_POST};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// length of the read heads cross-fade
+const PITCH_WINDOW_SECS: f32 = 0.04;
+/// pitch closer to 1 than that is played as is
+const PITCH_EPS: f32 = 1E-3;
+/// how fast the shifted signal replaces the original one
+/// when the pitch goes away from 1
+const PITCH_WET_SLOPE: f32 = 10.0;
+
+// f32 bits, audio thread reads it on every callback
+static PITCH: AtomicU32 = AtomicU32::new(0x3f80_0000); // 1.0
+
+/// Pitch of the whole mix, music and effects are slowed down together
+pub fn set_pitch(pitch: f32) {
+    PITCH.store(pitch.to_bits(), Ordering::Relaxed);
+}
+
+fn pitch() -> f32 {
+    f32::from_bits(PITCH.load(Ordering::Relaxed))
+}
+
+/// Delay line pitch shifter. Two read heads slide through the recent
+/// input at the pitch rate and cross-fade, so the tempo is kept
+pub struct PitchShifter {
+    channels: usize,
+    window: f32,
+    /// interleaved history
+    buffer: Vec<f32>,
+    /// frame written last
+    write: usize,
+    /// delay of the first head in frames, the second one is
+    /// half of the window behind
+    delay: f32,
+}
+
+impl PitchShifter {
+    pub fn new(frequency: usize, channels: usize) -> Self {
+        let window = (frequency as f32 * PITCH_WINDOW_SECS).max(2.0);
+        let frames = window as usize + 2;
+        PitchShifter {
+            channels,
+            window,
+            buffer: vec![0f32; frames * channels],
+            write: 0,
+            delay: 0f32,
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// linear interpolation between frames, delay is less than the window
+    fn read(&self, delay: f32, channel: usize) -> f32 {
+        let frames = self.frames();
+        let position = self.write as f32 + frames as f32 - delay;
+        let frame = position.floor();
+        let fraction = position - frame;
+        let frame = frame as usize % frames;
+        let next = (frame + 1) % frames;
+        self.buffer[frame * self.channels + channel] * (1.0 - fraction)
+            + self.buffer[next * self.channels + channel] * fraction
+    }
+
+    /// Interleaved 16 bit samples, shifted in place
+    pub fn process(&mut self, samples: &mut [i16], pitch: f32) {
+        let wet = (PITCH_WET_SLOPE * (1.0 - pitch).abs()).min(1.0);
+        let half = self.window / 2.0;
+        for frame in samples.chunks_mut(self.channels) {
+            self.write = (self.write + 1) % self.frames();
+            for (channel, sample) in frame.iter().enumerate() {
+                self.buffer[self.write * self.channels + channel] =
+                    *sample as f32;
+            }
+            if wet < PITCH_EPS {
+                continue;
+            }
+            let delays = [self.delay, (self.delay + half) % self.window];
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                // sin^2 windows of the heads sum up to 1
+                let shifted: f32 = delays
+                    .iter()
+                    .map(|&delay| {
+                        let gain = (std::f32::consts::PI * delay / self.window)
+                            .sin()
+                            .powi(2);
+                        gain * self.read(delay, channel)
+                    })
+                    .sum();
+                let mixed = (1.0 - wet) * *sample as f32 + wet * shifted;
+                *sample = mixed
+                    .max(std::i16::MIN as f32)
+                    .min(std::i16::MAX as f32) as i16;
+            }
+            // heads move pitch frames per frame through the history
+            self.delay = (self.delay + 1.0 - pitch).rem_euclid(self.window);
+        }
+    }
+}
+
+unsafe extern "C" fn pitch_effect(
+    _channel: c_int,
+    stream: *mut c_void,
+    len: c_int,
+    udata: *mut c_void,
+) {
+    let shifter = &mut *(udata as *mut PitchShifter);
+    let samples =
+        std::slice::from_raw_parts_mut(stream as *mut i16, len as usize / 2);
+    shifter.process(samples, pitch());
+}
+
+unsafe extern "C" fn pitch_effect_done(_channel: c_int, udata: *mut c_void) {
+    drop(Box::from_raw(udata as *mut PitchShifter));
+}
+
+/// SDL_mixer has no pitch control, the final mix is shifted instead
+pub fn register_pitch_effect() -> Result<(), String> {
+    let (frequency, format, channels) = query_spec()?;
+    if format != AUDIO_S16LSB {
+        return Err(format!("pitch effect needs 16 bit samples: {}", format));
+    }
+    let shifter = Box::into_raw(Box::new(PitchShifter::new(
+        frequency as usize,
+        channels as usize,
+    )));
+    let registered = unsafe {
+        Mix_RegisterEffect(
+            MIX_CHANNEL_POST,
+            Some(pitch_effect),
+            Some(pitch_effect_done),
+            shifter as *mut c_void,
+        )
+    };
+    if registered == 0 {
+        unsafe { drop(Box::from_raw(shifter)) };
+        return Err(sdl2::get_error());
+    }
+    Ok(())
+}
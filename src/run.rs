@@ -17,14 +17,19 @@ use std::sync::{Arc, Mutex};
 use crate::gui::{Primitive, UI};
 use crate::setup::*;
 use crate::systems::{
-    AISystem, CollisionSystem, CommonRespawn, ControlSystem, ControllingSystem,
-    DeadScreen, DestroySync, GUISystem, GamePlaySystem, InsertSystem,
-    KinematicSystem, MenuRenderingSystem, RenderingSystem, ScoreTableRendering,
-    SoundSystem, UpgradeGUI, UpgradeControlSystem, Upgrader
+    AISystem, ArenaSystem, BackgroundSystem, CameraSystem, CollisionSystem,
+    CommonRespawn, ControlSystem, ControllingSystem, DeadScreen, DestroySync,
+    GUISystem, GamePlaySystem, GravitySystem, InsertSystem, KinematicSystem,
+    MenuRenderingSystem, PresentSystem, RenderingSystem, ScoreTableRendering,
+    SoundSystem, SpatialHashSystem, TimeDilationSystem, TrailSystem,
+    UpgradeGUI, UpgradeControlSystem, Upgrader, WarpSystem,
 };
 use common::*;
 use components::*;
-use gfx_h::{effects::MenuParticles, Canvas, MovementParticles, ParticlesData};
+use gfx_h::{
+    Canvas, GlBackend, ParticleEffects, ParticleField, ParticlePool,
+    MAX_PARTICLES,
+};
 // #[cfg(not(any(target_os = "ios", target_os = "android", target_os = "emscripten"))]
 use log::info;
 use physics::safe_maintain;
@@ -53,13 +58,12 @@ pub fn run() -> Result<(), String> {
     trace!("opa 1");
     setup_physics(&mut specs_world);
     // We need to own _gl_context to avoid RAII crazyness
-    let (context, sdl_context, render_loop, _gl_context, hdpi, canvas) =
+    let (sdl_context, render_loop, _gl_context, hdpi, canvas) =
         setup_gfx(&mut specs_world)?;
     #[cfg(any(target_os = "android"))]
     trace!("opa 2");
     // Hide the cursor
     sdl_context.mouse().show_cursor(false);
-    setup_text(&context, &mut specs_world);
     let atlas = read_atlas("assets/out.ron");
     #[cfg(any(target_os = "android"))]
     trace!("opa 3");
@@ -77,17 +81,29 @@ pub fn run() -> Result<(), String> {
     let name_to_animation = load_animations(&atlas);
     load_description(&mut specs_world, &name_to_atlas);
     let preloaded_images = preloaded_images(&name_to_atlas, &name_to_animation);
-    let size = 10f32;
-    let movement_particles = ThreadPin::new(ParticlesData::MovementParticles(
-        MovementParticles::new_quad(&context, -size, -size, size, size, 100),
-    ));
+    let particles_config = load_particles();
+    let particle_effects =
+        ParticleEffects::new(&particles_config.emitters, &name_to_atlas)?;
+    let particle_field = |name: &str| {
+        particle_effects
+            .emitter(name, Point2::new(0f32, 0f32))
+            .map(ParticleField::new)
+            .ok_or_else(|| format!("no {} emitter in rons/particles.ron", name))
+    };
     // let engine_particles = ThreadPin::new(ParticlesData::Engine(
     //     Engine::new(&display, )
     // ))
-    let movement_particles_entity =
-        specs_world.create_entity().with(movement_particles).build();
+    let movement_particles_entity = specs_world
+        .create_entity()
+        .with(particle_field("movement")?)
+        .build();
+    let menu_particles_entity = specs_world
+        .create_entity()
+        .with(particle_field("menu")?)
+        .build();
     let preloaded_particles = PreloadedParticles {
         movement: movement_particles_entity,
+        menu: menu_particles_entity,
     };
     let destroy_sync =
         DestroySync::new(asteroids_spawn_channel.register_reader());
@@ -101,13 +117,16 @@ pub fn run() -> Result<(), String> {
     let menu_rendering_system = MenuRenderingSystem;
     let dead_screen_system = DeadScreen::default();
     let common_respawn = CommonRespawn::default();
+    let background_system = BackgroundSystem::default();
     let mut dead_screen_dispatcher = DispatcherBuilder::new()
         .with(common_respawn.clone(), "common_respawn", &[])
+        .with(background_system.clone(), "background_system", &[])
         .with_thread_local(physics_system.clone())
         .with_thread_local(dead_screen_system)
         .build();
     let mut menu_dispatcher = DispatcherBuilder::new()
         .with(common_respawn.clone(), "common_respawn", &[])
+        .with(background_system.clone(), "background_system", &[])
         .with_thread_local(menu_rendering_system)
         .with_thread_local(rendering_system2)
         .with_thread_local(physics_system.clone())
@@ -128,13 +147,16 @@ pub fn run() -> Result<(), String> {
     let controlling_system = ControllingSystem::default();
     let (preloaded_sounds, music_data, _audio, _mixer, timer) =
         init_sound(&sdl_context, &mut specs_world)?;
-    specs_world.add_resource(NebulaGrid::new(1, 100f32, 100f32, 50f32, 50f32));
-    specs_world.add_resource(PlanetGrid::new(1, 60f32, 60f32, 30f32, 30f32));
-    specs_world.add_resource(StarsGrid::new(3, 40f32, 40f32, 4f32, 4f32));
-    specs_world.add_resource(FogGrid::new(2, 50f32, 50f32, 5f32, 5f32));
+    specs_world.add_resource(BackgroundLayers::new(
+        &load_background().layers,
+        &name_to_atlas,
+        &name_to_animation,
+    ));
 
     // specs_world.add_resource(MacroGame{coins: 0, score_table: 0});
     // specs_world.add_resource(TimeTracker::new());
+    specs_world.add_resource(particle_effects);
+    specs_world.add_resource(ThreadPin::new(ParticlePool::new(MAX_PARTICLES)));
     specs_world.add_resource(name_to_atlas);
     specs_world.add_resource(ThreadPin::new(music_data));
     specs_world.add_resource(Music::default());
@@ -142,13 +164,6 @@ pub fn run() -> Result<(), String> {
     specs_world.add_resource(preloaded_sounds);
     specs_world.add_resource(preloaded_particles);
     specs_world.add_resource(ThreadPin::new(timer));
-    specs_world.add_resource(ThreadPin::new(MenuParticles::new_quad(
-        &context,
-        (-size, size),
-        (-size, size),
-        (-20.0, 20.0),
-        200,
-    )));
     specs_world.add_resource(GlobalParams::default());
     let mut sound_dispatcher = DispatcherBuilder::new()
         .with_thread_local(sound_system)
@@ -156,13 +171,25 @@ pub fn run() -> Result<(), String> {
     let mut rendering_dispatcher = DispatcherBuilder::new()
         .with_thread_local(rendering_system)
         .build();
+    let mut present_dispatcher = DispatcherBuilder::new()
+        .with_thread_local(PresentSystem::<GlBackend>::default())
+        .build();
     let mut dispatcher = DispatcherBuilder::new()
         // .with(control_system, "control_system", &[])
         .with_thread_local(control_system)
-        .with(gameplay_sytem, "gameplay_system", &[])
+        .with(SpatialHashSystem::default(), "spatial_hash", &[])
+        .with(gameplay_sytem, "gameplay_system", &["spatial_hash"])
         .with(common_respawn, "common_respawn", &[])
-        .with(ai_system, "ai_system", &[])
+        .with(ai_system, "ai_system", &["spatial_hash"])
         .with(collision_system, "collision_system", &["ai_system"])
+        .with(GravitySystem::default(), "gravity_system", &["ai_system"])
+        .with(ArenaSystem::default(), "arena_system", &["gravity_system"])
+        .with(WarpSystem::default(), "warp_system", &["gameplay_system"])
+        .with(
+            TimeDilationSystem::default(),
+            "time_dilation",
+            &["spatial_hash", "gameplay_system"],
+        )
         .with(
             physics_system,
             "physics_system",
@@ -171,10 +198,17 @@ pub fn run() -> Result<(), String> {
                 // "control_system",
                 "gameplay_system",
                 "collision_system",
+                "gravity_system",
+                "arena_system",
+                "time_dilation",
+                "warp_system",
             ],
         )
         .with(destroy_sync, "destroy_sync", &[])
         .with(KinematicSystem {}, "kinematic_system", &["physics_system"])
+        .with(TrailSystem::default(), "trail_system", &["physics_system"])
+        .with(CameraSystem::default(), "camera_system", &["physics_system"])
+        .with(background_system, "background_system", &["physics_system"])
         // .with_thread_local(insert_system)
         .build();
     let mut insert_dispatcher = DispatcherBuilder::new()
@@ -197,8 +231,8 @@ pub fn run() -> Result<(), String> {
     specs_world.add_resource(keys_vec);
     specs_world.add_resource(keys_channel);
     specs_world.add_resource(sounds_channel);
+    specs_world.add_resource(EventChannel::<AnimationEvent>::with_capacity(30));
     specs_world.add_resource(insert_channel);
-    specs_world.add_resource(ThreadPin::new(context));
     specs_world.add_resource(Mouse {
         wdpi: hdpi,
         hdpi: hdpi,
@@ -345,6 +379,7 @@ pub fn run() -> Result<(), String> {
                 rendering_dispatcher.dispatch(&specs_world.res);
             }
         }
+        present_dispatcher.dispatch(&specs_world.res);
         info!("asteroids: insert dispatcher");
         flame::start("insert");
         insert_dispatcher.dispatch(&specs_world.res);
@@ -413,9 +448,9 @@ pub fn run() -> Result<(), String> {
                     let mut viewport =
                         specs_world.write_resource::<red::Viewport>();
                     viewport.update_size(w, h);
-                    let context =
-                        specs_world.read_resource::<ThreadPin<red::GL>>();
-                    viewport.set_used(&*context);
+                    let backend =
+                        specs_world.read_resource::<ThreadPin<GlBackend>>();
+                    viewport.set_used(backend.gl());
                 }
                 _ => (),
             }
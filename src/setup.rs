@@ -3,12 +3,12 @@ use backtrace::Backtrace;
 use common::*;
 use components::*;
 use gfx_h::{
-    load_atlas_image, Canvas, GeometryData, GlyphVertex, ParticlesData,
-    TextData, TextVertexBuffer, WorldTextData,
+    load_atlas_image, Camera, CameraConfig, Canvas, Emitter, GlBackend, Mesh,
+    ParticleField, ParticlesConfig, PostProcessSettings,
 };
-use glyph_brush::*;
 #[cfg(any(target_os = "android"))]
 use log::trace;
+use log::warn;
 use nphysics2d::world::World;
 use packer::SerializedSpriteSheet;
 use physics::PHYSICS_SIMULATION_TIME;
@@ -26,31 +26,16 @@ use std::path::Path;
 use std::time::Duration;
 use telemetry::TeleGraph;
 
-const NEBULAS_NUM: usize = 2usize;
-
 pub fn preloaded_images(
     name_to_atlas: &HashMap<String, AtlasImage>,
     name_to_animation: &HashMap<String, Animation>,
 ) -> PreloadedImages {
-    let mut nebula_images = vec![];
-    for i in 1..=NEBULAS_NUM {
-        let nebula_image = name_to_atlas[&format!("nebula{}", i)];
-        nebula_images.push(nebula_image);
-    }
-    let mut stars_images = vec![];
-    for i in 2..=4 {
-        let stars_image = name_to_atlas[&format!("stars{}", i)];
-        stars_images.push(stars_image);
-    }
     let mut planet_images = vec![];
     for planet_name in vec!["planet", "jupyterish", "halfmoon"].iter() {
         let planet_image = name_to_atlas[&planet_name.to_string()];
         planet_images.push(planet_image);
     }
     PreloadedImages {
-        nebulas: nebula_images,
-        stars: stars_images,
-        fog: name_to_atlas["fog"],
         planets: planet_images,
         ship_speed_upgrade: name_to_atlas["speed_upgrade"],
         bullet_speed_upgrade: name_to_atlas["bullet_speed"],
@@ -73,40 +58,37 @@ pub fn preloaded_images(
         bullet_contact: name_to_animation["bullet_contact_anim"].clone(),
         double_coin: name_to_atlas["double_coin_ability"],
         double_exp: name_to_atlas["double_exp_ability"],
+        bullet_time: name_to_atlas["bullet_time"],
         basic_ship: name_to_atlas["basic"],
         heavy_ship: name_to_atlas["heavy"],
         super_ship: name_to_atlas["basic"],
         locked: name_to_atlas["locked"],
         cursor: name_to_atlas["cursor"],
+        mine: name_to_atlas["bomb"],
     }
 }
 
+/// Animations declared in the atlas metadata, frames missing
+/// in the atlas are skipped
 pub fn load_animations(
     atlas: &SerializedSpriteSheet,
 ) -> HashMap<String, Animation> {
     let mut name_to_animation = HashMap::new();
-    // load animations
-    let animations = [
-        ("explosion_anim", 7),
-        ("blast2_anim", 7),
-        ("bullet_contact_anim", 1),
-    ]; // (name, ticks)
-    for (animation_name, ticks) in animations.iter() {
-        let mut frames = vec![];
-        for i in 1..100 {
-            let animation_name = format!("{}_{}", animation_name, i);
-            if let Some(image) = load_atlas_image(&animation_name, &atlas, 1.0)
-            {
-                let animation_frame = AnimationFrame {
-                    image,
-                    ticks: *ticks,
-                };
-                frames.push(animation_frame);
-            } else {
-                break;
-            };
+    for (animation_name, desc) in atlas.animations.iter() {
+        let frames: Vec<_> = desc
+            .frames
+            .iter()
+            .filter_map(|frame| load_atlas_image(frame, &atlas, 1.0))
+            .map(|image| AnimationFrame {
+                image,
+                ticks: desc.ticks,
+            })
+            .collect();
+        if frames.is_empty() {
+            warn!("Animation {} has no frames in the atlas", animation_name);
+            continue;
         }
-        let animation = Animation::new(frames, 1, 0);
+        let animation = Animation::new(frames, desc.mode, desc.events.clone());
         name_to_animation.insert(animation_name.to_string(), animation);
     }
     name_to_animation
@@ -143,46 +125,6 @@ pub fn setup_logging() -> slog_scope::GlobalLoggerGuard {
     guard
 }
 
-pub fn setup_text(context: &red::GL, specs_world: &mut SpecsWorld) {
-    let dejavu: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
-    {
-        let text_buffer = TextVertexBuffer::empty_new(&context).unwrap();
-        let glyph_brush: GlyphBrush<GlyphVertex, _> =
-            GlyphBrushBuilder::using_font_bytes(dejavu).build();
-        let glyph_texture = red::shader::Texture::new(
-            context,
-            glyph_brush.texture_dimensions(),
-        );
-        let text_data = ThreadPin::new(TextData {
-            vertex_buffer: text_buffer,
-            vertex_num: 0,
-            glyph_texture: glyph_texture.clone(),
-            glyph_brush,
-        });
-        specs_world.add_resource(text_data);
-        specs_world.add_resource(glyph_texture);
-    }
-    {
-        // copy paste to store world text data separetly
-        // (needed because we need different transformation uniform in shader)
-        let text_buffer = TextVertexBuffer::empty_new(&context).unwrap();
-        let glyph_brush: GlyphBrush<GlyphVertex, _> =
-            GlyphBrushBuilder::using_font_bytes(dejavu).build();
-        let glyph_texture = red::shader::Texture::new(
-            context,
-            glyph_brush.texture_dimensions(),
-        );
-        let text_data = ThreadPin::new(WorldTextData {
-            vertex_buffer: text_buffer,
-            vertex_num: 0,
-            glyph_texture: glyph_texture.clone(),
-            glyph_brush,
-        });
-        specs_world.add_resource(text_data);
-        specs_world.add_resource(glyph_texture);
-    }
-}
-
 pub fn setup_telegraph() -> TeleGraph {
     let mut telegraph = TeleGraph::new(Duration::from_secs(10));
     telegraph.set_color("rendering".to_string(), Point3::new(1.0, 0.0, 0.0));
@@ -239,7 +181,6 @@ pub fn setup_gfx(
     specs_world: &mut SpecsWorld,
 ) -> Result<
     (
-        red::GL,
         sdl2::Sdl,
         glow::native::RenderLoop<sdl2::video::Window>,
         sdl2::video::GLContext,
@@ -285,9 +226,11 @@ pub fn setup_gfx(
         video.gl_get_proc_address(s) as *const _
     });
     let context = GL::new(context);
-    let canvas = Canvas::new(&context, "", "atlas", &glsl_version).unwrap();
+    let dejavu: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+    let backend = GlBackend::new(context, "", "atlas", &glsl_version, dejavu)?;
+    specs_world.add_resource(ThreadPin::new(backend));
     specs_world.add_resource(viewport);
-    Ok((context, sdl_context, render_loop, gl_context, hdpi, canvas))
+    Ok((sdl_context, render_loop, gl_context, hdpi, Canvas::new()))
 }
 
 pub fn read_atlas(path: &str) -> SerializedSpriteSheet {
@@ -327,7 +270,7 @@ pub fn data_setup(specs_world: &mut SpecsWorld) {
     specs_world.register::<Blast>();
     specs_world.register::<ThreadPin<ImageData>>();
     specs_world.register::<AtlasImage>();
-    specs_world.register::<ThreadPin<GeometryData>>();
+    specs_world.register::<Mesh>();
     specs_world.register::<Spin>();
     specs_world.register::<AttachPosition>();
     specs_world.register::<ShotGun>();
@@ -339,6 +282,7 @@ pub fn data_setup(specs_world: &mut SpecsWorld) {
     specs_world.register::<Size>();
     specs_world.register::<EnemyMarker>();
     specs_world.register::<LightMarker>();
+    specs_world.register::<Light>();
     specs_world.register::<ShipMarker>();
     specs_world.register::<Coin>();
     specs_world.register::<SideBulletCollectable>();
@@ -347,6 +291,7 @@ pub fn data_setup(specs_world: &mut SpecsWorld) {
     specs_world.register::<DoubleCoinsAbility>();
     specs_world.register::<DoubleExpCollectable>();
     specs_world.register::<DoubleExpAbility>();
+    specs_world.register::<BulletTimeCollectable>();
     specs_world.register::<Exp>();
     specs_world.register::<Health>();
     specs_world.register::<CollectableMarker>();
@@ -356,13 +301,10 @@ pub fn data_setup(specs_world: &mut SpecsWorld) {
     specs_world.register::<ThreadPin<SoundData>>();
     specs_world.register::<Lifes>();
     specs_world.register::<Shield>();
-    specs_world.register::<NebulaMarker>();
-    specs_world.register::<StarsMarker>();
-    specs_world.register::<FogMarker>();
-    specs_world.register::<PlanetMarker>();
+    specs_world.register::<BackgroundMarker>();
     specs_world.register::<Damage>();
     specs_world.register::<AI>();
-    specs_world.register::<ThreadPin<ParticlesData>>();
+    specs_world.register::<ParticleField>();
     specs_world.register::<ShipStats>();
     specs_world.register::<Animation>();
     specs_world.register::<Charge>();
@@ -371,10 +313,20 @@ pub fn data_setup(specs_world: &mut SpecsWorld) {
     specs_world.register::<SoundPlacement>();
     specs_world.register::<Rift>();
     specs_world.register::<DamageFlash>();
+    specs_world.register::<Tint>();
+    specs_world.register::<AttackTelegraph>();
+    specs_world.register::<Elite>();
+    specs_world.register::<AsteroidType>();
+    specs_world.register::<GravityWell>();
     specs_world.register::<TextComponent>();
     specs_world.register::<Position2D>();
     specs_world.register::<ReflectBulletCollectable>();
     specs_world.register::<ReflectBulletAbility>();
+    specs_world.register::<Faction>();
+    specs_world.register::<Emitter>();
+    specs_world.register::<Trail>();
+    specs_world.register::<CameraTarget>();
+    specs_world.register::<FloatingText>();
 
     specs_world.add_resource(UpgradesStats::default());
     specs_world.add_resource(DevInfo::new());
@@ -387,6 +339,34 @@ pub fn data_setup(specs_world: &mut SpecsWorld) {
     specs_world.add_resource(touches);
 }
 
+pub fn load_particles() -> ParticlesConfig {
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/particles.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/particles.ron");
+    match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn load_background() -> BackgroundSave {
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/background.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/background.ron");
+    match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 pub fn load_description(
     specs_world: &mut SpecsWorld,
     name_to_atlas: &HashMap<String, AtlasImage>,
@@ -404,6 +384,7 @@ pub fn load_description(
     fn process_description(
         description_save: DescriptionSave,
         name_to_atlas: &HashMap<String, AtlasImage>,
+        factions: &Factions,
     ) -> Description {
         Description {
             gun_costs: description_save.gun_costs,
@@ -421,7 +402,7 @@ pub fn load_description(
             enemies: description_save
                 .enemies
                 .iter()
-                .map(|enemy| load_enemy(enemy, name_to_atlas))
+                .map(|enemy| load_enemy(enemy, name_to_atlas, factions))
                 .collect(),
         }
     }
@@ -429,6 +410,7 @@ pub fn load_description(
     fn load_enemy(
         enemy_save: &EnemyKindSave,
         name_to_atlas: &HashMap<String, AtlasImage>,
+        factions: &Factions,
     ) -> EnemyKind {
         dbg!(&enemy_save.image_name);
         EnemyKind {
@@ -439,6 +421,10 @@ pub fn load_description(
             image: name_to_atlas[&enemy_save.image_name],
             snake: enemy_save.snake,
             rift: enemy_save.rift.clone(),
+            faction: factions.by_name(&enemy_save.faction),
+            telegraph: enemy_save.telegraph,
+            trail: enemy_save.trail,
+            boss: enemy_save.boss,
         }
     }
     #[derive(Debug, Serialize, Deserialize)]
@@ -451,7 +437,33 @@ pub fn load_description(
         pub snake: Option<usize>,
         #[serde(default)]
         pub rift: Option<Rift>,
+        #[serde(default = "default_enemy_faction")]
+        pub faction: String,
+        /// windup before kamikadze or charging dash
+        #[serde(default)]
+        pub telegraph: Option<Duration>,
+        #[serde(default)]
+        pub trail: Option<TrailDesc>,
+        /// framed by the camera together with the player, intro on spawn
+        #[serde(default)]
+        pub boss: bool,
     };
+    fn default_enemy_faction() -> String {
+        "pirates".to_string()
+    }
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/factions.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/factions.ron");
+    let factions_save: FactionsSave = match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+
+            std::process::exit(1);
+        }
+    };
+    let factions = Factions::load(&factions_save);
     #[cfg(not(target_os = "android"))]
     let file = just_read("rons/desc.ron").unwrap();
     #[cfg(target_os = "android")]
@@ -469,8 +481,9 @@ pub fn load_description(
     for (id, enemy) in desc.enemies.iter().enumerate() {
         enemy_name_to_id.insert(enemy.image_name.clone(), id);
     }
-    let desc = process_description(desc, &name_to_atlas);
+    let desc = process_description(desc, &name_to_atlas, &factions);
     specs_world.add_resource(desc);
+    specs_world.add_resource(factions);
     let file = include_str!("../rons/upgrades.ron");
     let upgrades_all: Vec<UpgradeCardRaw> = match from_str(file) {
         Ok(x) => x,
@@ -516,6 +529,9 @@ pub fn load_description(
             ships_number: wave.ships_number,
             const_distribution: const_distribution,
             iterations: wave.iterations,
+            elite_chance: wave.elite_chance,
+            gravity_wells: wave.gravity_wells.clone(),
+            arena: wave.arena,
         }
     }
     #[cfg(target_os = "android")]
@@ -537,6 +553,87 @@ pub fn load_description(
             .collect(),
     );
     specs_world.add_resource(waves);
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/sectors.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/sectors.ron");
+    let sectors_save: SectorsSave = match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    specs_world.add_resource(Sectors::load(&sectors_save, &enemy_name_to_id));
+    specs_world.add_resource(CurrentSector::default());
+    specs_world.add_resource(WorldOffset::default());
+    specs_world.add_resource(ActiveArena::default());
+    specs_world.add_resource(ChainJoints::default());
+    specs_world.add_resource(SpatialHash::default());
+    specs_world.add_resource(TimeScale::default());
+    specs_world.add_resource(Warp::default());
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/asteroids.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/asteroids.ron");
+    let mut asteroid_types: AsteroidTypes = match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = asteroid_types.load_images(name_to_atlas) {
+        println!("Failed to load config: {}", e);
+        std::process::exit(1);
+    }
+    specs_world.add_resource(asteroid_types);
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/graphics.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/graphics.ron");
+    let post_process_settings: PostProcessSettings = match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // offscreen targets are too expensive for phones
+    #[cfg(target_os = "android")]
+    let post_process_settings = PostProcessSettings {
+        fallback: true,
+        ..post_process_settings
+    };
+    specs_world.add_resource(post_process_settings);
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/camera.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/camera.ron");
+    let camera_config: CameraConfig = match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = camera_config.validate() {
+        println!("Failed to load config: {}", e);
+        std::process::exit(1);
+    }
+    specs_world.add_resource(Camera::new(camera_config));
+    #[cfg(not(target_os = "android"))]
+    let file = just_read("rons/hud.ron").unwrap();
+    #[cfg(target_os = "android")]
+    let file = include_str!("../rons/hud.ron");
+    let hud_settings: HudSettings = match from_str(&file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    specs_world.add_resource(hud_settings);
     specs_world.add_resource(upgrades_all);
     specs_world.add_resource(CurrentWave::default());
 
@@ -2,6 +2,9 @@ use super::*;
 use log::info;
 use physics::*;
 
+// dash of charging ships is that much faster than their thrust
+const CHARGING_DASH_FACTOR: f32 = 2.0;
+
 #[derive(Default)]
 pub struct AISystem;
 
@@ -27,6 +30,12 @@ impl<'a> System<'a> for AISystem {
         Write<'a, BodiesMap>,
         Write<'a, EventChannel<Sound>>,
         ReadExpect<'a, PreloadedSounds>,
+        ReadStorage<'a, ShipMarker>,
+        ReadStorage<'a, Faction>,
+        ReadExpect<'a, Factions>,
+        WriteStorage<'a, AttackTelegraph>,
+        WriteStorage<'a, DamageFlash>,
+        (Read<'a, SpatialHash>, WriteStorage<'a, Trail>),
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -52,15 +61,16 @@ impl<'a> System<'a> for AISystem {
             bodies_map,
             mut sounds_channel,
             preloaded_sounds,
+            ship_markers,
+            faction_markers,
+            factions,
+            mut telegraphs,
+            mut flashes,
+            (spatial_hash, mut trails),
         ) = data;
-        let (character_entity, character_position, _) = if let Some(value) =
-            (&entities, &isometries, &character_markers).join().next()
-        {
-            value
-        } else {
+        if (&character_markers).join().next().is_none() {
             return;
         };
-        let character_position = character_position.0.translation.vector;
         for (entity, iso, vel, physics_component, spin, _enemy, ai) in (
             &entities,
             &isometries,
@@ -74,18 +84,47 @@ impl<'a> System<'a> for AISystem {
         {
             let isometry = iso.0;
             let position = isometry.translation.vector;
-            let diff = character_position - position;
+            let faction = *faction_markers.get(entity).unwrap();
+            // nearest hostile ship, active ships are never farther apart
+            let target = spatial_hash.nearest(
+                Point2::new(position.x, position.y),
+                2.0 * ENEMY_ACTIVE_AREA,
+                |target_entity| {
+                    target_entity != entity
+                        && ship_markers.get(target_entity).is_some()
+                        && faction_markers.get(target_entity).map_or(
+                            false,
+                            |target_faction| {
+                                factions.is_hostile(faction, *target_faction)
+                            },
+                        )
+                },
+            );
+            let target_entity = if let Some((target_entity, _)) = target {
+                target_entity
+            } else {
+                if let Some(telegraph) = telegraphs.get_mut(entity) {
+                    telegraph.reset();
+                }
+                continue;
+            };
+            // hash is rebuilt from isometries in the same tick
+            let target_position =
+                isometries.get(target_entity).unwrap().0.translation.vector;
+            let target_point =
+                Point2::new(target_position.x, target_position.y);
+            let diff = target_position - position;
             let dir = Vector2::new(diff.x, diff.y).normalize();
             let pos = Point2::new(position.x, position.y);
             let ray = Ray::new(pos, dir);
             let enemy_collision_groups =
-                get_collision_groups(EntityType::Enemy);
+                get_collision_groups(EntityType::Enemy(faction), &factions);
             let nearby = get_min_dist(&mut world, ray, enemy_collision_groups);
-            let mut character_noticed = false;
+            let mut target_noticed = false;
             if let Some(body) = nearby.1 {
                 // body that we facing
-                if bodies_map[&body] == character_entity {
-                    character_noticed = true;
+                if bodies_map[&body] == target_entity {
+                    target_noticed = true;
                 }
             };
             let follow_area =
@@ -94,6 +133,8 @@ impl<'a> System<'a> for AISystem {
                 } else {
                     SCREEN_AREA
                 };
+            // thrust of the frame in parts of ship thrust force
+            let mut applied_thrust = 0f32;
             for ai_type in ai.kinds.iter() {
                 match ai_type {
                     AIType::Shoot => {
@@ -102,10 +143,10 @@ impl<'a> System<'a> for AISystem {
                         if let Some(gun) = gun {
                             if diff.norm() < SCREEN_AREA
                                 && gun.shoot()
-                                && character_noticed
+                                && target_noticed
                             {
                                 let bullets = gun.spawn_bullets(
-                                    EntityType::Enemy,
+                                    EntityType::Enemy(faction),
                                     isometry,
                                     gun.bullet_speed,
                                     gun.bullets_damage,
@@ -123,7 +164,7 @@ impl<'a> System<'a> for AISystem {
                         if let Some(shotgun) = shotgun {
                             if diff.norm() < SCREEN_AREA && shotgun.shoot() {
                                 let bullets = shotgun.spawn_bullets(
-                                    EntityType::Enemy,
+                                    EntityType::Enemy(faction),
                                     isometry,
                                     shotgun.bullet_speed,
                                     shotgun.bullets_damage,
@@ -140,7 +181,7 @@ impl<'a> System<'a> for AISystem {
                         if let Some(rocket_gun) = rocket_guns.get_mut(entity) {
                             if diff.norm() < SCREEN_AREA && rocket_gun.shoot() {
                                 let bullets = rocket_gun.spawn_bullets(
-                                    EntityType::Enemy,
+                                    EntityType::Enemy(faction),
                                     isometry,
                                     rocket_gun.bullet_speed,
                                     rocket_gun.bullets_damage,
@@ -171,37 +212,14 @@ impl<'a> System<'a> for AISystem {
                     AIType::Follow => {
                         let speed =
                             ship_stats.get(entity).unwrap().thrust_force;
-                        let mut is_chain = false;
-                        if let Some(chain) = chains.get(entity) {
-                            if let Some(iso) = isometries.get(chain.follow) {
-                                is_chain = true;
-                                let follow_vector = iso.0.translation.vector;
-                                let follow_pos = Point2::new(
-                                    follow_vector.x,
-                                    follow_vector.y,
-                                );
-                                let diff = follow_pos - pos;
-                                // if diff.norm() > 1.5f32 { // for not overlap
-                                let dir = diff.normalize();
-                                let ai_vel = speed * dir;
-                                *vel = Velocity::new(ai_vel.x, ai_vel.y);
-                                let body = world
-                                    .rigid_body_mut(
-                                        physics_component.body_handle,
-                                    )
-                                    .unwrap();
-                                let mut velocity = *body.velocity();
-                                *velocity.as_vector_mut() =
-                                    Vector3::new(vel.0.x, vel.0.y, spin.0);
-                                body.set_velocity(velocity);
-                                // }
-                            }
-                        };
+                        // links are dragged by joints, only the head is driven
+                        let is_chain = chains.get(entity).is_some();
                         if !is_chain {
                             if diff.norm() > follow_area {
-                                if character_noticed {
+                                if target_noticed {
                                     let ai_vel = speed * dir;
                                     *vel = Velocity::new(ai_vel.x, ai_vel.y);
+                                    applied_thrust = 1.0;
                                 }
                             } else {
                                 let vel_vec = DAMPING_FACTOR * vel.0;
@@ -219,38 +237,15 @@ impl<'a> System<'a> for AISystem {
                     AIType::FollowRotate { spin: rot_spin } => {
                         let speed =
                             ship_stats.get(entity).unwrap().thrust_force;
-                        let mut is_chain = false;
-                        if let Some(chain) = chains.get(entity) {
-                            if let Some(iso) = isometries.get(chain.follow) {
-                                is_chain = true;
-                                let follow_vector = iso.0.translation.vector;
-                                let follow_pos = Point2::new(
-                                    follow_vector.x,
-                                    follow_vector.y,
-                                );
-                                let diff = follow_pos - pos;
-                                // if diff.norm() > 1.5f32 { // for not overlap
-                                let dir = diff.normalize();
-                                let ai_vel = speed * dir;
-                                *vel = Velocity::new(ai_vel.x, ai_vel.y);
-                                let body = world
-                                    .rigid_body_mut(
-                                        physics_component.body_handle,
-                                    )
-                                    .unwrap();
-                                let mut velocity = *body.velocity();
-                                *velocity.as_vector_mut() =
-                                    Vector3::new(vel.0.x, vel.0.y, spin.0);
-                                body.set_velocity(velocity);
-                                // }
-                            }
-                        };
+                        // links are dragged by joints, only the head is driven
+                        let is_chain = chains.get(entity).is_some();
                         let rot_spin = rot_spin.unwrap();
                         if !is_chain {
                             if diff.norm() > rot_spin.abs() {
-                                if character_noticed {
+                                if target_noticed {
                                     let ai_vel = speed * dir;
                                     *vel = Velocity::new(ai_vel.x, ai_vel.y);
+                                    applied_thrust = 1.0;
                                 }
                             } else {
                                 // let vel_vec = DAMPING_FACTOR * vel.0;
@@ -262,6 +257,7 @@ impl<'a> System<'a> for AISystem {
                                     tangent_vel.x + ai_vel.x * spiral,
                                     tangent_vel.y + ai_vel.y * spiral,
                                 );
+                                applied_thrust = 1.0;
                             }
                             let body = world
                                 .rigid_body_mut(physics_component.body_handle)
@@ -276,8 +272,8 @@ impl<'a> System<'a> for AISystem {
                         let ship_torque = DT
                             * calculate_player_ship_spin_for_aim(
                                 Vector2::new(
-                                    character_position.x,
-                                    character_position.y,
+                                    target_position.x,
+                                    target_position.y,
                                 ) - Vector2::new(position.x, position.y),
                                 iso.rotation(),
                                 spin.0,
@@ -288,10 +284,37 @@ impl<'a> System<'a> for AISystem {
                         spin.0 = *speed;
                     }
                     AIType::Kamikadze => {
-                        let speed = 0.1f32;
-                        let diff = character_position - position;
-                        let dir = speed * (diff).normalize();
+                        // elite modifiers scale the thrust
+                        let speed =
+                            ship_stats.get(entity).unwrap().thrust_force;
+                        let diff = target_position - position;
+                        let mut dir = speed * (diff).normalize();
+                        if let Some(telegraph) = telegraphs.get_mut(entity) {
+                            // target got away, warn again on the next lunge
+                            if telegraph.released && diff.norm() > SCREEN_AREA {
+                                telegraph.reset();
+                            }
+                            if !telegraph.released {
+                                if telegraph.is_over() {
+                                    telegraph.stop();
+                                    telegraph.released = true;
+                                } else if telegraph.is_active() {
+                                    // hold still while winding up
+                                    dir = Vector2::new(0f32, 0f32);
+                                } else if diff.norm() < SCREEN_AREA
+                                    && target_noticed
+                                {
+                                    telegraph.start(target_point);
+                                    sounds_channel.single_write(Sound(
+                                        preloaded_sounds.windup,
+                                        pos,
+                                    ));
+                                    dir = Vector2::new(0f32, 0f32);
+                                }
+                            }
+                        }
                         *vel = Velocity::new(dir.x, dir.y);
+                        applied_thrust = dir.norm() / speed;
                         let body = world
                             .rigid_body_mut(physics_component.body_handle)
                             .unwrap();
@@ -301,25 +324,67 @@ impl<'a> System<'a> for AISystem {
                         body.set_velocity(velocity);
                     }
                     AIType::Charging(_) => {
-                        let speed = 0.2f32;
+                        let speed = CHARGING_DASH_FACTOR
+                            * ship_stats.get(entity).unwrap().thrust_force;
                         let charging = chargings.get_mut(entity).expect(
                             "no charging component while have charging AI",
                         );
-                        if charging.shoot() {
-                            let diff = character_position - position;
-                            let dir = speed * (diff).normalize();
-                            *vel = Velocity::new(dir.x, dir.y);
-                            let body = world
-                                .rigid_body_mut(physics_component.body_handle)
-                                .unwrap();
-                            let mut velocity = *body.velocity();
-                            *velocity.as_vector_mut() =
-                                Vector3::new(vel.0.x, vel.0.y, spin.0);
-                            body.set_velocity(velocity);
+                        // point locked at the start of the windup
+                        let dash_target = if let Some(telegraph) =
+                            telegraphs.get_mut(entity)
+                        {
+                            if !charging.is_ready() {
+                                None
+                            } else if telegraph.is_over() {
+                                telegraph.stop();
+                                Some(telegraph.target)
+                            } else {
+                                if !telegraph.is_active()
+                                    && diff.norm() < SCREEN_AREA
+                                    && target_noticed
+                                {
+                                    telegraph.start(target_point);
+                                    sounds_channel.single_write(Sound(
+                                        preloaded_sounds.windup,
+                                        pos,
+                                    ));
+                                }
+                                None
+                            }
+                        } else {
+                            Some(target_point)
+                        };
+                        if let Some(dash_target) = dash_target {
+                            if charging.shoot() {
+                                let diff = dash_target - pos;
+                                let dir = speed * (diff).normalize();
+                                *vel = Velocity::new(dir.x, dir.y);
+                                applied_thrust = 1.0;
+                                let body = world
+                                    .rigid_body_mut(
+                                        physics_component.body_handle,
+                                    )
+                                    .unwrap();
+                                let mut velocity = *body.velocity();
+                                *velocity.as_vector_mut() =
+                                    Vector3::new(vel.0.x, vel.0.y, spin.0);
+                                body.set_velocity(velocity);
+                            }
                         }
                     }
                 }
             }
+            if let Some(telegraph) = telegraphs.get_mut(entity) {
+                if telegraph.is_active() {
+                    if let Some(flash) = flashes.get_mut(entity) {
+                        flash.0 = flash.0.max(telegraph.progress());
+                    }
+                }
+            }
+            // ai drives velocity directly, plume follows the driven speed
+            if let Some(trail) = trails.get_mut(entity) {
+                trail.add_thrust(applied_thrust);
+            }
             info!("asteroids: ai ended");
         }
     }
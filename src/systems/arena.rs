@@ -0,0 +1,188 @@
+use super::*;
+use log::info;
+
+// wrapped bodies appear a bit inside the border, the same as in area_reflection
+const ARENA_WRAP_MARGIN: f32 = 0.9;
+// tries to find spawn position far enough from the character
+const ARENA_SPAWN_CANDIDATES: usize = 8;
+
+/// outward normal of the border if the point is outside of the arena
+fn outside_normal(
+    shape: ArenaShape,
+    center: Point2,
+    point: Point2,
+) -> Option<Vector2> {
+    let diff = point - center;
+    match shape {
+        ArenaShape::Circle { radius } => {
+            if diff.norm() > radius {
+                Some(diff.normalize())
+            } else {
+                None
+            }
+        }
+        ArenaShape::Rectangle { width, height } => {
+            let mut normal = Vector2::new(0f32, 0f32);
+            if diff.x.abs() > width / 2.0 {
+                normal.x = diff.x.signum();
+            }
+            if diff.y.abs() > height / 2.0 {
+                normal.y = diff.y.signum();
+            }
+            if normal.norm() > EPS {
+                Some(normal.normalize())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn wrap_position(shape: ArenaShape, center: Point2, point: Point2) -> Point2 {
+    match shape {
+        ArenaShape::Circle { radius } => area_reflection(center, point, radius),
+        ArenaShape::Rectangle { width, height } => {
+            let diff = point - center;
+            let mut wrapped = point;
+            if diff.x.abs() > width / 2.0 {
+                wrapped.x = center.x
+                    - diff.x.signum() * width / 2.0 * ARENA_WRAP_MARGIN;
+            }
+            if diff.y.abs() > height / 2.0 {
+                wrapped.y = center.y
+                    - diff.y.signum() * height / 2.0 * ARENA_WRAP_MARGIN;
+            }
+            wrapped
+        }
+    }
+}
+
+fn clamp_position(shape: ArenaShape, center: Point2, point: Point2) -> Point2 {
+    match shape {
+        ArenaShape::Circle { radius } => {
+            center + (point - center).normalize() * radius
+        }
+        ArenaShape::Rectangle { width, height } => Point2::new(
+            point
+                .x
+                .max(center.x - width / 2.0)
+                .min(center.x + width / 2.0),
+            point
+                .y
+                .max(center.y - height / 2.0)
+                .min(center.y + height / 2.0),
+        ),
+    }
+}
+
+fn random_arena_point(shape: ArenaShape, center: Point2) -> Point2 {
+    let mut rng = thread_rng();
+    match shape {
+        ArenaShape::Circle { radius } => {
+            let angle = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+            // sqrt keeps the points uniform over the disk
+            let distance = radius * rng.gen_range(0f32, 1f32).sqrt();
+            center + distance * Vector2::new(angle.cos(), angle.sin())
+        }
+        ArenaShape::Rectangle { width, height } => spawn_in_rectangle(
+            center.x - width / 2.0,
+            center.x + width / 2.0,
+            center.y - height / 2.0,
+            center.y + height / 2.0,
+        ),
+    }
+}
+
+/// Spawn position for the current wave.
+/// Without arena it's the ring around the character,
+/// inside of arena it's the point farthest from the character
+/// among a few candidates if arena is smaller than the ring
+pub fn arena_spawn_position(
+    char_pos: Point2,
+    active_arena: &ActiveArena,
+    world_offset: &WorldOffset,
+) -> Point2 {
+    let arena = if let Some(arena) = active_arena.arena {
+        arena
+    } else {
+        return spawn_position(char_pos, PLAYER_AREA, ACTIVE_AREA);
+    };
+    let center = active_arena.center - world_offset.0;
+    let mut best = random_arena_point(arena.shape, center);
+    for _ in 1..ARENA_SPAWN_CANDIDATES {
+        if (best - char_pos).norm() >= PLAYER_AREA {
+            break;
+        }
+        let candidate = random_arena_point(arena.shape, center);
+        if (candidate - char_pos).norm() > (best - char_pos).norm() {
+            best = candidate;
+        }
+    }
+    best
+}
+
+/// keep dynamic bodies inside the arena of the current wave
+#[derive(Default)]
+pub struct ArenaSystem;
+
+impl<'a> System<'a> for ArenaSystem {
+    type SystemData = (
+        ReadStorage<'a, PhysicsComponent>,
+        Write<'a, World<f32>>,
+        Read<'a, ActiveArena>,
+        Read<'a, WorldOffset>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("asteroids: arena started");
+        let (physics, mut world, active_arena, world_offset) = data;
+        let arena = if let Some(arena) = active_arena.arena {
+            arena
+        } else {
+            return;
+        };
+        let center = active_arena.center - world_offset.0;
+        for physics_component in (&physics).join() {
+            let body = if let Some(body) =
+                world.rigid_body_mut(physics_component.body_handle)
+            {
+                body
+            } else {
+                continue;
+            };
+            if body.status() != BodyStatus::Dynamic {
+                continue;
+            }
+            let mut isometry = *body.position();
+            let position = Point2::new(
+                isometry.translation.vector.x,
+                isometry.translation.vector.y,
+            );
+            let normal = if let Some(normal) =
+                outside_normal(arena.shape, center, position)
+            {
+                normal
+            } else {
+                continue;
+            };
+            let new_position = match arena.behavior {
+                ArenaBehavior::Wrap => {
+                    wrap_position(arena.shape, center, position)
+                }
+                ArenaBehavior::Bounce => {
+                    let mut velocity = *body.velocity();
+                    let along = velocity.linear.dot(&normal);
+                    if along > 0f32 {
+                        velocity.linear -= 2f32 * along * normal;
+                        body.set_velocity(velocity);
+                    }
+                    clamp_position(arena.shape, center, position)
+                }
+            };
+            isometry.translation.vector.x = new_position.x;
+            isometry.translation.vector.y = new_position.y;
+            body.set_position(isometry);
+        }
+        info!("asteroids: arena ended");
+    }
+}
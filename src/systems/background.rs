@@ -0,0 +1,98 @@
+use super::*;
+
+/// Keeps the background layers from rons/background.ron
+/// filled around the character
+#[derive(Default, Clone)]
+pub struct BackgroundSystem;
+
+impl<'a> System<'a> for BackgroundSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Isometry>,
+        ReadStorage<'a, CharacterMarker>,
+        ReadStorage<'a, BackgroundMarker>,
+        WriteExpect<'a, BackgroundLayers>,
+        Write<'a, EventChannel<InsertEvent>>,
+        ReadExpect<'a, Sectors>,
+        Read<'a, CurrentSector>,
+        Read<'a, WorldOffset>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mut isometries,
+            character_markers,
+            background_markers,
+            mut layers,
+            mut insert_channel,
+            sectors,
+            current_sector,
+            world_offset,
+        ) = data;
+        let biome = &sectors.biomes[current_sector.biome];
+        // physics moved the world by that much since the last run,
+        // after a reset of the offset layers are just respawned
+        let shift = world_offset.0 - layers.offset.unwrap_or(world_offset.0);
+        layers.offset = Some(world_offset.0);
+        for (isometry, marker) in (&mut isometries, &background_markers).join()
+        {
+            let follow = 1.0 - layers.layers[marker.0].desc.parallax;
+            isometry.0.translation.vector +=
+                follow * Vector3::new(shift.x, shift.y, 0.0);
+        }
+        for layer in layers.layers.iter_mut() {
+            let parallax = layer.desc.parallax;
+            layer.grid.shift(-parallax * shift.x, -parallax * shift.y);
+        }
+        let character_position = if let Some((char_isometry, _char)) =
+            (&isometries, &character_markers).join().next()
+        {
+            let char_vec = char_isometry.0.translation.vector;
+            Point2::new(char_vec.x, char_vec.y)
+        } else {
+            Point2::new(0.0, 0.0)
+        };
+        for (entity, isometry, marker) in
+            (&entities, &isometries, &background_markers).join()
+        {
+            let grid = &mut layers.layers[marker.0].grid;
+            let pos3d = isometry.0.translation.vector;
+            if (pos3d.x - character_position.x).abs() > grid.max_w
+                || (pos3d.y - character_position.y).abs() > grid.max_h
+            {
+                entities.delete(entity).unwrap();
+                continue;
+            }
+            if let Some(count) = grid.cell_mut(Point2::new(pos3d.x, pos3d.y)) {
+                *count += 1;
+            }
+        }
+        let mut rng = thread_rng();
+        for (id, layer) in layers.layers.iter().enumerate() {
+            if biome.hidden_layers.contains(&layer.desc.name) {
+                continue;
+            }
+            for i in 0..layer.grid.size {
+                for j in 0..layer.grid.size {
+                    let count = *layer.grid.get_cell_value(i, j);
+                    for _ in count..layer.desc.density {
+                        let ((min_w, max_w), (min_h, max_h)) =
+                            layer.grid.get_rectangle(i, j);
+                        let spawn_pos =
+                            spawn_in_rectangle(min_w, max_w, min_h, max_h);
+                        let angle = if layer.desc.rotate {
+                            rng.gen_range(0.0, 2.0 * std::f32::consts::PI)
+                        } else {
+                            0.0
+                        };
+                        insert_channel.single_write(InsertEvent::Background {
+                            layer: id,
+                            iso: Point3::new(spawn_pos.x, spawn_pos.y, angle),
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
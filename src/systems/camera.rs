@@ -0,0 +1,56 @@
+use super::*;
+
+/// Moves the camera after the player, frames camera targets around
+#[derive(Default)]
+pub struct CameraSystem;
+
+impl<'a> System<'a> for CameraSystem {
+    type SystemData = (
+        ReadStorage<'a, Isometry>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, CharacterMarker>,
+        ReadStorage<'a, CameraTarget>,
+        Read<'a, Mouse>,
+        ReadExpect<'a, red::Viewport>,
+        Read<'a, Warp>,
+        WriteExpect<'a, Camera>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            isometries,
+            velocities,
+            character_markers,
+            camera_targets,
+            mouse,
+            viewport,
+            warp,
+            mut camera,
+        ) = data;
+        let delta = TRACKER.lock().unwrap().last_delta().as_secs_f32();
+        let dims = viewport.dimensions();
+        let aspect = dims.0 as f32 / dims.1 as f32;
+        let targets: Vec<Point2> = (&isometries, &camera_targets)
+            .join()
+            .map(|(iso, _)| {
+                let position = iso.0.translation.vector;
+                Point2::new(position.x, position.y)
+            })
+            .collect();
+        if let Some((iso, vel, _)) =
+            (&isometries, &velocities, &character_markers).join().next()
+        {
+            let position = iso.0.translation.vector;
+            camera.update(
+                delta,
+                Point2::new(position.x, position.y),
+                vel.0,
+                vel.0.norm() / VELOCITY_MAX
+                    + WARP_CAMERA_PULL * warp.intensity(),
+                Vector2::new(mouse.x01, mouse.y01).normalize(),
+                &targets,
+                aspect,
+            );
+        }
+    }
+}
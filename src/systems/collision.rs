@@ -1,25 +1,50 @@
 use super::*;
 use log::info;
+use ncollide2d::bounding_volume::BoundingVolume;
+use ncollide2d::query;
 use nphysics2d::algebra::Force2;
 use nphysics2d::algebra::ForceType;
+use nphysics2d::object::RigidBody;
+use std::collections::{HashMap, HashSet};
 
 const ASTEROID_DAMAGE: usize = 140usize;
 
-fn reflect_bullet(
-    projectile: specs::Entity,
-    physics_components: &ReadStorage<PhysicsComponent>,
-    world: &mut Write<World<f32>>,
-    reflections: &mut WriteStorage<Reflection>,
+/// Damage multiplier for `body` hit by `other`.
+/// Grows with relative speed along contact normal and
+/// with how heavy the other body is, tuned by the ship stats of `body`
+fn ram_factor(
+    world: &World<f32>,
+    body: BodyHandle,
+    other: BodyHandle,
     normal: Vector2,
-    lifetimes: &mut WriteStorage<Lifetime>,
-) {
-    let reflection = reflections.get_mut(projectile).unwrap();
-    let physics_component = physics_components.get(projectile).unwrap();
-    let body = world.rigid_body_mut(physics_component.body_handle).unwrap();
+    ship_stats: &ShipStats,
+) -> f32 {
+    let rigid_bodies = (world.rigid_body(body), world.rigid_body(other));
+    let (body, other) = match rigid_bodies {
+        (Some(body), Some(other)) => (body, other),
+        _ => return 0f32,
+    };
+    let relative_velocity = body.velocity().linear - other.velocity().linear;
+    let speed = relative_velocity.dot(&normal.normalize()).abs();
+    let mass = body.augmented_mass().linear;
+    let other_mass = other.augmented_mass().linear;
+    let mass_ratio = if mass + other_mass > 0f32 {
+        2f32 * other_mass / (mass + other_mass)
+    } else {
+        1f32
+    };
+    ship_stats.ram_factor(speed, mass_ratio)
+}
+
+/// ramming damage after hull armor
+fn ram_damage(damage: usize, factor: f32, ship_stats: &ShipStats) -> usize {
+    ((damage as f32 * factor) as usize).saturating_sub(ship_stats.hull_armor)
+}
+
+/// send the bullet along `vel` and turn it that way
+fn redirect_bullet(body: &mut RigidBody<f32>, vel: Vector2) {
     let position = body.position();
     let mut velocity = *body.velocity();
-    let vel = reflection.speed
-        * reflect(velocity.linear, normal.normalize()).normalize();
     *velocity.as_vector_mut() = Vector3::new(vel.x, vel.y, 0.0);
     let standart = Vector2::new(0.0, -1.0);
     let alpha =
@@ -31,6 +56,23 @@ fn reflect_bullet(
         ),
         alpha,
     );
+    body.set_position(position);
+    body.set_velocity(velocity);
+}
+
+fn reflect_bullet(
+    projectile: specs::Entity,
+    physics_components: &ReadStorage<PhysicsComponent>,
+    world: &mut Write<World<f32>>,
+    reflections: &mut WriteStorage<Reflection>,
+    normal: Vector2,
+    lifetimes: &mut WriteStorage<Lifetime>,
+) {
+    let reflection = reflections.get_mut(projectile).unwrap();
+    let physics_component = physics_components.get(projectile).unwrap();
+    let body = world.rigid_body_mut(physics_component.body_handle).unwrap();
+    let vel = reflection.speed
+        * reflect(body.velocity().linear, normal.normalize()).normalize();
     let mut new_reflection = reflection.clone();
     new_reflection.times = Some(1);
     let lifetime = if let Some(times) = reflection.times {
@@ -43,8 +85,101 @@ fn reflect_bullet(
     };
     *lifetimes.get_mut(projectile).unwrap() = Lifetime::new(lifetime);
     *reflection = new_reflection;
-    body.set_position(position);
-    body.set_velocity(velocity);
+    redirect_bullet(body, vel);
+}
+
+/// bounce of an ordinary bullet, it keeps its speed and lifetime
+fn bounce_bullet(
+    projectile: specs::Entity,
+    physics_components: &ReadStorage<PhysicsComponent>,
+    world: &mut Write<World<f32>>,
+    normal: Vector2,
+) {
+    let physics_component = physics_components.get(projectile).unwrap();
+    let body = world.rigid_body_mut(physics_component.body_handle).unwrap();
+    let vel = reflect(body.velocity().linear, normal.normalize());
+    redirect_bullet(body, vel);
+}
+
+/// Sweep the shape of the body along its last step to find
+/// what it could tunnel through. Returns the first collider on the way,
+/// position of the body at the contact and contact normal.
+/// `ignored` is the collider the body bounced off, the sweep starts on it
+pub fn swept_contact(
+    world: &World<f32>,
+    collider_handle: CollisionObjectHandle,
+    body_handle: BodyHandle,
+    ignored: Option<CollisionObjectHandle>,
+) -> Option<(CollisionObjectHandle, Point2, Vector2)> {
+    let collider_world = world.collider_world();
+    let collider = collider_world.collider(collider_handle)?;
+    let collision_groups = *collider.collision_groups();
+    let shape = collider.shape().clone();
+    let body = world.rigid_body(body_handle)?;
+    let current = *body.position();
+    let step = body.velocity().linear * world.timestep();
+    if step.norm() < EPS {
+        return None;
+    }
+    let mut previous = current;
+    previous.translation.vector -= step;
+    let swept_aabb = shape.aabb(&previous).merged(&shape.aabb(&current));
+    let zero = Vector2::new(0f32, 0f32);
+    let mut min_toi = 1f32;
+    let mut closest = None;
+    for other in
+        collider_world.interferences_with_aabb(&swept_aabb, &collision_groups)
+    {
+        if other.body() == body_handle
+            || Some(other.handle()) == ignored
+            || other.query_type().is_proximity_query()
+        {
+            continue;
+        }
+        // step is the velocity over the unit of time, so toi is in [0, 1]
+        let toi = query::time_of_impact(
+            &previous,
+            &step,
+            shape.as_ref(),
+            other.position(),
+            &zero,
+            other.shape().as_ref(),
+        );
+        if let Some(toi) = toi {
+            if toi <= min_toi {
+                min_toi = toi;
+                let mut hit = previous;
+                hit.translation.vector += step * toi;
+                let hit_position = hit.translation.vector;
+                // shapes just touch at the time of impact
+                let normal = query::contact(
+                    &hit,
+                    shape.as_ref(),
+                    other.position(),
+                    other.shape().as_ref(),
+                    step.norm(),
+                )
+                .map_or(step.normalize(), |contact| *contact.normal);
+                closest = Some((
+                    other.handle(),
+                    Point2::new(hit_position.x, hit_position.y),
+                    normal,
+                ));
+            }
+        }
+    }
+    closest
+}
+
+fn projectile_entity(
+    world: &World<f32>,
+    bodies_map: &BodiesMap,
+    projectiles: &ReadStorage<Projectile>,
+    handle: CollisionObjectHandle,
+) -> Option<specs::Entity> {
+    let body = world.collider_world().collider(handle)?.body();
+    let entity = *bodies_map.get(&body)?;
+    projectiles.get(entity).map(|_| entity)
 }
 
 fn damage_ship(
@@ -65,11 +200,13 @@ fn damage_ship(
     ship_pos: Point2,
     damage: usize,
     bullet: bool,
+    elite: Option<&Elite>,
 ) {
     if is_character {
         if bullet {
             global_params.damaged(DAMAGED_RED);
-            insert_channel.single_write(InsertEvent::Wobble(0.1f32));
+            insert_channel
+                .single_write(InsertEvent::Wobble(HIT_WOBBLE.to_string()));
         }
     }
     if bullet {
@@ -81,11 +218,25 @@ fn damage_ship(
             preloaded_images,
         );
     }
-    if process_damage(
+    let killed = process_damage(
         lifes.get_mut(ship).unwrap(),
         shields.get_mut(ship),
         damage,
-    ) {
+    );
+    if !is_character && damage > 0 {
+        // there are no critical hits, killing ones are shown larger
+        insert_channel.single_write(InsertEvent::FloatingText {
+            position: ship_pos,
+            text: damage.to_string(),
+            color: (1.0, 0.8, 0.4, 1.0),
+            font_size: if killed {
+                LETHAL_TEXT_SIZE
+            } else {
+                DAMAGE_TEXT_SIZE
+            },
+        });
+    }
+    if killed {
         // ship is done... Explode it
         ship_explode(
             ship_pos,
@@ -93,6 +244,15 @@ fn damage_ship(
             sounds_channel,
             preloaded_sounds,
         );
+        if let Some(elite) = elite {
+            elite_death(
+                elite,
+                ship,
+                ship_pos,
+                insert_channel,
+                preloaded_images,
+            );
+        }
         if is_character {
             to_menu(app_state, progress, &mut macro_game.score_table);
         }
@@ -105,6 +265,12 @@ pub struct CollisionSystem {
     colliding_pairs:
         Vec<(CollisionObjectHandle, CollisionObjectHandle, Vector2)>,
     colliding_start_events: Vec<(CollisionObjectHandle, CollisionObjectHandle)>,
+    // projectiles which made at least one physics step
+    moved_projectiles: HashSet<specs::Entity>,
+    // last collider each projectile touched
+    last_contacts: HashMap<specs::Entity, CollisionObjectHandle>,
+    // ordinary bullets which already bounced off a metallic asteroid
+    bounced_projectiles: HashSet<specs::Entity>,
 }
 
 impl<'a> System<'a> for CollisionSystem {
@@ -128,20 +294,29 @@ impl<'a> System<'a> for CollisionSystem {
         Read<'a, BodiesMap>,
         Write<'a, EventChannel<InsertEvent>>,
         Write<'a, EventChannel<Sound>>,
-        ReadExpect<'a, PreloadedSounds>,
-        ReadExpect<'a, PreloadedImages>,
+        (
+            ReadExpect<'a, PreloadedSounds>,
+            ReadExpect<'a, PreloadedImages>,
+        ),
         Write<'a, Progress>,
         Write<'a, AppState>,
         WriteExpect<'a, MacroGame>,
         WriteExpect<'a, GlobalParams>,
         ReadExpect<'a, Arc<Mutex<EventChannel<InsertEvent>>>>,
+        (
+            ReadStorage<'a, Faction>,
+            ReadExpect<'a, Factions>,
+            ReadStorage<'a, Elite>,
+            ReadStorage<'a, ShipStats>,
+            ReadStorage<'a, AsteroidType>,
+        ),
     );
 
     fn run(&mut self, data: Self::SystemData) {
         info!("asteroids: collision started");
         let (
             entities,
-            isometries,
+            mut isometries,
             physics_components,
             asteroids,
             character_markers,
@@ -159,13 +334,13 @@ impl<'a> System<'a> for CollisionSystem {
             bodies_map,
             mut insert_channel,
             mut sounds_channel,
-            preloaded_sounds,
-            preloaded_images,
+            (preloaded_sounds, preloaded_images),
             mut progress,
             mut app_state,
             mut macro_game,
             mut global_params,
             asteroids_channel,
+            (faction_markers, factions, elites, ships_stats, asteroid_types),
         ) = data;
         self.colliding_pairs.clear();
         self.colliding_start_events.clear();
@@ -191,6 +366,76 @@ impl<'a> System<'a> for CollisionSystem {
                 }
             }
         }
+        // bounced projectile touches the same collider for one more step
+        let last_contacts = &self.last_contacts;
+        let is_bounce = |handle, other| {
+            projectile_entity(&world, &bodies_map, &projectiles, handle)
+                .map_or(false, |projectile| {
+                    last_contacts.get(&projectile) == Some(&other)
+                })
+        };
+        self.colliding_pairs.retain(|(handle1, handle2, _)| {
+            !is_bounce(*handle1, *handle2) && !is_bounce(*handle2, *handle1)
+        });
+        // fast projectiles tunnel through small bodies in one step,
+        // so sweep them and treat the first hit as a usual contact
+        let mut moved_projectiles = HashSet::new();
+        for (projectile, physics_component, _projectile) in
+            (&entities, &physics_components, &projectiles).join()
+        {
+            moved_projectiles.insert(projectile);
+            if !self.moved_projectiles.contains(&projectile) {
+                continue;
+            }
+            let collider_handle = physics_component.collider_handle;
+            if self.colliding_pairs.iter().any(|(h1, h2, _)| {
+                *h1 == collider_handle || *h2 == collider_handle
+            }) {
+                continue;
+            }
+            if let Some((hit_handle, hit_point, normal)) = swept_contact(
+                &world,
+                collider_handle,
+                physics_component.body_handle,
+                self.last_contacts.get(&projectile).cloned(),
+            ) {
+                // put the projectile where it actually hit
+                let body = world
+                    .rigid_body_mut(physics_component.body_handle)
+                    .unwrap();
+                let mut physics_isometry = *body.position();
+                physics_isometry.translation.vector.x = hit_point.x;
+                physics_isometry.translation.vector.y = hit_point.y;
+                body.set_position(physics_isometry);
+                if let Some(isometry) = isometries.get_mut(projectile) {
+                    isometry.0.translation.vector.x = hit_point.x;
+                    isometry.0.translation.vector.y = hit_point.y;
+                }
+                self.colliding_pairs.push((
+                    collider_handle,
+                    hit_handle,
+                    normal,
+                ));
+            }
+        }
+        self.last_contacts
+            .retain(|projectile, _| moved_projectiles.contains(projectile));
+        self.bounced_projectiles
+            .retain(|projectile| moved_projectiles.contains(projectile));
+        self.moved_projectiles = moved_projectiles;
+        for (handle1, handle2, _) in self.colliding_pairs.iter() {
+            for &(handle, other) in
+                [(*handle1, *handle2), (*handle2, *handle1)].iter()
+            {
+                if let Some(projectile) =
+                    projectile_entity(&world, &bodies_map, &projectiles, handle)
+                {
+                    self.last_contacts.insert(projectile, other);
+                }
+            }
+        }
+        // several bullets may hit the same asteroid in one frame
+        let mut carved_asteroids = HashSet::new();
 
         for (handle1, handle2, normal) in self.colliding_pairs.iter() {
             let (body_handle1, body_handle2) = {
@@ -250,6 +495,9 @@ impl<'a> System<'a> for CollisionSystem {
                     );
                     let projectile = entity2;
                     let projectile_damage = damages.get(projectile).unwrap().0;
+                    let reflective = asteroid_types
+                        .get(asteroid)
+                        .map_or(false, |kind| kind.reflective);
                     if projectile_damage != 0 {
                         if reflections.get(projectile).is_some() {
                             reflect_bullet(
@@ -260,29 +508,81 @@ impl<'a> System<'a> for CollisionSystem {
                                 *normal,
                                 &mut lifetimes,
                             );
+                        } else if reflective
+                            && self.bounced_projectiles.insert(projectile)
+                        {
+                            // metallic asteroid bounces ordinary bullets once
+                            bounce_bullet(
+                                projectile,
+                                &physics_components,
+                                &mut world,
+                                *normal,
+                            );
                         } else {
                             entities.delete(projectile).unwrap();
                         }
                     }
-                    let lifes = lifes.get_mut(asteroid).unwrap();
-                    if lifes.0 > projectile_damage {
-                        lifes.0 -= projectile_damage
+                    let polygon = polygons.get(asteroid).unwrap();
+                    let carvable = projectile_damage != 0
+                        && !reflective
+                        && polygon.max_r > CARVE_MIN_RADIUS;
+                    let carved = carvable
+                        && (carved_asteroids.contains(&asteroid) || {
+                            let body_handle = physics_components
+                                .get(asteroid)
+                                .unwrap()
+                                .body_handle;
+                            carve_asteroid(
+                                asteroid,
+                                isometries.get(asteroid).unwrap().0,
+                                polygon,
+                                asteroid_types.get(asteroid).unwrap(),
+                                world
+                                    .rigid_body(body_handle)
+                                    .unwrap()
+                                    .velocity()
+                                    .linear,
+                                proj_pos2d,
+                                CARVE_BULLET_RADIUS,
+                                &entities,
+                                &mut insert_channel,
+                            )
+                        });
+                    if carved {
+                        carved_asteroids.insert(asteroid);
                     } else {
-                        if lifes.0 > 0 {
-                            lifes.0 = 0;
-                            asteroid_explosion = true
+                        let lifes = lifes.get_mut(asteroid).unwrap();
+                        if lifes.0 > projectile_damage {
+                            lifes.0 -= projectile_damage
+                        } else {
+                            if lifes.0 > 0 {
+                                lifes.0 = 0;
+                                asteroid_explosion = true
+                            }
                         }
                     }
                 };
-                if ships.get(entity2).is_some() {
+                let ship_ram = if let (Some(_), Some(ship_stats)) =
+                    (ships.get(entity2), ships_stats.get(entity2))
+                {
+                    ram_factor(
+                        &world,
+                        physics_components.get(entity2).unwrap().body_handle,
+                        physics_components.get(asteroid).unwrap().body_handle,
+                        *normal,
+                        ship_stats,
+                    )
+                } else {
+                    0f32
+                };
+                if ship_ram > 0f32 {
                     let ship = entity2;
                     let isometry = isometries.get(ship).unwrap().0;
                     let position = isometry.translation.vector;
                     // asteroid_explosion = true;
                     let effect = InsertEvent::Explosion {
                         position: Point2::new(position.x, position.y),
-                        num: 3usize,
-                        lifetime: Duration::from_secs(EXPLOSION_LIFETIME_SECS),
+                        emitter: "ram".to_string(),
                         with_animation: None,
                     };
                     insert_channel.single_write(effect);
@@ -310,10 +610,15 @@ impl<'a> System<'a> for CollisionSystem {
                             &mut global_params,
                             Point2::new(position.x, position.y),
                             Point2::new(position.x, position.y),
-                            (ASTEROID_DAMAGE as f32
-                                * sizes.get(asteroid).unwrap().0)
-                                as usize,
+                            ram_damage(
+                                (ASTEROID_DAMAGE as f32
+                                    * sizes.get(asteroid).unwrap().0)
+                                    as usize,
+                                ship_ram,
+                                ships_stats.get(ship).unwrap(),
+                            ),
                             false,
+                            None,
                         );
                         bullet_contact(
                             Point2::new(position.x, position.y),
@@ -326,8 +631,9 @@ impl<'a> System<'a> for CollisionSystem {
                     }
                 }
                 if asteroid_explosion {
-                    insert_channel
-                        .single_write(InsertEvent::Wobble(EXPLOSION_WOBBLE));
+                    insert_channel.single_write(InsertEvent::Wobble(
+                        EXPLOSION_WOBBLE.to_string(),
+                    ));
                     let isometry = isometries.get(asteroid).unwrap().0;
                     let position = isometry.translation.vector;
                     let polygon = polygons.get(asteroid).unwrap();
@@ -341,6 +647,7 @@ impl<'a> System<'a> for CollisionSystem {
                     );
                     let iso = isometries.get(asteroid).unwrap().0;
                     let poly = polygons.get(asteroid).unwrap().clone();
+                    let kind = asteroid_types.get(asteroid).unwrap().clone();
                     let channel_arc = (*asteroids_channel).clone();
                     thread::spawn(move || {
                         spawn_asteroids(
@@ -348,6 +655,7 @@ impl<'a> System<'a> for CollisionSystem {
                             poly,
                             channel_arc,
                             bullet_position,
+                            kind,
                         );
                     });
                     entities.delete(asteroid).unwrap();
@@ -389,7 +697,17 @@ impl<'a> System<'a> for CollisionSystem {
                     Point2::new(position.x, position.y),
                     projectile_damage,
                     true,
+                    elites.get(ship),
                 );
+                if character_markers.get(ship).is_some() {
+                    vampiric_heal(
+                        projectiles.get(projectile).unwrap().owner,
+                        projectile_damage,
+                        &elites,
+                        &mut lifes,
+                        &ships_stats,
+                    );
+                }
                 // Kludge
                 if projectile_damage != 0 {
                     if reflections.get(projectile).is_some() {
@@ -414,6 +732,23 @@ impl<'a> System<'a> for CollisionSystem {
                 if character_markers.get(ship2).is_some() {
                     swap(&mut ship1, &mut ship2)
                 }
+                let ram = |ship: specs::Entity, other: specs::Entity| {
+                    let ship_stats = match ships_stats.get(ship) {
+                        Some(ship_stats) => ship_stats,
+                        None => return 0,
+                    };
+                    ram_damage(
+                        damages.get(other).unwrap().0,
+                        ram_factor(
+                            &world,
+                            physics_components.get(ship).unwrap().body_handle,
+                            physics_components.get(other).unwrap().body_handle,
+                            *normal,
+                            ship_stats,
+                        ),
+                        ship_stats,
+                    )
+                };
                 if character_markers.get(ship1).is_some() {
                     let character_ship = ship1;
                     let other_ship = ship2;
@@ -422,10 +757,14 @@ impl<'a> System<'a> for CollisionSystem {
                         preloaded_sounds.collision,
                         Point2::new(0f32, 0f32),
                     ));
+                    let (other_damage, character_damage) = (
+                        ram(other_ship, character_ship),
+                        ram(character_ship, other_ship),
+                    );
                     if process_damage(
                         lifes.get_mut(other_ship).unwrap(),
                         shields.get_mut(other_ship),
-                        damages.get(character_ship).unwrap().0,
+                        other_damage,
                     ) {
                         ship_explode(
                             Point2::new(position.x, position.y),
@@ -433,13 +772,31 @@ impl<'a> System<'a> for CollisionSystem {
                             &mut sounds_channel,
                             &preloaded_sounds,
                         );
+                        if let Some(elite) = elites.get(other_ship) {
+                            elite_death(
+                                elite,
+                                other_ship,
+                                Point2::new(position.x, position.y),
+                                &mut insert_channel,
+                                &preloaded_images,
+                            );
+                        }
                         entities.delete(other_ship).unwrap();
                     }
-                    global_params.damaged(DAMAGED_RED);
+                    if character_damage > 0 {
+                        global_params.damaged(DAMAGED_RED);
+                    }
+                    vampiric_heal(
+                        other_ship,
+                        character_damage,
+                        &elites,
+                        &mut lifes,
+                        &ships_stats,
+                    );
                     if process_damage(
                         lifes.get_mut(character_ship).unwrap(),
                         shields.get_mut(character_ship),
-                        damages.get(other_ship).unwrap().0,
+                        character_damage,
                     ) {
                         to_menu(
                             &mut app_state,
@@ -449,6 +806,60 @@ impl<'a> System<'a> for CollisionSystem {
                         // delete character
                         entities.delete(character_ship).unwrap();
                     }
+                } else {
+                    // infighting between hostile factions
+                    let hostile = match (
+                        faction_markers.get(ship1),
+                        faction_markers.get(ship2),
+                    ) {
+                        (Some(faction1), Some(faction2)) => {
+                            factions.is_hostile(*faction1, *faction2)
+                        }
+                        _ => false,
+                    };
+                    if hostile {
+                        let ram_damages =
+                            [ram(ship1, ship2), ram(ship2, ship1)];
+                        for (ship, damage) in
+                            [ship1, ship2].iter().zip(ram_damages.iter())
+                        {
+                            if !entities.is_alive(*ship) || *damage == 0 {
+                                continue;
+                            }
+                            if let Some(flash) = flashes.get_mut(*ship) {
+                                flash.0 = (flash.0 + 0.5).min(1f32);
+                            }
+                            let damage = *damage;
+                            if process_damage(
+                                lifes.get_mut(*ship).unwrap(),
+                                shields.get_mut(*ship),
+                                damage,
+                            ) {
+                                let position = isometries
+                                    .get(*ship)
+                                    .unwrap()
+                                    .0
+                                    .translation
+                                    .vector;
+                                ship_explode(
+                                    Point2::new(position.x, position.y),
+                                    &mut insert_channel,
+                                    &mut sounds_channel,
+                                    &preloaded_sounds,
+                                );
+                                if let Some(elite) = elites.get(*ship) {
+                                    elite_death(
+                                        elite,
+                                        *ship,
+                                        Point2::new(position.x, position.y),
+                                        &mut insert_channel,
+                                        &preloaded_images,
+                                    );
+                                }
+                                entities.delete(*ship).unwrap();
+                            }
+                        }
+                    }
                 }
             }
         }
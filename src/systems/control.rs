@@ -38,6 +38,9 @@ impl<'a> System<'a> for ControlSystem {
             ReadStorage<'a, AsteroidMarker>,
             WriteStorage<'a, ShipStats>,
             WriteStorage<'a, Rift>,
+            ReadStorage<'a, Faction>,
+            ReadStorage<'a, AsteroidType>,
+            WriteStorage<'a, Trail>,
         ),
         Read<'a, EventChannel<Keycode>>,
         Read<'a, Mouse>,
@@ -48,11 +51,13 @@ impl<'a> System<'a> for ControlSystem {
         Write<'a, BodiesMap>,
         Write<'a, EventChannel<InsertEvent>>,
         Write<'a, AppState>,
-        WriteExpect<'a, Canvas>,
+        WriteExpect<'a, Camera>,
         Write<'a, Progress>,
         WriteExpect<'a, MacroGame>,
         WriteExpect<'a, DevInfo>,
         ReadExpect<'a, Arc<Mutex<EventChannel<InsertEvent>>>>,
+        ReadExpect<'a, Factions>,
+        ReadStorage<'a, Elite>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -72,6 +77,9 @@ impl<'a> System<'a> for ControlSystem {
                 asteroid_markers,
                 mut ships_stats,
                 mut rifts,
+                faction_markers,
+                asteroid_types,
+                mut trails,
             ),
             keys_channel,
             mouse_state,
@@ -82,11 +90,13 @@ impl<'a> System<'a> for ControlSystem {
             bodies_map,
             mut insert_channel,
             mut app_state,
-            mut canvas,
+            mut camera,
             mut progress,
             mut macro_game,
             mut dev_info,
             asteroids_channel,
+            factions,
+            elites,
         ) = data;
         info!("asteroids: started control system");
         let (ship_stats, _) = if let Some(value) =
@@ -137,12 +147,19 @@ impl<'a> System<'a> for ControlSystem {
                     multy_lazer.set_all(false);
                 }
             }
+            let lazer_kind = |entity| {
+                if let Some(faction) = faction_markers.get(entity) {
+                    EntityType::from_faction(*faction)
+                } else {
+                    EntityType::Player
+                }
+            };
             let mut process_lazer =
                 |isometry: &Isometry3,
                  lazer: &mut Lazer,
                  world: &mut Write<World<f32>>,
                  bodies_map: &Write<BodiesMap>,
-                 is_character: bool,
+                 kind: EntityType,
                  rotation| {
                     // let body = world
                     //     .rigid_body(physics_component.body_handle)
@@ -153,11 +170,8 @@ impl<'a> System<'a> for ControlSystem {
                     let pos = Point2::new(position.x, position.y);
                     let dir = isometry * (rotation * Vector2::new(0f32, -1f32));
                     let ray = Ray::new(pos, dir);
-                    let collision_groups = if is_character {
-                        get_collision_groups(EntityType::Player)
-                    } else {
-                        get_collision_groups(EntityType::Enemy)
-                    };
+                    let collision_groups =
+                        get_collision_groups(kind, &factions);
                     let (min_d, closest_body) =
                         get_min_dist(world, ray, collision_groups);
                     if min_d < lazer.distance {
@@ -202,6 +216,10 @@ impl<'a> System<'a> for ControlSystem {
                                             .get(asteroid)
                                             .unwrap()
                                             .clone();
+                                        let kind = asteroid_types
+                                            .get(asteroid)
+                                            .unwrap()
+                                            .clone();
                                         let channel_arc =
                                             (*asteroids_channel).clone();
                                         thread::spawn(move || {
@@ -210,6 +228,7 @@ impl<'a> System<'a> for ControlSystem {
                                                 poly,
                                                 channel_arc,
                                                 None,
+                                                kind,
                                             );
                                         });
                                     } else {
@@ -219,19 +238,32 @@ impl<'a> System<'a> for ControlSystem {
                                             .0
                                             .translation
                                             .vector;
+                                        let target_position = Point2::new(
+                                            target_position.x,
+                                            target_position.y,
+                                        );
                                         ship_explode(
-                                            Point2::new(
-                                                target_position.x,
-                                                target_position.y,
-                                            ),
+                                            target_position,
                                             &mut insert_channel,
                                             &mut sounds_channel,
                                             &preloaded_sounds,
                                         );
+                                        if let Some(elite) =
+                                            elites.get(*target_entity)
+                                        {
+                                            elite_death(
+                                                elite,
+                                                *target_entity,
+                                                target_position,
+                                                &mut insert_channel,
+                                                &preloaded_images,
+                                            );
+                                        }
                                     }
-                                    let explosion_size = 20;
                                     insert_channel.single_write(
-                                        InsertEvent::Wobble(EXPLOSION_WOBBLE),
+                                        InsertEvent::Wobble(
+                                            EXPLOSION_WOBBLE.to_string(),
+                                        ),
                                     );
                                     if character_markers
                                         .get(*target_entity)
@@ -250,8 +282,7 @@ impl<'a> System<'a> for ControlSystem {
                                             effect_position.x,
                                             effect_position.y,
                                         ),
-                                        num: explosion_size,
-                                        lifetime: Duration::from_millis(800),
+                                        emitter: "lazer_kill".to_string(),
                                         with_animation: None,
                                     };
                                     insert_channel.single_write(effect);
@@ -294,7 +325,7 @@ impl<'a> System<'a> for ControlSystem {
                         &mut lazer,
                         &mut world,
                         &bodies_map,
-                        character_markers.get(e1).is_some(),
+                        lazer_kind(e1),
                         zero_rotation,
                     );
                     upgdate_rifts.push((e1, lazer.clone(), dir.normalize()));
@@ -325,7 +356,7 @@ impl<'a> System<'a> for ControlSystem {
                         lazer,
                         &mut world,
                         &bodies_map,
-                        character_markers.get(entity).is_some(),
+                        lazer_kind(entity),
                         rotation,
                     )
                 }
@@ -360,6 +391,8 @@ impl<'a> System<'a> for ControlSystem {
             self.prev_keys = self.new_keys.clone();
             self.new_keys.clear();
             let mut angular_vel = None;
+            // in parts of thrust force, engine plume follows it
+            let mut applied_thrust = 0f32;
             for key in keys_channel.read(&mut self.reader) {
                 self.new_keys.insert(*key);
                 let mut thrust = match key {
@@ -379,10 +412,10 @@ impl<'a> System<'a> for ControlSystem {
                 };
                 match key {
                     Keycode::LeftBracket => {
-                        canvas.z_far -= 0.5;
+                        camera.zoom_in();
                     }
                     Keycode::RightBracket => {
-                        canvas.z_far += 0.5;
+                        camera.zoom_out();
                     }
                     Keycode::Left => {
                         let player_torque = -5. * DT;
@@ -428,6 +461,7 @@ impl<'a> System<'a> for ControlSystem {
                     thrust,
                     *character_velocity.as_vector(),
                 );
+                applied_thrust += thrust.norm() / ship_stats.thrust_force;
                 *character_velocity.as_vector_mut() += thrust;
             }
             let new_pressed = &self.new_keys - &self.prev_keys;
@@ -448,8 +482,12 @@ impl<'a> System<'a> for ControlSystem {
                 let _vel = velocities.get_mut(character).unwrap();
                 let thrust = ship_stats.thrust_force
                     * (rotation * Vector3::new(0.0, 1.0, 0.0));
+                applied_thrust += 1.0;
                 *character_velocity.as_vector_mut() += thrust;
             }
+            if let Some(trail) = trails.get_mut(character) {
+                trail.add_thrust(applied_thrust);
+            }
             let character_body = world
                 .rigid_body_mut(physics.get(character).unwrap().body_handle)
                 .unwrap();
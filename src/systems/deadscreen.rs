@@ -12,6 +12,11 @@ impl<'a> System<'a> for DeadScreen {
         Write<'a, UI>,
         Write<'a, AppState>,
         Write<'a, CurrentWave>,
+        Write<'a, CurrentSector>,
+        Write<'a, ActiveArena>,
+        Write<'a, WorldOffset>,
+        Write<'a, TimeScale>,
+        Write<'a, Warp>,
         Write<'a, SpawnedUpgrades>,
         Read<'a, Mouse>,
         WriteExpect<'a, PreloadedImages>,
@@ -28,6 +33,11 @@ impl<'a> System<'a> for DeadScreen {
             mut ui,
             mut app_state,
             mut current_wave,
+            mut current_sector,
+            mut active_arena,
+            mut world_offset,
+            mut time_scale,
+            mut warp,
             mut spawned_upgrades,
             mouse,
             preloaded_images,
@@ -84,6 +94,11 @@ impl<'a> System<'a> for DeadScreen {
             macro_game.score_table.sort_by(|a, b| b.cmp(a));
             *progress = Progress::default();
             *current_wave = CurrentWave::default();
+            *current_sector = CurrentSector::default();
+            *active_arena = ActiveArena::default();
+            *world_offset = WorldOffset::default();
+            *time_scale = TimeScale::default();
+            *warp = Warp::default();
         }
     }
 }
@@ -55,6 +55,23 @@ impl<'a> System<'a> for GamePlaySystem {
         ReadExpect<'a, Arc<Mutex<EventChannel<InsertEvent>>>>,
         Read<'a, LazyUpdate>,
         Write<'a, UpgradesStats>,
+        ReadStorage<'a, Faction>,
+        ReadExpect<'a, Factions>,
+        ReadStorage<'a, Elite>,
+        ReadStorage<'a, AsteroidType>,
+        ReadStorage<'a, GravityWell>,
+        (
+            ReadExpect<'a, Sectors>,
+            Write<'a, CurrentSector>,
+            Write<'a, ActiveArena>,
+            Read<'a, WorldOffset>,
+            ReadStorage<'a, Velocity>,
+            Read<'a, SpatialHash>,
+            ReadStorage<'a, BulletTimeCollectable>,
+            Write<'a, TimeScale>,
+            Write<'a, Warp>,
+            ReadStorage<'a, FloatingText>,
+        ),
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -107,6 +124,23 @@ impl<'a> System<'a> for GamePlaySystem {
             asteroids_channel,
             lazy_update,
             mut upgrade_stats,
+            faction_markers,
+            factions,
+            elites,
+            asteroid_types,
+            gravity_wells,
+            (
+                sectors,
+                mut current_sector,
+                mut active_arena,
+                world_offset,
+                velocities,
+                spatial_hash,
+                bullet_time_collectables,
+                mut time_scale,
+                mut warp,
+                floating_texts,
+            ),
         ) = data;
         let dims = viewport.dimensions();
         let (w, h) = (dims.0 as f32, dims.1 as f32);
@@ -123,11 +157,15 @@ impl<'a> System<'a> for GamePlaySystem {
                 (shield.0 + ship_stats.shield_regen).min(ship_stats.max_shield);
             life.0 =
                 (life.0 + ship_stats.health_regen).min(ship_stats.max_health);
+            let biome = &sectors.biomes[current_sector.biome];
+            shield.0 = shield.0.saturating_sub(biome.shield_drain);
         } else {
             return;
         };
+        let mut leveled_up = false;
         if progress.experience >= progress.current_max_experience() {
             progress.level_up();
+            leveled_up = true;
             let mut rng = thread_rng();
             let up_id = rng.gen_range(0, avaliable_upgrades.len());
             let mut second_id = rng.gen_range(0, avaliable_upgrades.len());
@@ -145,6 +183,30 @@ impl<'a> System<'a> for GamePlaySystem {
         let char_isometry = char_isometry.clone(); // to avoid borrow
         let pos3d = char_isometry.0.translation.vector;
         let character_position = Point2::new(pos3d.x, pos3d.y);
+        if leveled_up {
+            insert_channel.single_write(InsertEvent::FloatingText {
+                position: character_position,
+                text: "LEVEL UP".to_string(),
+                color: (1.0, 1.0, 1.0, 1.0),
+                font_size: LEVEL_UP_TEXT_SIZE,
+            });
+        }
+        let sector = sectors.sector(character_position + world_offset.0);
+        if current_sector.sector != Some(sector) {
+            current_sector.sector = Some(sector);
+            current_sector.biome = sectors.biome_id(sector);
+            add_screen_text(
+                &entities,
+                TextComponent {
+                    text: sectors.biomes[current_sector.biome].name.clone(),
+                    color: (0.7, 0.9, 1.0, 1.0),
+                },
+                &lazy_update,
+                Point2::new(w / 2.0, h / 3.0),
+                Some(Lifetime::new(Duration::from_secs(1))),
+            );
+        }
+        let biome = &sectors.biomes[current_sector.biome];
         {
             // player trace
             let mut transparent_basic = preloaded_images.glow;
@@ -172,6 +234,10 @@ impl<'a> System<'a> for GamePlaySystem {
         }
         for (entity, lifetime) in (&entities, &mut lifetimes).join() {
             if lifetime.delete() {
+                // pooled, InsertSystem reuses expired floating texts
+                if floating_texts.get(entity).is_some() {
+                    continue;
+                }
                 if double_coins_abilities.get(entity).is_some() {
                     upgrade_stats.coins_mult /= 2;
                     entities.delete(entity).unwrap();
@@ -215,6 +281,7 @@ impl<'a> System<'a> for GamePlaySystem {
                         } else {
                             entity
                         };
+                    let blast_faction = faction_markers.get(entity);
                     let position =
                         isometries.get(entity).unwrap().0.translation.vector;
                     blast_explode(
@@ -236,13 +303,43 @@ impl<'a> System<'a> for GamePlaySystem {
                         let is_character = entity == char_entity;
                         let is_asteroid =
                             asteroid_markers.get(entity).is_some();
-                        let affected = is_character && owner != char_entity
-                            || entity != char_entity
-                                && (owner == char_entity || is_asteroid);
+                        let hostile = match (
+                            blast_faction,
+                            faction_markers.get(entity),
+                        ) {
+                            (Some(f1), Some(f2)) => {
+                                factions.is_hostile(*f1, *f2)
+                            }
+                            // exploding asteroids hurt everyone
+                            (None, _) => true,
+                            _ => false,
+                        };
+                        let affected =
+                            entity != owner && (hostile || is_asteroid);
                         if affected
                             && (blast_position - position).norm()
                                 < blast.blast_radius
                         {
+                            if is_asteroid
+                                && polygons.get(entity).unwrap().max_r
+                                    > CARVE_MIN_RADIUS
+                                && carve_asteroid(
+                                    entity,
+                                    isometry.0,
+                                    polygons.get(entity).unwrap(),
+                                    asteroid_types.get(entity).unwrap(),
+                                    velocities.get(entity).unwrap().0,
+                                    Point2::new(
+                                        blast_position.x,
+                                        blast_position.y,
+                                    ),
+                                    blast.blast_radius,
+                                    &entities,
+                                    &mut insert_channel,
+                                )
+                            {
+                                continue;
+                            }
                             if is_character {
                                 global_params.damaged(DAMAGED_RED);
                             }
@@ -266,6 +363,10 @@ impl<'a> System<'a> for GamePlaySystem {
                                         isometries.get(asteroid).unwrap().0;
                                     let poly =
                                         polygons.get(asteroid).unwrap().clone();
+                                    let kind = asteroid_types
+                                        .get(asteroid)
+                                        .unwrap()
+                                        .clone();
                                     let channel_arc =
                                         (*asteroids_channel).clone();
                                     thread::spawn(move || {
@@ -274,9 +375,19 @@ impl<'a> System<'a> for GamePlaySystem {
                                             poly,
                                             channel_arc,
                                             None,
+                                            kind,
                                         );
                                     });
                                 }
+                                if let Some(elite) = elites.get(entity) {
+                                    elite_death(
+                                        elite,
+                                        entity,
+                                        Point2::new(position.x, position.y),
+                                        &mut insert_channel,
+                                        &preloaded_images,
+                                    );
+                                }
                                 if is_character {
                                     to_menu(
                                         &mut app_state,
@@ -294,9 +405,14 @@ impl<'a> System<'a> for GamePlaySystem {
                 entities.delete(entity).unwrap()
             }
         }
-        for (entity, iso, _collectable) in
-            (&entities, &mut isometries, &collectables).join()
+        for (entity, _) in
+            spatial_hash.query_radius(character_position, MAGNETO_RADIUS)
         {
+            let iso =
+                match (isometries.get_mut(entity), collectables.get(entity)) {
+                    (Some(iso), Some(_)) => iso,
+                    _ => continue,
+                };
             let collectable_position = iso.0.translation.vector;
             if (pos3d - collectable_position).norm() < MAGNETO_RADIUS {
                 let vel = 0.3 * (pos3d - collectable_position).normalize();
@@ -307,19 +423,15 @@ impl<'a> System<'a> for GamePlaySystem {
                 if let Some(coin) = coins.get(entity) {
                     let coin_id = rng.gen_range(1, 3);
                     let coins_add = upgrade_stats.coins_mult * coin.0;
-                    add_text(
-                        &entities,
-                        TextComponent {
-                            text: format!("+{}", coins_add).to_string(),
-                            color: (1.0, 1.0, 0.7, 1.0),
-                        },
-                        &lazy_update,
-                        Point2::new(
+                    insert_channel.single_write(InsertEvent::FloatingText {
+                        position: Point2::new(
                             collectable_position.x,
                             collectable_position.y,
                         ),
-                        Some(Lifetime::new(Duration::from_secs(1))),
-                    );
+                        text: format!("+{} coins", coins_add),
+                        color: (1.0, 1.0, 0.7, 1.0),
+                        font_size: DAMAGE_TEXT_SIZE,
+                    });
                     let coin_sound = if coin_id == 0 {
                         preloaded_sounds.coin
                     } else {
@@ -346,19 +458,15 @@ impl<'a> System<'a> for GamePlaySystem {
                     ));
                     let add_exp = upgrade_stats.exp_mult * exp.0;
                     progress.add_score(3 * add_exp);
-                    add_text(
-                        &entities,
-                        TextComponent {
-                            text: format!("+{}", add_exp).to_string(),
-                            color: (0.6, 3.0, 1.0, 1.0),
-                        },
-                        &lazy_update,
-                        Point2::new(
+                    insert_channel.single_write(InsertEvent::FloatingText {
+                        position: Point2::new(
                             collectable_position.x,
                             collectable_position.y,
                         ),
-                        Some(Lifetime::new(Duration::from_secs(1))),
-                    );
+                        text: format!("+{} exp", add_exp),
+                        color: (0.6, 1.0, 1.0, 1.0),
+                        font_size: DAMAGE_TEXT_SIZE,
+                    });
                     progress.add_exp(add_exp);
                 }
                 if let Some(health) = healths.get(entity) {
@@ -437,18 +545,41 @@ impl<'a> System<'a> for GamePlaySystem {
                     );
                     insert_channel.single_write(InsertEvent::DoubleExpAbility)
                 }
+                if bullet_time_collectables.get(entity).is_some() {
+                    add_text(
+                        &entities,
+                        TextComponent {
+                            text: "Bullet time".to_string(),
+                            color: (1.0, 1.0, 1.0, 1.0),
+                        },
+                        &lazy_update,
+                        Point2::new(
+                            collectable_position.x,
+                            collectable_position.y,
+                        ),
+                        Some(Lifetime::new(Duration::from_secs(1))),
+                    );
+                    time_scale
+                        .bullet_time(Duration::from_secs(BULLET_TIME_SEC));
+                }
                 entities.delete(entity).unwrap();
             }
         }
         let cnt = ships.count();
         let wave = &waves.0[current_wave.id];
-        let (add_cnt, const_spawn) = if cnt == 1 {
+        // next wave is spawned only after the warp jump
+        let (mut add_cnt, const_spawn) = if cnt == 1 && !warp.is_active() {
             current_wave.iteration += 1;
             (wave.ships_number - cnt + 1, true)
         } else {
             (0, false)
         };
-        if current_wave.iteration > wave.iterations {
+        let wave_changed = current_wave.iteration > wave.iterations;
+        if wave_changed {
+            add_cnt = 0;
+            let heading =
+                char_isometry.0.rotation * Vector3::new(0f32, -1f32, 0f32);
+            warp.start(Vector2::new(heading.x, heading.y));
             current_wave.iteration = 0;
             current_wave.id = (waves.0.len() - 1).min(current_wave.id + 1);
             add_screen_text(
@@ -462,38 +593,62 @@ impl<'a> System<'a> for GamePlaySystem {
                 Some(Lifetime::new(Duration::from_secs(1))),
             );
         }
-        let mut rng = thread_rng();
-        fn ships2insert(spawn_pos: Point2, enemy: EnemyKind) -> InsertEvent {
-            InsertEvent::Ship {
-                iso: Point3::new(spawn_pos.x, spawn_pos.y, 0f32),
-                light_shape: Geometry::Circle { radius: 1f32 },
-                spin: 0f32,
-                kind: enemy.ai_kind,
-                gun_kind: enemy.gun_kind,
-                ship_stats: enemy.ship_stats,
-                size: enemy.size,
-                image: enemy.image,
-                snake: enemy.snake,
-                rift: enemy.rift,
+        {
+            // arena is placed around the player when the warp is over
+            let arena = if warp.is_active() {
+                None
+            } else {
+                waves.0[current_wave.id].arena
+            };
+            if active_arena.arena != arena {
+                active_arena.center = character_position + world_offset.0;
+                active_arena.arena = arena;
+            }
+        }
+        // wells of the wave are placed once at fixed world positions
+        // and stay there until the wave changes
+        if wave_changed {
+            for (entity, _) in (&entities, &gravity_wells).join() {
+                entities.delete(entity).unwrap();
             }
+        } else if !warp.is_active() && (&gravity_wells).join().next().is_none()
+        {
+            for well in waves.0[current_wave.id].gravity_wells.iter() {
+                let spawn_pos = arena_spawn_position(
+                    character_position,
+                    &active_arena,
+                    &world_offset,
+                );
+                insert_channel.single_write(InsertEvent::GravityWell {
+                    position: spawn_pos,
+                    well: *well,
+                });
+            }
+        }
+        // biome enemy pool replaces the wave one
+        let distribution = if biome.enemies.len() > 0 {
+            &biome.enemies
+        } else {
+            &wave.distribution
         };
+        let mut rng = thread_rng();
         for _ in 0..add_cnt {
-            if wave.distribution.len() > 0 {
-                let spawn_pos = spawn_position(
+            if distribution.len() > 0 {
+                let spawn_pos = arena_spawn_position(
                     character_position,
-                    PLAYER_AREA,
-                    ACTIVE_AREA,
+                    &active_arena,
+                    &world_offset,
                 );
                 // TODO move from loop
                 let ships = &description.enemies;
-                let ship_id = wave
-                    .distribution
+                let ship_id = distribution
                     .choose_weighted(&mut rng, |item| item.1)
                     .unwrap()
                     .0;
-                insert_channel.single_write(ships2insert(
+                insert_channel.single_write(promote_elite(
                     spawn_pos,
                     ships[ship_id].clone(),
+                    wave.elite_chance,
                 ));
             }
         }
@@ -501,16 +656,17 @@ impl<'a> System<'a> for GamePlaySystem {
             for kind in wave.const_distribution.iter() {
                 // dbg!(kind);
                 for _ in 0..kind.1 {
-                    let spawn_pos = spawn_position(
+                    let spawn_pos = arena_spawn_position(
                         character_position,
-                        PLAYER_AREA,
-                        ACTIVE_AREA,
+                        &active_arena,
+                        &world_offset,
                     );
                     let ships = &description.enemies;
                     let ship_id = kind.0;
-                    insert_channel.single_write(ships2insert(
+                    insert_channel.single_write(promote_elite(
                         spawn_pos,
                         ships[ship_id].clone(),
+                        wave.elite_chance,
                     ));
                 }
             }
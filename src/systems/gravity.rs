@@ -0,0 +1,93 @@
+use super::*;
+use log::info;
+use nphysics2d::algebra::Force2;
+use nphysics2d::algebra::ForceType;
+
+// closer than that pull is not growing anymore
+const GRAVITY_MIN_DISTANCE: f32 = 1f32;
+
+/// pull every dynamic body towards gravity wells,
+/// black holes destroy what they swallow
+#[derive(Default)]
+pub struct GravitySystem;
+
+impl<'a> System<'a> for GravitySystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Isometry>,
+        ReadStorage<'a, PhysicsComponent>,
+        ReadStorage<'a, GravityWell>,
+        ReadStorage<'a, CharacterMarker>,
+        Write<'a, World<f32>>,
+        Write<'a, AppState>,
+        Write<'a, Progress>,
+        WriteExpect<'a, MacroGame>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("asteroids: gravity started");
+        let (
+            entities,
+            isometries,
+            physics,
+            gravity_wells,
+            character_markers,
+            mut world,
+            mut app_state,
+            mut progress,
+            mut macro_game,
+        ) = data;
+        let mut wells = vec![];
+        for (iso, well) in (&isometries, &gravity_wells).join() {
+            let position = iso.0.translation.vector;
+            wells.push((Vector2::new(position.x, position.y), *well));
+        }
+        if wells.is_empty() {
+            return;
+        }
+        for (entity, physics_component) in (&entities, &physics).join() {
+            let body = if let Some(body) =
+                world.rigid_body_mut(physics_component.body_handle)
+            {
+                body
+            } else {
+                continue;
+            };
+            if body.status() != BodyStatus::Dynamic {
+                continue;
+            }
+            let position = body.position().translation.vector;
+            let mut acceleration = Vector2::new(0f32, 0f32);
+            let mut swallowed = false;
+            for (well_position, well) in wells.iter() {
+                let diff = well_position - position;
+                let distance = diff.norm();
+                if let Some(horizon) = well.horizon {
+                    if distance < horizon {
+                        swallowed = true;
+                    }
+                }
+                if distance < well.radius && distance > EPS {
+                    let distance = distance.max(GRAVITY_MIN_DISTANCE);
+                    acceleration += well.strength / (distance * distance)
+                        * diff.normalize();
+                }
+            }
+            if swallowed {
+                if character_markers.get(entity).is_some() {
+                    to_menu(
+                        &mut app_state,
+                        &mut progress,
+                        &mut macro_game.score_table,
+                    );
+                }
+                entities.delete(entity).unwrap();
+                continue;
+            }
+            // acceleration, so light bullets bend as much as heavy ships
+            let force = Force2::new(acceleration, 0f32);
+            body.apply_force(0, &force, ForceType::AccelerationChange, true);
+        }
+        info!("asteroids: gravity ended");
+    }
+}
@@ -37,6 +37,10 @@ impl<'a> System<'a> for GUISystem {
         Write<'a, World<f32>>,
         Write<'a, EventChannel<InsertEvent>>,
         Write<'a, AppState>,
+        ReadStorage<'a, EnemyMarker>,
+        ReadStorage<'a, CollectableMarker>,
+        ReadStorage<'a, CameraTarget>,
+        Read<'a, HudSettings>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -72,6 +76,10 @@ impl<'a> System<'a> for GUISystem {
             mut world,
             mut insert_channel,
             mut app_state,
+            enemy_markers,
+            collectables,
+            camera_targets,
+            hud_settings,
         ) = data;
         let dims = viewport.dimensions();
         let (w, h) = (dims.0 as f32, dims.1 as f32);
@@ -227,6 +235,55 @@ impl<'a> System<'a> for GUISystem {
             with_projection: false,
         });
 
+        // radar under the FPS counter
+        if hud_settings.radar {
+            let top = h / 7.0;
+            let radius = h / 8.0;
+            // the move stick takes the bottom left corner
+            #[cfg(target_os = "android")]
+            let radius = radius.min((h - 2.0 * ctrl_size - top) / 2.0);
+            let radar_center = Point2::new(w / 7.0, top + radius);
+            ui.primitives.push(Primitive {
+                kind: PrimitiveKind::Picture(Picture {
+                    position: radar_center - Vector2::new(radius, radius),
+                    width: 2.0 * radius,
+                    height: 2.0 * radius,
+                    image: preloaded_images.circle,
+                }),
+                with_projection: false,
+            });
+            let translation =
+                isometries.get(character).unwrap().0.translation.vector;
+            let player = Point2::new(translation.x, translation.y);
+            let blip_size = RADAR_BLIP_SIZE * h;
+            let mut dots = vec![(player, pallete.white_color)];
+            for (position, blip) in blips(
+                player,
+                hud_settings.radar_range,
+                &isometries,
+                &enemy_markers,
+                &collectables,
+                &camera_targets,
+            ) {
+                let (r, g, b) = blip.color();
+                dots.push((position, Point3::new(r, g, b)));
+            }
+            for (position, color) in dots {
+                let offset =
+                    radius / hud_settings.radar_range * (position - player);
+                ui.primitives.push(Primitive {
+                    kind: PrimitiveKind::Rectangle(Rectangle {
+                        position: radar_center + offset
+                            - Vector2::new(blip_size / 2.0, blip_size / 2.0),
+                        width: blip_size,
+                        height: blip_size,
+                        color,
+                    }),
+                    with_projection: false,
+                });
+            }
+        }
+
         let side_bullets_cnt = side_bullet_abilities.count();
         let double_coins_cnt = double_coins_abilities.count();
         let double_exp_cnt = double_exp_abilities.count();
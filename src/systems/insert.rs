@@ -1,5 +1,7 @@
 use super::*;
-use log::info;
+use log::{info, warn};
+use nphysics2d::joint::RevoluteConstraint;
+use std::collections::VecDeque;
 
 // pub fn insert_character(
 //     entities: &Entities,
@@ -105,13 +107,70 @@ pub fn add_screen_text(
     lazy_update.insert(entity, Position2D(Point2::new(position.x, position.y)));
 }
 
+fn pickup_light() -> Light {
+    Light::new((1.0, 0.9, 0.5), PICKUP_LIGHT_RADIUS, 0.5)
+}
+
+/// Trail of a projectile with the length and color of the shooter trail
+/// from desc.ron, `default` is used for shooters without one
+fn projectile_trail(
+    trails: &ReadStorage<Trail>,
+    owner: specs::Entity,
+    default: TrailDesc,
+) -> Trail {
+    let desc = match trails.get(owner) {
+        Some(trail) => TrailDesc {
+            length: trail.desc.length,
+            color: trail.desc.color,
+            ..default
+        },
+        None => default,
+    };
+    Trail::new(desc)
+}
+
+/// Floating text entities are not deleted when their Lifetime ends,
+/// expired ones are reused for new texts
+#[derive(Default)]
+pub struct FloatingTextPool {
+    // the least recently used first
+    entities: VecDeque<specs::Entity>,
+}
+
+impl FloatingTextPool {
+    /// The oldest entity if it's expired or the pool is full, a new one
+    /// otherwise
+    pub fn acquire(
+        &mut self,
+        entities: &Entities,
+        lifetimes: &ReadStorage<Lifetime>,
+    ) -> specs::Entity {
+        self.entities.retain(|entity| entities.is_alive(*entity));
+        let reuse = self.entities.front().map_or(false, |oldest| {
+            self.entities.len() >= FLOATING_TEXT_MAX
+                || lifetimes.get(*oldest).map_or(false, Lifetime::delete)
+        });
+        let entity = if reuse {
+            self.entities.pop_front().unwrap()
+        } else {
+            entities.create()
+        };
+        self.entities.push_back(entity);
+        entity
+    }
+}
+
 pub struct InsertSystem {
     reader: ReaderId<InsertEvent>,
+    floating_texts: FloatingTextPool,
 }
 
 impl InsertSystem {
     pub fn new(reader: ReaderId<InsertEvent>) -> Self {
-        InsertSystem { reader: reader }
+        InsertSystem {
+            reader: reader,
+            floating_texts: FloatingTextPool::default(),
+        }
     }
 }
 
@@ -121,15 +180,21 @@ impl<'a> System<'a> for InsertSystem {
         WriteStorage<'a, PhysicsComponent>,
         WriteStorage<'a, ShotGun>,
         ReadStorage<'a, CharacterMarker>,
-        ReadExpect<'a, ThreadPin<red::GL>>,
         WriteExpect<'a, PreloadedImages>,
         Write<'a, World<f32>>,
         Write<'a, BodiesMap>,
         Write<'a, Progress>,
         Read<'a, EventChannel<InsertEvent>>,
-        WriteExpect<'a, Canvas>,
+        WriteExpect<'a, Camera>,
         Read<'a, LazyUpdate>,
         Write<'a, UpgradesStats>,
+        ReadExpect<'a, Factions>,
+        Write<'a, ChainJoints>,
+        ReadExpect<'a, ParticleEffects>,
+        Read<'a, HudSettings>,
+        ReadExpect<'a, BackgroundLayers>,
+        ReadStorage<'a, Lifetime>,
+        ReadStorage<'a, Trail>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -138,15 +203,21 @@ impl<'a> System<'a> for InsertSystem {
             mut physics,
             mut shotguns,
             character_markers,
-            gl,
             preloaded_images,
             mut world,
             mut bodies_map,
             mut progress,
             insert_channel,
-            mut canvas,
+            mut camera,
             lazy_update,
             mut upgrades_stats,
+            factions,
+            mut chain_joints,
+            particle_effects,
+            hud_settings,
+            background_layers,
+            lifetimes,
+            trails,
         ) = data;
         let mut rng = thread_rng();
         info!("asteroids: started insert system");
@@ -156,6 +227,7 @@ impl<'a> System<'a> for InsertSystem {
                     gun_kind,
                     ship_stats,
                     image,
+                    trail,
                 } => {
                     *progress = Progress::default();
                     let char_size = 0.5f32;
@@ -188,19 +260,15 @@ impl<'a> System<'a> for InsertSystem {
                     lazy_update.insert(character, character_shape);
                     lazy_update.insert(character, Size(char_size));
                     lazy_update.insert(character, *ship_stats);
+                    if let Some(trail) = trail {
+                        lazy_update.insert(character, Trail::engine(*trail));
+                    }
                     let character_physics_shape =
                         ncollide2d::shape::Ball::new(char_size);
 
-                    let mut character_collision_groups = CollisionGroups::new();
-                    character_collision_groups
-                        .set_membership(&[CollisionId::PlayerShip as usize]);
-                    character_collision_groups.set_whitelist(&[
-                        CollisionId::Asteroid as usize,
-                        CollisionId::EnemyBullet as usize,
-                        CollisionId::EnemyShip as usize,
-                    ]);
-                    character_collision_groups
-                        .set_blacklist(&[CollisionId::PlayerBullet as usize]);
+                    lazy_update.insert(character, PLAYER_FACTION);
+                    let character_collision_groups =
+                        factions.ship_collision_groups(PLAYER_FACTION);
 
                     PhysicsComponent::safe_insert(
                         &mut physics,
@@ -241,6 +309,7 @@ impl<'a> System<'a> for InsertSystem {
                     velocity,
                     polygon,
                     spin,
+                    kind,
                 } => {
                     let mut polygon = polygon.clone();
                     let center = polygon.center();
@@ -249,9 +318,8 @@ impl<'a> System<'a> for InsertSystem {
                     let iso =
                         Point3::new(iso.x + center.x, iso.y + center.y, 0.0);
                     let physics_polygon = if let Some(physics_polygon) =
-                        ncollide2d::shape::ConvexPolygon::try_from_points(
-                            &polygon.points(),
-                        ) {
+                        asteroid_shape(&polygon)
+                    {
                         physics_polygon
                     } else {
                         // TODO: looks like BUG!
@@ -259,14 +327,16 @@ impl<'a> System<'a> for InsertSystem {
                         break;
                         // panic!();
                     };
-                    let triangulation =
-                        polygon.clone().into_rounded(5).triangulate();
-                    let geom_data = GeometryData::new(
-                        &gl,
+                    // rounding the outline would fill craters back
+                    let triangulation = if polygon.is_carved() {
+                        polygon.triangulate()
+                    } else {
+                        polygon.clone().into_rounded(5).triangulate()
+                    };
+                    let mesh = Mesh::new(
                         &triangulation.points,
                         &triangulation.indicies,
-                    )
-                    .unwrap();
+                    );
                     let asteroid = entities.create();
                     lazy_update.insert(asteroid, light_shape.clone());
                     lazy_update
@@ -285,9 +355,10 @@ impl<'a> System<'a> for InsertSystem {
                     );
                     lazy_update.insert(asteroid, polygon);
                     lazy_update.insert(asteroid, AsteroidMarker::default());
+                    lazy_update.insert(asteroid, kind.clone());
                     lazy_update.insert(asteroid, Spin(*spin));
                     lazy_update.insert(asteroid, Size(1f32));
-                    lazy_update.insert(asteroid, ThreadPin::new(geom_data));
+                    lazy_update.insert(asteroid, mesh);
 
                     // let asteroid = entities
                     //     .build_entity()
@@ -302,20 +373,12 @@ impl<'a> System<'a> for InsertSystem {
                     //     .with(Size(1f32), &mut sizes)
                     //     .build();
 
-                    let mut asteroid_collision_groups = CollisionGroups::new();
-                    asteroid_collision_groups
-                        .set_membership(&[CollisionId::Asteroid as usize]);
-                    asteroid_collision_groups.set_whitelist(&[
-                        CollisionId::Asteroid as usize,
-                        CollisionId::EnemyShip as usize,
-                        CollisionId::PlayerShip as usize,
-                        CollisionId::PlayerBullet as usize,
-                        CollisionId::EnemyBullet as usize,
-                    ]);
+                    let asteroid_collision_groups =
+                        factions.asteroid_collision_groups();
                     PhysicsComponent::safe_insert(
                         &mut physics,
                         asteroid,
-                        ShapeHandle::new(physics_polygon),
+                        physics_polygon,
                         Isometry2::new(Vector2::new(iso.x, iso.y), iso.z),
                         *velocity,
                         BodyStatus::Dynamic,
@@ -336,6 +399,11 @@ impl<'a> System<'a> for InsertSystem {
                     image,
                     snake,
                     rift,
+                    faction,
+                    telegraph,
+                    elite,
+                    trail,
+                    boss,
                 } => {
                     let mut kind = kind.clone();
                     let num =
@@ -346,20 +414,18 @@ impl<'a> System<'a> for InsertSystem {
                         let enemy_shape = Geometry::Circle { radius: size };
                         let enemy_physics_shape =
                             ncollide2d::shape::Ball::new(size);
-                        let mut enemy_collision_groups = CollisionGroups::new();
-                        enemy_collision_groups
-                            .set_membership(&[CollisionId::EnemyShip as usize]);
-                        enemy_collision_groups.set_whitelist(&[
-                            CollisionId::Asteroid as usize,
-                            CollisionId::EnemyShip as usize,
-                            CollisionId::PlayerShip as usize,
-                            CollisionId::PlayerBullet as usize,
-                        ]);
-                        enemy_collision_groups.set_blacklist(&[
-                            CollisionId::EnemyBullet as usize,
-                        ]);
+                        let enemy_collision_groups =
+                            factions.ship_collision_groups(*faction);
                         let enemy = entities.create();
-
+                        // links of a snake follow the head, one plume is enough
+                        if let (Some(trail), 0) = (trail, i) {
+                            lazy_update.insert(enemy, Trail::engine(*trail));
+                        }
+                        if *boss && i == 0 {
+                            lazy_update.insert(enemy, CameraTarget);
+                            camera.cinematic(Point2::new(iso.x, iso.y));
+                            camera.shake(BOSS_WOBBLE);
+                        }
                         match gun_kind {
                             GunKind::ShotGun(shotgun) => {
                                 let side_num = 3usize;
@@ -369,6 +435,14 @@ impl<'a> System<'a> for InsertSystem {
                             }
                             GunKind::MultyLazer(multy_lazer) => {
                                 lazy_update.insert(enemy, multy_lazer.clone());
+                                lazy_update.insert(
+                                    enemy,
+                                    Light::new(
+                                        (1.0, 0.2, 0.2),
+                                        LAZER_LIGHT_RADIUS,
+                                        0.6,
+                                    ),
+                                );
                             }
                             GunKind::Cannon(cannon) => {
                                 lazy_update.insert(enemy, cannon.clone());
@@ -393,7 +467,11 @@ impl<'a> System<'a> for InsertSystem {
                                 _ => (),
                             }
                         }
-                        let iso = Point3::new(iso.x + i as f32, iso.y, iso.z);
+                        let iso = Point3::new(
+                            iso.x + i as f32 * CHAIN_LINK_GAP,
+                            iso.y,
+                            iso.z,
+                        );
                         lazy_update
                             .insert(enemy, Isometry::new(iso.x, iso.y, iso.z));
                         lazy_update.insert(enemy, Velocity::new(0f32, 0f32));
@@ -412,7 +490,19 @@ impl<'a> System<'a> for InsertSystem {
                         lazy_update.insert(enemy, enemy_shape);
                         lazy_update.insert(enemy, Size(size));
                         lazy_update.insert(enemy, DamageFlash(0f32));
-                        PhysicsComponent::safe_insert(
+                        lazy_update.insert(enemy, *faction);
+                        if ship_stats.max_shield > 0 {
+                            lazy_update
+                                .insert(enemy, Shield(ship_stats.max_shield));
+                        }
+                        if let Some(elite) = elite {
+                            lazy_update.insert(enemy, elite.clone());
+                        }
+                        if let Some(duration) = telegraph {
+                            lazy_update
+                                .insert(enemy, AttackTelegraph::new(*duration));
+                        }
+                        let enemy_physics = PhysicsComponent::safe_insert(
                             &mut physics,
                             enemy,
                             ShapeHandle::new(enemy_physics_shape),
@@ -425,21 +515,45 @@ impl<'a> System<'a> for InsertSystem {
                             0.5f32,
                         );
                         // snake thing
-                        if let Some(last_entity) = last_entity {
+                        if let Some((last_entity, last_body)) = last_entity {
                             if snake.is_some() {
                                 lazy_update.insert(
                                     enemy,
                                     Chain {
                                         follow: last_entity,
                                     },
-                                )
+                                );
+                                // links are pinned halfway between each other
+                                let anchor = Rotation2::new(-iso.z)
+                                    * Vector2::new(CHAIN_LINK_GAP / 2.0, 0f32);
+                                let leader_part = world
+                                    .rigid_body(last_body)
+                                    .unwrap()
+                                    .part_handle();
+                                let follower_part = world
+                                    .rigid_body(enemy_physics.body_handle)
+                                    .unwrap()
+                                    .part_handle();
+                                let joint = RevoluteConstraint::new(
+                                    leader_part,
+                                    follower_part,
+                                    Point2::new(anchor.x, anchor.y),
+                                    Point2::new(-anchor.x, -anchor.y),
+                                );
+                                chain_joints.0.push(ChainJoint {
+                                    handle: world.add_constraint(joint),
+                                    leader: last_entity,
+                                    follower: enemy,
+                                    stiffness: CHAIN_STIFFNESS,
+                                    max_angle: CHAIN_MAX_ANGLE,
+                                });
                             }
                         }
                         if let Some(rift) = rift {
                             lazy_update.insert(enemy, rift.clone());
                             // lazy_update.insert(enemy, Aim(last_entity))
                         }
-                        last_entity = Some(enemy);
+                        last_entity = Some((enemy, enemy_physics.body_handle));
                         // with light
                         //     {
                         // let _light = entities
@@ -478,6 +592,11 @@ impl<'a> System<'a> for InsertSystem {
                     lazy_update.insert(bullet, Projectile { owner: *owner });
                     lazy_update.insert(bullet, Lifetime::new(*lifetime));
                     lazy_update.insert(bullet, Size(*size));
+                    lazy_update.insert(bullet, kind.faction());
+                    lazy_update.insert(
+                        bullet,
+                        projectile_trail(&trails, *owner, BULLET_TRAIL),
+                    );
                     if let Some(reflection) = reflection {
                         lazy_update.insert(bullet, *reflection);
                     }
@@ -499,7 +618,8 @@ impl<'a> System<'a> for InsertSystem {
                     }
                     // let bullet = bullet
                     //     .build();
-                    let bullet_collision_groups = get_collision_groups(*kind);
+                    let bullet_collision_groups =
+                        get_collision_groups(*kind, &factions);
                     let ball = ncollide2d::shape::Ball::new(*size);
                     let bullet_physics_component =
                         PhysicsComponent::safe_insert(
@@ -537,10 +657,21 @@ impl<'a> System<'a> for InsertSystem {
                     lazy_update.insert(entity, Velocity::new(0f32, 0f32));
                     lazy_update.insert(entity, *rocket_image);
                     lazy_update.insert(entity, Spin::default());
-                    lazy_update.insert(entity, Rocket(Instant::now()));
+                    lazy_update
+                        .insert(entity, Rocket(TRACKER.lock().unwrap().now()));
                     lazy_update.insert(entity, Projectile { owner: *owner });
                     lazy_update.insert(entity, Size(r));
-                    let bullet_collision_groups = get_collision_groups(*kind);
+                    lazy_update.insert(entity, kind.faction());
+                    lazy_update.insert(
+                        entity,
+                        Light::new((1.0, 0.6, 0.2), ROCKET_LIGHT_RADIUS, 0.8),
+                    );
+                    lazy_update.insert(
+                        entity,
+                        projectile_trail(&trails, *owner, ROCKET_TRAIL),
+                    );
+                    let bullet_collision_groups =
+                        get_collision_groups(*kind, &factions);
                     let ball = ncollide2d::shape::Ball::new(r);
                     let bullet_physics_component =
                         PhysicsComponent::safe_insert(
@@ -566,6 +697,7 @@ impl<'a> System<'a> for InsertSystem {
                     lazy_update.insert(entity, Coin(*value));
                     lazy_update.insert(entity, iso);
                     lazy_update.insert(entity, Size(0.25));
+                    lazy_update.insert(entity, pickup_light());
                     lazy_update.insert(entity, preloaded_images.coin);
                     lazy_update.insert(
                         entity,
@@ -585,6 +717,7 @@ impl<'a> System<'a> for InsertSystem {
                     );
                     lazy_update.insert(entity, iso);
                     lazy_update.insert(entity, Size(0.5));
+                    lazy_update.insert(entity, pickup_light());
                     lazy_update
                         .insert(entity, preloaded_images.side_bullet_ability);
                 }
@@ -611,6 +744,7 @@ impl<'a> System<'a> for InsertSystem {
                     );
                     lazy_update.insert(entity, iso);
                     lazy_update.insert(entity, Size(0.5));
+                    lazy_update.insert(entity, pickup_light());
                     lazy_update.insert(entity, preloaded_images.double_coin);
                 }
                 InsertEvent::DoubleCoinsAbility => {
@@ -637,9 +771,26 @@ impl<'a> System<'a> for InsertSystem {
                     );
                     lazy_update.insert(coin_entity, iso);
                     lazy_update.insert(coin_entity, Size(0.5));
+                    lazy_update.insert(coin_entity, pickup_light());
                     lazy_update
                         .insert(coin_entity, preloaded_images.double_exp);
                 }
+                InsertEvent::BulletTimeCollectable { position } => {
+                    let iso = Isometry::new(position.x, position.y, 0f32);
+                    let entity = entities.create();
+                    lazy_update.insert(entity, CollectableMarker);
+                    lazy_update.insert(entity, BulletTimeCollectable);
+                    lazy_update.insert(
+                        entity,
+                        Lifetime::new(Duration::from_secs(
+                            COLLECTABLE_BULLET_TIME_SEC,
+                        )),
+                    );
+                    lazy_update.insert(entity, iso);
+                    lazy_update.insert(entity, Size(0.5));
+                    lazy_update.insert(entity, pickup_light());
+                    lazy_update.insert(entity, preloaded_images.bullet_time);
+                }
                 InsertEvent::DoubleExpAbility => {
                     upgrades_stats.exp_mult *= 2;
                     let entity = entities.create();
@@ -668,6 +819,7 @@ impl<'a> System<'a> for InsertSystem {
                     );
                     lazy_update.insert(reflect_bullet_entity, iso);
                     lazy_update.insert(reflect_bullet_entity, Size(0.5));
+                    lazy_update.insert(reflect_bullet_entity, pickup_light());
                     lazy_update.insert(
                         reflect_bullet_entity,
                         preloaded_images.attack_speed_upgrade,
@@ -703,6 +855,7 @@ impl<'a> System<'a> for InsertSystem {
                     lazy_update.insert(coin_entity, Health(*value));
                     lazy_update.insert(coin_entity, iso);
                     lazy_update.insert(coin_entity, Size(0.25));
+                    lazy_update.insert(coin_entity, pickup_light());
                     lazy_update.insert(coin_entity, preloaded_images.health);
                     lazy_update.insert(
                         coin_entity,
@@ -716,12 +869,12 @@ impl<'a> System<'a> for InsertSystem {
                     lazy_update.insert(exp_entity, Exp(*value));
                     lazy_update.insert(exp_entity, iso);
                     lazy_update.insert(exp_entity, Size(0.25));
+                    lazy_update.insert(exp_entity, pickup_light());
                     lazy_update.insert(exp_entity, preloaded_images.exp);
                 }
                 InsertEvent::Explosion {
                     position,
-                    num,
-                    lifetime,
+                    emitter,
                     with_animation,
                 } => {
                     let iso = Isometry::new(position.x, position.y, 0f32);
@@ -740,20 +893,30 @@ impl<'a> System<'a> for InsertSystem {
                         );
                         lazy_update.insert(animation_entity, Size(size * 2.0));
                     }
-                    // particles of explosion
-                    let explosion_particles = ThreadPin::new(
-                        ParticlesData::Explosion(Explosion::new(
-                            &gl,
-                            *position,
-                            *num,
-                            Some(*lifetime),
-                        )),
+                    let light = entities.create();
+                    lazy_update.insert(light, iso);
+                    lazy_update.insert(
+                        light,
+                        Light::new(
+                            (1.0, 0.7, 0.3),
+                            EXPLOSION_LIGHT_RADIUS,
+                            1.0,
+                        ),
                     );
-                    let explosion_particles_entity = entities.create();
                     lazy_update.insert(
-                        explosion_particles_entity,
-                        explosion_particles,
+                        light,
+                        Lifetime::new(Duration::from_millis(
+                            EXPLOSION_LIGHT_MS,
+                        )),
                     );
+                    // particles of explosion
+                    match particle_effects.emitter(emitter, *position) {
+                        Some(emitter) => {
+                            let emitter_entity = entities.create();
+                            lazy_update.insert(emitter_entity, emitter);
+                        }
+                        None => warn!("unknown particle emitter {}", emitter),
+                    }
                 }
                 InsertEvent::Animation {
                     animation,
@@ -769,61 +932,104 @@ impl<'a> System<'a> for InsertSystem {
                         .insert(animation_entity, Lifetime::new(*lifetime));
                     lazy_update.insert(animation_entity, Size(*size));
                 }
-                InsertEvent::Nebula { iso } => {
-                    let mut rng = thread_rng();
-                    let z = rng.gen_range(-120f32, -80f32);
-                    let nebulas_num = preloaded_images.nebulas.len();
-                    let nebula_id = rng.gen_range(0, nebulas_num);
-                    let nebula = entities.create();
+                InsertEvent::Background { layer, iso } => {
+                    let background = &background_layers.layers[*layer];
+                    let entity = entities.create();
+                    let z = background.z(&mut rng);
                     lazy_update.insert(
-                        nebula,
+                        entity,
                         Isometry::new3d(iso.x, iso.y, z, iso.z),
                     );
-                    lazy_update
-                        .insert(nebula, preloaded_images.nebulas[nebula_id]);
-                    lazy_update.insert(nebula, NebulaMarker::default());
-                    lazy_update.insert(nebula, Size(60f32));
+                    match &background.sprite {
+                        LayerSprite::Images(images) => {
+                            let image = *images.choose(&mut rng).unwrap();
+                            lazy_update.insert(entity, image);
+                        }
+                        LayerSprite::Animation(animation) => {
+                            lazy_update.insert(entity, animation.clone());
+                        }
+                    }
+                    lazy_update.insert(entity, BackgroundMarker(*layer));
+                    lazy_update.insert(entity, Size(background.desc.size));
                 }
-                InsertEvent::Stars { iso } => {
-                    let mut rng = thread_rng();
-                    let z = rng.gen_range(-180f32, -140f32);
-                    let stars_num = preloaded_images.stars.len();
-                    let stars_id = rng.gen_range(0, stars_num);
-                    let stars = entities.create();
-                    lazy_update
-                        .insert(stars, Isometry::new3d(iso.x, iso.y, z, iso.z));
-                    lazy_update.insert(stars, preloaded_images.stars[stars_id]);
-                    lazy_update.insert(stars, StarsMarker);
-                    lazy_update.insert(stars, Size(30f32));
+                InsertEvent::FloatingText {
+                    position,
+                    text,
+                    color,
+                    font_size,
+                } => {
+                    if !hud_settings.floating_text {
+                        continue;
+                    }
+                    let entity =
+                        self.floating_texts.acquire(&entities, &lifetimes);
+                    lazy_update.insert(
+                        entity,
+                        TextComponent {
+                            text: text.clone(),
+                            color: *color,
+                        },
+                    );
+                    lazy_update.insert(
+                        entity,
+                        Isometry::new(position.x, position.y, 0f32),
+                    );
+                    lazy_update.insert(
+                        entity,
+                        Lifetime::new(Duration::from_millis(FLOATING_TEXT_MS)),
+                    );
+                    lazy_update.insert(
+                        entity,
+                        FloatingText {
+                            velocity: Vector2::new(0.0, -FLOATING_TEXT_RISE),
+                            font_size: *font_size,
+                        },
+                    );
+                }
+                InsertEvent::Wobble(profile) => {
+                    if !camera.shake(profile) {
+                        warn!("Unknown camera shake {}", profile);
+                    }
                 }
-                InsertEvent::Fog { iso } => {
-                    let mut rng = thread_rng();
-                    let z = rng.gen_range(-40f32, -20f32);
+                InsertEvent::GravityWell { position, well } => {
                     let entity = entities.create();
+                    let image = if well.horizon.is_some() {
+                        // black hole
+                        lazy_update
+                            .insert(entity, Tint((0f32, 0f32, 0f32, 1f32)));
+                        preloaded_images.circle
+                    } else {
+                        *preloaded_images.planets.choose(&mut rng).unwrap()
+                    };
                     lazy_update.insert(
                         entity,
-                        Isometry::new3d(iso.x, iso.y, z, iso.z),
+                        Isometry::new(position.x, position.y, 0f32),
                     );
-                    lazy_update.insert(entity, preloaded_images.fog);
-                    lazy_update.insert(entity, FogMarker);
-                    lazy_update.insert(entity, Size(35f32));
+                    lazy_update.insert(entity, *well);
+                    lazy_update.insert(entity, Size(well.size));
+                    lazy_update.insert(entity, image);
+                    if well.horizon.is_none() {
+                        lazy_update.insert(
+                            entity,
+                            Light::new(
+                                (0.6, 0.7, 1.0),
+                                PLANET_LIGHT_SCALE * well.size,
+                                0.4,
+                            ),
+                        );
+                    }
                 }
-                InsertEvent::Planet { iso } => {
-                    let mut rng = thread_rng();
-                    let z = -45.0;
-                    let planets_num = preloaded_images.planets.len();
-                    let planet_id = rng.gen_range(0, planets_num);
-                    let nebula = entities.create();
+                InsertEvent::Blast { position, blast } => {
+                    let entity = entities.create();
                     lazy_update.insert(
-                        nebula,
-                        Isometry::new3d(iso.x, iso.y, z, iso.z),
+                        entity,
+                        Isometry::new(position.x, position.y, 0f32),
                     );
+                    lazy_update.insert(entity, *blast);
+                    // explodes on the next frame in gameplay system
                     lazy_update
-                        .insert(nebula, preloaded_images.planets[planet_id]);
-                    lazy_update.insert(nebula, PlanetMarker::default());
-                    lazy_update.insert(nebula, Size(25f32));
+                        .insert(entity, Lifetime::new(Duration::from_secs(0)));
                 }
-                InsertEvent::Wobble(wobble) => canvas.add_wobble(*wobble),
             }
         }
         info!("asteroids: ended insert system");
@@ -1,6 +1,6 @@
 use super::rendering::*;
 use super::*;
-use gfx_h::MenuParticles;
+use gfx_h::PostProcessSettings;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 
@@ -8,7 +8,6 @@ pub struct MenuRenderingSystem;
 
 impl<'a> System<'a> for MenuRenderingSystem {
     type SystemData = (
-        ReadExpect<'a, ThreadPin<red::GL>>,
         WriteExpect<'a, Canvas>,
         ReadExpect<'a, red::Viewport>,
         Write<'a, EventChannel<Primitive>>,
@@ -16,7 +15,6 @@ impl<'a> System<'a> for MenuRenderingSystem {
         Write<'a, UIState>,
         Write<'a, EventChannel<InsertEvent>>,
         WriteExpect<'a, PreloadedImages>,
-        WriteExpect<'a, ThreadPin<MenuParticles>>,
         Read<'a, Mouse>,
         Write<'a, AppState>,
         ReadExpect<'a, Description>,
@@ -26,11 +24,12 @@ impl<'a> System<'a> for MenuRenderingSystem {
         WriteExpect<'a, MacroGame>,
         Write<'a, EventChannel<Sound>>,
         ReadExpect<'a, PreloadedSounds>,
+        Write<'a, PostProcessSettings>,
+        Write<'a, HudSettings>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
-            gl,
             canvas,
             viewport,
             mut primitives_channel,
@@ -38,7 +37,6 @@ impl<'a> System<'a> for MenuRenderingSystem {
             mut ui_state,
             mut insert_channel,
             preloaded_images,
-            mut menu_particles,
             mouse,
             mut app_state,
             // text_data
@@ -49,10 +47,9 @@ impl<'a> System<'a> for MenuRenderingSystem {
             mut macro_game,
             mut sounds_channel,
             preloaded_sounds,
+            mut post_process_settings,
+            mut hud_settings,
         ) = data;
-        let mut frame = red::Frame::new(&gl);
-        // frame.set_clear_color(0.0, 0.0, 0.0, 1.0);
-        // frame.clear_color();
         let dims = viewport.dimensions();
         let (w, h) = (dims.0 as f32, dims.1 as f32);
         // return;
@@ -282,6 +279,81 @@ impl<'a> System<'a> for MenuRenderingSystem {
                 _ => (),
             }
         }
+        // post processing and hud toggles
+        {
+            let toggles = [
+                (Widgets::BloomToggle, "Bloom", post_process_settings.bloom),
+                (
+                    Widgets::AberrationToggle,
+                    "Aberration",
+                    post_process_settings.chromatic_aberration,
+                ),
+                (
+                    Widgets::VignetteToggle,
+                    "Vignette",
+                    post_process_settings.vignette,
+                ),
+                (
+                    Widgets::WarpDistortionToggle,
+                    "Warp",
+                    post_process_settings.warp_distortion,
+                ),
+                (
+                    Widgets::FloatingTextToggle,
+                    "Floating text",
+                    hud_settings.floating_text,
+                ),
+            ];
+            let toggle_w = w / 10.0;
+            let toggle_h = toggle_w / 4.0;
+            for (i, (widget, name, enabled)) in toggles.iter().enumerate() {
+                let button = Button::new(
+                    Point2::new(w / 40.0, h / 2.0 + i as f32 * 1.5 * toggle_h),
+                    toggle_w,
+                    toggle_h,
+                    None,
+                    false,
+                    Some(preloaded_images.upg_bar),
+                    format!(
+                        "{}: {}",
+                        name,
+                        if *enabled { "on" } else { "off" }
+                    ),
+                    *widget as usize,
+                    Some(Sound(
+                        preloaded_sounds.hover,
+                        Point2::new(0f32, 0f32),
+                    )),
+                    Some(Sound(
+                        preloaded_sounds.click,
+                        Point2::new(0f32, 0f32),
+                    )),
+                );
+                if button.place_and_check(&mut ui, &*mouse) {
+                    let settings = &mut *post_process_settings;
+                    match widget {
+                        Widgets::BloomToggle => {
+                            settings.bloom = !settings.bloom
+                        }
+                        Widgets::AberrationToggle => {
+                            settings.chromatic_aberration =
+                                !settings.chromatic_aberration
+                        }
+                        Widgets::VignetteToggle => {
+                            settings.vignette = !settings.vignette
+                        }
+                        Widgets::WarpDistortionToggle => {
+                            settings.warp_distortion = !settings.warp_distortion
+                        }
+                        Widgets::FloatingTextToggle => {
+                            hud_settings.floating_text =
+                                !hud_settings.floating_text
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
         let button_w = w / 6.0;
         let button_h = button_w;
         let score_table_button = Button::new(
@@ -323,6 +395,7 @@ impl<'a> System<'a> for MenuRenderingSystem {
                     gun_kind: gun.clone(),
                     ship_stats: description.player_ships[ship].ship_stats,
                     image: ship_images[ship],
+                    trail: description.player_ships[ship].trail,
                 });
                 *avaliable_upgrades = get_avaliable_cards(
                     &upgrade_cards_raw,
@@ -7,7 +7,7 @@ use rand::prelude::*;
 use std::time::{Duration, Instant};
 
 use ncollide2d::query::Ray;
-use ncollide2d::shape::ShapeHandle;
+use ncollide2d::shape::{Compound, ConvexPolygon, ShapeHandle};
 use ncollide2d::world::CollisionGroups;
 use ncollide2d::world::CollisionObjectHandle;
 use nphysics2d::object::{Body, BodyHandle, BodyStatus};
@@ -19,49 +19,74 @@ use specs::Join;
 
 use crate::gui::{Primitive, PrimitiveKind, Text, UI};
 use components::*;
-use geometry::{generate_convex_polygon, Polygon, TriangulateFromCenter, EPS};
-use gfx_h::{iso3_iso2, Explosion, GeometryData, ParticlesData};
-use physics::CollisionId;
-use sound::{MusicData, PreloadedSounds, SoundData, EFFECT_MAX_VOLUME};
+use geometry::{
+    circle_polygon, generate_convex_polygon, generate_polygon, Polygon,
+    TriangulateFromCenter, EPS,
+};
+use gfx_h::{
+    iso3_iso2, visible_half_extents, Camera, Mesh, ParticleEffects,
+    ParticleField,
+};
+use sound::{
+    set_pitch, MusicData, PreloadedSounds, SoundData, EFFECT_MAX_VOLUME,
+};
 
 mod ai;
+mod arena;
+mod background;
+mod camera;
 mod collision;
 mod common_respawn;
 mod control;
 mod deadscreen;
 mod destroy_sync;
 mod gameplay;
+mod gravity;
 mod gui_system;
 mod insert;
 mod kinematic;
 mod menu_rendering_system;
+mod present;
 mod rendering;
 mod score_table;
 mod sound_system;
+mod spatial_hash;
+mod time_dilation;
+mod trails;
 mod ui_controlling;
 mod upgrade_ui;
 mod upgrade_control;
 mod upgrader;
+mod warp;
 
 pub use ai::*;
+pub use arena::*;
+pub use background::*;
+pub use camera::*;
 pub use collision::*;
 pub use common_respawn::*;
 pub use control::*;
 pub use deadscreen::*;
 pub use destroy_sync::*;
 pub use gameplay::*;
+pub use gravity::*;
 pub use gui_system::*;
 pub use insert::*;
 pub use kinematic::*;
 pub use menu_rendering_system::*;
 pub use physics_system::*;
+pub use present::*;
 pub use rendering::*;
 pub use score_table::*;
 pub use sound_system::*;
+pub use spatial_hash::*;
+pub use time_dilation::*;
+pub use trails::*;
 pub use ui_controlling::*;
 pub use upgrade_ui::*;
 pub use upgrade_control::*;
 pub use upgrader::*;
+pub use warp::*;
 
 const DAMPING_FACTOR: f32 = 1.0f32; // TODO is it used only for ships. no? separate then
 const VELOCITY_MAX: f32 = 1f32;
@@ -73,15 +98,28 @@ const PLAYER_AREA: f32 = 20f32;
 const ACTIVE_AREA: f32 = 40f32;
 const ENEMY_ACTIVE_AREA: f32 = 21f32;
 // the same for NEBULAS
-#[cfg(debug_assertions)]
-const ASTEROIDS_MIN_NUMBER: usize = 20;
-#[cfg(not(debug_assertions))]
-const ASTEROIDS_MIN_NUMBER: usize = 20;
+// ships and asteroids further than that are hidden in fog biomes
+const FOG_VISIBILITY: f32 = 10f32;
+// distance between snake links
+const CHAIN_LINK_GAP: f32 = 1f32;
+// snake links bend like a spine, not like a rope
+const CHAIN_STIFFNESS: f32 = 0.02;
+const CHAIN_MAX_ANGLE: f32 = std::f32::consts::PI / 4.0;
+// smaller asteroids are shattered whole instead of being carved
+const CARVE_MIN_RADIUS: f32 = 2f32;
+const CARVE_BULLET_RADIUS: f32 = 0.5f32;
+const CARVE_MAX_PIECES: usize = 24;
+const CRATER_POINTS: usize = 8;
+// circle arena border is drawn as a polygon
+const ARENA_BORDER_SEGMENTS: usize = 64;
 const ASTEROID_MAX_RADIUS: f32 = 4.2f32;
 const ASTEROID_MIN_RADIUS: f32 = 0.5;
 const ASTEROID_INERTIA: f32 = 2f32;
 
-const EXPLOSION_WOBBLE: f32 = 0.4;
+// camera shake profiles from rons/camera.ron
+const EXPLOSION_WOBBLE: &str = "explosion";
+const HIT_WOBBLE: &str = "hit";
+const BOSS_WOBBLE: &str = "boss";
 const DAMAGED_RED: f32 = 0.2;
 
 const MAGNETO_RADIUS: f32 = 4f32;
@@ -96,7 +134,56 @@ const DOUBLE_COINS_LIFETIME_SEC: u64 = 5;
 const REFLECT_BULLET_LIFETIME_SEC: u64 = 5;
 const COLLECTABLE_DOUBLE_COINS_SEC: u64 = 5;
 const COLLECTABLE_REFLECT_BULLET_SEC: u64 = 5;
-const DESTUCTION_SITES: usize = 20;
+const COLLECTABLE_BULLET_TIME_SEC: u64 = 5;
+const BULLET_TIME_SEC: u64 = 4;
+// projectile flying by closer than that slows the time down
+const NEAR_MISS_RADIUS: f32 = 1.5f32;
+const NEAR_MISS_MS: u64 = 400;
+const WARP_ACCELERATION: f32 = 0.05f32;
+const WARP_SPEED: f32 = 3f32;
+// velocity is multiplied by that every frame of arrival
+const WARP_BRAKE: f32 = 0.95f32;
+// extra speed ratio of the camera at the full warp
+const WARP_CAMERA_PULL: f32 = 2f32;
+// streak is as long as the way made in that many frames
+const WARP_STREAK_FRAMES: f32 = 8f32;
+const EXPLOSION_LIGHT_RADIUS: f32 = 6f32;
+const EXPLOSION_LIGHT_MS: u64 = 500;
+const ROCKET_LIGHT_RADIUS: f32 = 3f32;
+const LAZER_LIGHT_RADIUS: f32 = 3f32;
+const PICKUP_LIGHT_RADIUS: f32 = 1.5f32;
+// planet light radius relative to its sprite size
+const PLANET_LIGHT_SCALE: f32 = 1.5f32;
+// projectile trail widths, length and color come from the shooter
+const BULLET_TRAIL: TrailDesc = TrailDesc {
+    length: 0.1,
+    width: 0.12,
+    color: (1.0, 0.9, 0.7, 0.5),
+};
+const ROCKET_TRAIL: TrailDesc = TrailDesc {
+    length: 0.6,
+    width: 0.3,
+    color: (1.0, 0.6, 0.2, 0.8),
+};
+const ELITE_TINT: f32 = 0.35;
+const ELITE_COINS: usize = 3;
+const ELITE_EXP: usize = 50;
+const ELITE_SPLIT_SCALE: f32 = 0.7;
+const ELITE_MINE_LIFETIME_SECS: u64 = 3;
+const VAMPIRIC_HEAL: f32 = 0.5;
+// off-screen arrows, sizes are in world units
+const INDICATOR_MIN_SIZE: f32 = 0.25;
+const INDICATOR_MAX_SIZE: f32 = 0.6;
+// gap between arrows and the screen edge
+const INDICATOR_MARGIN: f32 = 0.8;
+const RADAR_BLIP_SIZE: f32 = 0.012; // relative to the screen height
+// the oldest floating text is reused when there are that many
+const FLOATING_TEXT_MAX: usize = 32;
+const FLOATING_TEXT_MS: u64 = 800;
+const FLOATING_TEXT_RISE: f32 = 1.5;
+const DAMAGE_TEXT_SIZE: f32 = 1.0;
+const LETHAL_TEXT_SIZE: f32 = 1.6;
+const LEVEL_UP_TEXT_SIZE: f32 = 2.0;
 
 pub fn thrust_calculation(
     maneuverability: f32,
@@ -153,8 +240,17 @@ pub fn spawn_in_rectangle(
     max_h: f32,
 ) -> Point2 {
     let mut rng = thread_rng();
-    let x = rng.gen_range(min_w, max_w);
-    let y = rng.gen_range(min_h, max_h);
+    // borders may take the whole cell, then it's the center
+    let x = if min_w < max_w {
+        rng.gen_range(min_w, max_w)
+    } else {
+        min_w
+    };
+    let y = if min_h < max_h {
+        rng.gen_range(min_h, max_h)
+    } else {
+        min_h
+    };
     Point2::new(x, y)
 }
 
@@ -175,6 +271,75 @@ pub fn is_active_rad(
     (point.coords - character_position.coords).norm() < active_area
 }
 
+/// What off-screen indicators and radar blips show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blip {
+    Enemy,
+    Pickup,
+    Boss,
+}
+
+impl Blip {
+    pub fn color(self) -> (f32, f32, f32) {
+        match self {
+            Blip::Enemy => (1.0, 0.3, 0.3),
+            Blip::Pickup => (0.4, 1.0, 0.5),
+            Blip::Boss => (1.0, 0.2, 1.0),
+        }
+    }
+}
+
+/// Enemies, pickups and bosses within `range` from the player
+pub fn blips<D>(
+    player: Point2,
+    range: f32,
+    isometries: &specs::Storage<Isometry, D>,
+    enemies: &ReadStorage<EnemyMarker>,
+    collectables: &ReadStorage<CollectableMarker>,
+    camera_targets: &ReadStorage<CameraTarget>,
+) -> Vec<(Point2, Blip)>
+where
+    D: std::ops::Deref<Target = specs::storage::MaskedStorage<Isometry>>,
+{
+    let position = |iso: &Isometry| {
+        let translation = iso.0.translation.vector;
+        Point2::new(translation.x, translation.y)
+    };
+    let bosses = camera_targets.maybe();
+    let ships = (isometries, enemies, bosses).join().map(|(iso, _, boss)| {
+        let blip = if boss.is_some() {
+            Blip::Boss
+        } else {
+            Blip::Enemy
+        };
+        (position(iso), blip)
+    });
+    let pickups = (isometries, collectables)
+        .join()
+        .map(|(iso, _)| (position(iso), Blip::Pickup));
+    ships
+        .chain(pickups)
+        .filter(|(point, _)| is_active_rad(player, *point, range))
+        .collect()
+}
+
+/// Point on the edge of the visible rectangle towards `target`,
+/// None when the target is on the screen
+pub fn edge_position(
+    center: Point2,
+    half_extents: Vector2,
+    target: Point2,
+    margin: f32,
+) -> Option<Point2> {
+    let dir = target - center;
+    if dir.x.abs() < half_extents.x && dir.y.abs() < half_extents.y {
+        return None;
+    }
+    let inner = half_extents - Vector2::new(margin, margin);
+    let scale = (inner.x / dir.x.abs()).min(inner.y / dir.y.abs());
+    Some(center + scale * dir)
+}
+
 pub fn area_reflection(
     character_position: Point2,
     point: Point2,
@@ -184,31 +349,198 @@ pub fn area_reflection(
     character_position - dir * active_area * 0.9
 }
 
-fn get_collision_groups(kind: EntityType) -> CollisionGroups {
-    match kind {
-        EntityType::Player => {
-            let mut player_bullet_collision_groups = CollisionGroups::new();
-            player_bullet_collision_groups
-                .set_membership(&[CollisionId::PlayerBullet as usize]);
-            player_bullet_collision_groups.set_whitelist(&[
-                CollisionId::Asteroid as usize,
-                CollisionId::EnemyShip as usize,
-            ]);
-            player_bullet_collision_groups
-                .set_blacklist(&[CollisionId::PlayerShip as usize]);
-            player_bullet_collision_groups
+fn get_collision_groups(
+    kind: EntityType,
+    factions: &Factions,
+) -> CollisionGroups {
+    factions.bullet_collision_groups(kind.faction())
+}
+
+/// Collider of the asteroid, concave and carved ones are compounds
+/// of convex pieces
+pub fn asteroid_shape(polygon: &Polygon) -> Option<ShapeHandle<f32>> {
+    if polygon.pieces.len() == 1 {
+        return ConvexPolygon::try_from_points(&polygon.points)
+            .map(ShapeHandle::new);
+    }
+    let shapes: Vec<_> = polygon
+        .pieces
+        .iter()
+        .filter_map(|piece| ConvexPolygon::try_from_points(piece))
+        .map(|piece| (Isometry2::identity(), ShapeHandle::new(piece)))
+        .collect();
+    if shapes.is_empty() {
+        None
+    } else {
+        Some(ShapeHandle::new(Compound::new(shapes)))
+    }
+}
+
+/// Subtract a crater from the asteroid, every connected part left
+/// is respawned as a separate asteroid.
+/// Returns false if the asteroid is too fragmented to be carved
+pub fn carve_asteroid(
+    asteroid: specs::Entity,
+    isometry: Isometry3,
+    polygon: &Polygon,
+    kind: &AsteroidType,
+    velocity: Vector2,
+    crater_position: Point2,
+    crater_radius: f32,
+    entities: &Entities,
+    insert_channel: &mut Write<EventChannel<InsertEvent>>,
+) -> bool {
+    let position = isometry.translation.vector;
+    let angle = isometry.rotation.euler_angles().2;
+    let local_position = Rotation2::new(-angle)
+        * Vector2::new(
+            crater_position.x - position.x,
+            crater_position.y - position.y,
+        );
+    let crater = circle_polygon(
+        Point2::from(local_position),
+        crater_radius,
+        CRATER_POINTS,
+    );
+    let parts = polygon.carve(&crater);
+    let pieces: usize = parts.iter().map(|part| part.pieces.len()).sum();
+    if pieces > CARVE_MAX_PIECES {
+        return false;
+    }
+    let mut rng = thread_rng();
+    for part in parts.into_iter() {
+        insert_channel.single_write(InsertEvent::Asteroid {
+            iso: Point3::new(position.x, position.y, angle),
+            velocity: Velocity2::new(velocity, 0f32),
+            polygon: part,
+            spin: rng.gen_range(-1E-2, 1E-2),
+            kind: kind.clone(),
+        });
+    }
+    entities.delete(asteroid).unwrap();
+    true
+}
+
+pub fn ships2insert(
+    spawn_pos: Point2,
+    enemy: EnemyKind,
+    elite: Option<Elite>,
+) -> InsertEvent {
+    InsertEvent::Ship {
+        iso: Point3::new(spawn_pos.x, spawn_pos.y, 0f32),
+        light_shape: Geometry::Circle { radius: 1f32 },
+        spin: 0f32,
+        kind: enemy.ai_kind,
+        gun_kind: enemy.gun_kind,
+        ship_stats: enemy.ship_stats,
+        size: enemy.size,
+        image: enemy.image,
+        snake: enemy.snake,
+        rift: enemy.rift,
+        faction: enemy.faction,
+        telegraph: enemy.telegraph,
+        elite: elite,
+        trail: enemy.trail,
+        boss: enemy.boss,
+    }
+}
+
+/// spawn ship, with `elite_chance` it becomes elite with random modifier
+pub fn promote_elite(
+    spawn_pos: Point2,
+    enemy: EnemyKind,
+    elite_chance: f32,
+) -> InsertEvent {
+    let mut rng = thread_rng();
+    if rng.gen_range(0.0, 1.0) < elite_chance {
+        let modifier = *ELITE_MODIFIERS.choose(&mut rng).unwrap();
+        let mut promoted = enemy.clone();
+        modifier.promote(&mut promoted);
+        let elite = Elite {
+            modifier: modifier,
+            kind: enemy,
+        };
+        ships2insert(spawn_pos, promoted, Some(elite))
+    } else {
+        ships2insert(spawn_pos, enemy, None)
+    }
+}
+
+/// extra drops, splitting and mines of killed elite
+fn elite_death(
+    elite: &Elite,
+    ship: specs::Entity,
+    ship_pos: Point2,
+    insert_channel: &mut Write<EventChannel<InsertEvent>>,
+    preloaded_images: &ReadExpect<PreloadedImages>,
+) {
+    let mut rng = thread_rng();
+    for _ in 0..ELITE_COINS {
+        let shift =
+            Vector2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+        insert_channel.single_write(InsertEvent::Coin {
+            value: 1,
+            position: ship_pos + shift,
+        });
+    }
+    insert_channel.single_write(InsertEvent::Exp {
+        value: ELITE_EXP,
+        position: ship_pos,
+    });
+    match elite.modifier {
+        EliteModifier::Splitting => {
+            let mut child = elite.kind.clone();
+            child.size *= ELITE_SPLIT_SCALE;
+            child.snake = None;
+            child.ship_stats.max_health =
+                (child.ship_stats.max_health / 2).max(1);
+            for side in [-1f32, 1f32].iter() {
+                let spawn_pos =
+                    ship_pos + Vector2::new(side * 2.0 * child.size, 0f32);
+                insert_channel.single_write(ships2insert(
+                    spawn_pos,
+                    child.clone(),
+                    None,
+                ));
+            }
+        }
+        EliteModifier::Mine => {
+            insert_channel.single_write(InsertEvent::Bullet {
+                kind: EntityType::Enemy(elite.kind.faction),
+                iso: Point3::new(ship_pos.x, ship_pos.y, 0f32),
+                size: elite.kind.size,
+                velocity: Point2::new(0f32, 0f32),
+                damage: 0,
+                owner: ship,
+                lifetime: Duration::from_secs(ELITE_MINE_LIFETIME_SECS),
+                bullet_image: preloaded_images.mine,
+                blast: Some(Blast {
+                    blast_damage: elite.kind.ship_stats.damage.max(100),
+                    blast_radius: 3.0,
+                }),
+                reflection: None,
+            });
         }
-        EntityType::Enemy => {
-            let mut enemy_bullet_collision_groups = CollisionGroups::new();
-            enemy_bullet_collision_groups
-                .set_membership(&[CollisionId::EnemyBullet as usize]);
-            enemy_bullet_collision_groups.set_whitelist(&[
-                CollisionId::Asteroid as usize,
-                CollisionId::PlayerShip as usize,
-            ]);
-            enemy_bullet_collision_groups
-                .set_blacklist(&[CollisionId::EnemyShip as usize]);
-            enemy_bullet_collision_groups
+        _ => (),
+    }
+}
+
+/// vampiric elite heals part of the damage it deals to the player
+fn vampiric_heal(
+    attacker: specs::Entity,
+    damage: usize,
+    elites: &ReadStorage<Elite>,
+    lifes: &mut WriteStorage<Lifes>,
+    ships_stats: &ReadStorage<ShipStats>,
+) {
+    if let Some(elite) = elites.get(attacker) {
+        if elite.modifier == EliteModifier::Vampiric {
+            if let (Some(life), Some(ship_stats)) =
+                (lifes.get_mut(attacker), ships_stats.get(attacker))
+            {
+                let heal = (VAMPIRIC_HEAL * damage as f32) as usize;
+                life.0 = (life.0 + heal).min(ship_stats.max_health);
+            }
         }
     }
 }
@@ -219,19 +551,32 @@ pub fn spawn_asteroids(
     polygon: Polygon,
     insert_channel: Arc<Mutex<EventChannel<InsertEvent>>>,
     bullet_position: Option<Point2>,
+    kind: AsteroidType,
 ) {
     flame::start("asteroids");
     let position = isometry.translation.vector;
+    if let Some(blast) = kind.blast {
+        insert_channel
+            .lock()
+            .unwrap()
+            .single_write(InsertEvent::Blast {
+                position: Point2::new(position.x, position.y),
+                blast: blast,
+            });
+    }
     let new_polygons = if let Some(bullet_position) = bullet_position {
         polygon.deconstruct(
             bullet_position - Vector2::new(position.x, position.y),
-            DESTUCTION_SITES,
+            kind.shards,
         )
     } else {
-        polygon.deconstruct(polygon.center(), DESTUCTION_SITES)
+        polygon.deconstruct(polygon.center(), kind.shards)
     };
     let mut rng = thread_rng();
     if new_polygons.len() > 1 {
+        // shards keep the look, but only the whole asteroid explodes
+        let mut shard_kind = kind.clone();
+        shard_kind.blast = None;
         for poly in new_polygons.iter() {
             let insert_event = InsertEvent::Asteroid {
                 iso: Point3::new(
@@ -242,6 +587,7 @@ pub fn spawn_asteroids(
                 velocity: initial_shard_velocity(),
                 polygon: poly.clone(),
                 spin: rng.gen_range(-1E-2, 1E-2),
+                kind: shard_kind.clone(),
             };
             insert_channel.lock().unwrap().single_write(insert_event);
         }
@@ -267,6 +613,15 @@ pub fn spawn_asteroids(
                     position: spawn_position,
                 });
         }
+        for _ in 0..kind.coins {
+            insert_channel
+                .lock()
+                .unwrap()
+                .single_write(InsertEvent::Coin {
+                    value: 1,
+                    position: spawn_position,
+                });
+        }
         if rng.gen_range(0.0, 1.0) < 0.01 {
             insert_channel.lock().unwrap().single_write(
                 InsertEvent::SideBulletCollectable {
@@ -295,6 +650,13 @@ pub fn spawn_asteroids(
                 },
             );
         }
+        if rng.gen_range(0.0, 1.0) < 0.01 {
+            insert_channel.lock().unwrap().single_write(
+                InsertEvent::BulletTimeCollectable {
+                    position: spawn_position,
+                },
+            );
+        }
     }
     flame::end("asteroids");
 }
@@ -341,12 +703,12 @@ fn ship_explode(
     });
     let effect = InsertEvent::Explosion {
         position: ship_pos,
-        num: 30,
-        lifetime: Duration::from_secs(EXPLOSION_LIFETIME_SECS),
+        emitter: "ship_explosion".to_string(),
         with_animation: Some(1f32),
     };
 
-    insert_channel.single_write(InsertEvent::Wobble(EXPLOSION_WOBBLE));
+    insert_channel
+        .single_write(InsertEvent::Wobble(EXPLOSION_WOBBLE.to_string()));
     insert_channel.single_write(effect);
     sounds_channel
         .single_write(Sound(preloaded_sounds.ship_explosion, ship_pos));
@@ -361,8 +723,7 @@ fn bullet_contact(
 ) {
     let effect = InsertEvent::Explosion {
         position: contact_pos,
-        num: 2,
-        lifetime: Duration::from_secs(EXPLOSION_LIFETIME_SECS),
+        emitter: "bullet_contact".to_string(),
         with_animation: None,
     };
     let animation = InsertEvent::Animation {
@@ -389,8 +750,7 @@ fn asteroid_explode(
     ));
     let effect = InsertEvent::Explosion {
         position: Point2::new(explode_position.x, explode_position.y),
-        num: 30usize,
-        lifetime: Duration::from_secs(EXPLOSION_LIFETIME_SECS),
+        emitter: "asteroid_explosion".to_string(),
         with_animation: Some(size),
     };
     insert_channel.single_write(effect);
@@ -0,0 +1,30 @@
+use super::*;
+use gfx_h::Backend;
+use std::marker::PhantomData;
+
+/// Hands the frame recorded on the canvas to the backend.
+/// Runs last, after all rendering systems of the state
+pub struct PresentSystem<B> {
+    backend: PhantomData<B>,
+}
+
+impl<B> Default for PresentSystem<B> {
+    fn default() -> Self {
+        PresentSystem {
+            backend: PhantomData,
+        }
+    }
+}
+
+impl<'a, B: Backend + 'static> System<'a> for PresentSystem<B> {
+    type SystemData = (
+        WriteExpect<'a, Canvas>,
+        ReadExpect<'a, red::Viewport>,
+        WriteExpect<'a, ThreadPin<B>>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut canvas, viewport, mut backend) = data;
+        backend.execute(&viewport, canvas.take_commands());
+    }
+}
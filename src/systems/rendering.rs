@@ -1,6 +1,7 @@
 pub use crate::gui::{Button, Picture, Rectangle, Selector};
 use gfx_h::{
-    unproject_with_z, RenderMode, SpriteBatch, TextData, WorldTextData,
+    unproject_with_z, Emitter, Mesh, ParticlePool, PostProcessParams,
+    PostProcessSettings, RenderMode, TextRun, TrailPoint,
 };
 use num_enum::TryFromPrimitive;
 use telemetry::{render_plot, TeleGraph};
@@ -11,10 +12,26 @@ use super::*;
 #[cfg(any(target_os = "android"))]
 use crate::gui::VecController;
 use geometry::{shadow_geometry, Triangulation};
-use glyph_brush::{rusttype::Scale, Section};
 use physics_system::MENU_VELOCITY;
 
+// only that many extra lights cast shadows, the rest just glow
+const MAX_SHADOW_LIGHTS: usize = 4;
+const LIGHT_DISK_SEGMENTS: usize = 24;
+// asteroids further than light radius + margin don't block the light
+const LIGHT_OCCLUDER_MARGIN: f32 = ASTEROID_MAX_RADIUS;
+// vignette starts to appear below that part of max health
+const LOW_HEALTH: f32 = 0.3;
+
 fn visible(canvas: &Canvas, iso: &Isometry3, dims: (i32, i32)) -> bool {
+    visible_with_margin(canvas, iso, dims, 0f32)
+}
+
+fn visible_with_margin(
+    canvas: &Canvas,
+    iso: &Isometry3,
+    dims: (i32, i32),
+    margin: f32,
+) -> bool {
     let unprojected = unproject_with_z(
         canvas.observer(),
         &Point2::new(1.0, 1.0),
@@ -26,7 +43,20 @@ fn visible(canvas: &Canvas, iso: &Isometry3, dims: (i32, i32)) -> bool {
     let corner_rvec = Vector2::new(unprojected.x, unprojected.y);
     let object_rvec =
         Vector2::new(iso.translation.vector.x, iso.translation.vector.y);
-    object_rvec.norm() < corner_rvec.norm()
+    object_rvec.norm() < corner_rvec.norm() + margin
+}
+
+fn light_disk(center: Point2, radius: f32) -> Triangulation {
+    let mut points = vec![center];
+    let mut indicies = vec![];
+    for i in 0..LIGHT_DISK_SEGMENTS {
+        let angle =
+            2.0 * std::f32::consts::PI * i as f32 / LIGHT_DISK_SEGMENTS as f32;
+        points.push(center + radius * Vector2::new(angle.cos(), angle.sin()));
+        let next = (i + 1) % LIGHT_DISK_SEGMENTS;
+        indicies.extend(&[0, i as u16 + 1, next as u16 + 1]);
+    }
+    Triangulation { points, indicies }
 }
 
 #[derive(Clone, Copy, Debug, TryFromPrimitive)]
@@ -52,25 +82,28 @@ pub enum Widgets {
     Done,
     WeaponSelector,
     ShipsSelector,
-    UpgradeSelector
+    UpgradeSelector,
+    BloomToggle,
+    AberrationToggle,
+    VignetteToggle,
+    WarpDistortionToggle,
+    FloatingTextToggle,
 }
 
 pub fn render_primitives<'a>(
     mouse: &Read<'a, Mouse>,
     reader: &mut ReaderId<Primitive>,
-    frame: &mut red::Frame,
-    gl: &ReadExpect<'a, ThreadPin<red::GL>>,
     canvas: &mut WriteExpect<'a, Canvas>,
     viewport: &ReadExpect<'a, red::Viewport>,
     primitives_channel: &mut Write<'a, EventChannel<Primitive>>,
-    text_data: &mut WriteExpect<'a, ThreadPin<TextData<'static>>>,
-    world_text_data: &mut WriteExpect<'a, ThreadPin<WorldTextData<'static>>>,
 ) {
     let dims = viewport.dimensions();
     let (w, h) = (dims.0 as f32, dims.1 as f32);
     let world_text_scale =
         ((w * w + h * h).sqrt() / 10000.0 * mouse.hdpi as f32).round();
     let scale = ((w * w + h * h).sqrt() / 11000.0 * mouse.hdpi as f32).round();
+    let mut text_runs = vec![];
+    let mut world_text_runs = vec![];
     for primitive in primitives_channel.read(reader) {
         match primitive {
             Primitive {
@@ -79,9 +112,6 @@ pub fn render_primitives<'a>(
             } => {
                 let (model, _points, _indicies) = picture.get_gfx();
                 canvas.render_primitive_texture(
-                    &gl,
-                    &viewport,
-                    frame,
                     &picture.image,
                     &model,
                     *with_projection,
@@ -93,14 +123,9 @@ pub fn render_primitives<'a>(
                 with_projection,
             } => {
                 let (model, points, indicies) = rectangle.get_gfx();
-                let geom_data =
-                    GeometryData::new(&gl, &points, &indicies).unwrap();
                 let fill_color = rectangle.color;
                 canvas.render_primitive(
-                    &gl,
-                    &viewport,
-                    frame,
-                    &geom_data,
+                    Mesh::new(&points, &indicies),
                     &model,
                     (fill_color.x, fill_color.y, fill_color.z),
                     *with_projection,
@@ -111,7 +136,6 @@ pub fn render_primitives<'a>(
                 kind: PrimitiveKind::Text(text),
                 with_projection,
             } => {
-                use glyph_brush::{HorizontalAlign, Layout, VerticalAlign};
                 if *with_projection {
                     // INSANE hack to deal with projections and glyph brush!
                     // calculating text screen coords via projections on CPU
@@ -134,48 +158,26 @@ pub fn render_primitives<'a>(
                         t_pos,
                     );
                     let point = (point.x, point.y);
-                    world_text_data.glyph_brush.queue(Section {
-                        text: &text.text,
-                        scale: Scale::uniform(
-                            world_text_scale * text.font_size,
-                        ),
-                        screen_position: point,
-                        // bounds: (w /20.0, h / 20.0),
-                        color: [
-                            text.color.0,
-                            text.color.1,
-                            text.color.2,
-                            text.color.3,
-                        ],
-                        layout: Layout::default()
-                            .h_align(HorizontalAlign::Center)
-                            .v_align(VerticalAlign::Center),
-                        ..Section::default()
+                    world_text_runs.push(TextRun {
+                        text: text.text.clone(),
+                        position: point,
+                        scale: world_text_scale * text.font_size,
+                        color: text.color,
                     });
                 } else {
                     // orthographic projection
-                    text_data.glyph_brush.queue(Section {
-                        text: &text.text,
-                        scale: Scale::uniform(scale * text.font_size),
-                        screen_position: (text.position.x, text.position.y),
-                        // bounds: (w /3.15, h),
-                        color: [
-                            text.color.0,
-                            text.color.1,
-                            text.color.2,
-                            text.color.3,
-                        ],
-                        layout: Layout::default()
-                            .h_align(HorizontalAlign::Center)
-                            .v_align(VerticalAlign::Center),
-                        ..Section::default()
+                    text_runs.push(TextRun {
+                        text: text.text.clone(),
+                        position: (text.position.x, text.position.y),
+                        scale: scale * text.font_size,
+                        color: text.color,
                     });
                 }
             }
         }
     }
-    canvas.render_text(text_data, &viewport, frame, false);
-    canvas.render_world_text(world_text_data, &viewport, frame, false);
+    canvas.render_text(text_runs, false);
+    canvas.render_text(world_text_runs, true);
 }
 
 pub struct RenderingSystem {
@@ -199,10 +201,7 @@ impl<'a> System<'a> for RenderingSystem {
             ReadStorage<'a, ShipMarker>,
             ReadStorage<'a, AsteroidMarker>,
             ReadStorage<'a, LightMarker>,
-            ReadStorage<'a, StarsMarker>,
-            ReadStorage<'a, NebulaMarker>,
-            ReadStorage<'a, PlanetMarker>,
-            ReadStorage<'a, FogMarker>,
+            ReadStorage<'a, BackgroundMarker>,
             ReadStorage<'a, Projectile>,
             ReadStorage<'a, ThreadPin<ImageData>>,
             ReadStorage<'a, AtlasImage>,
@@ -210,11 +209,11 @@ impl<'a> System<'a> for RenderingSystem {
             ReadStorage<'a, Size>,
             ReadStorage<'a, Geometry>,
             ReadStorage<'a, CollectableMarker>,
-            WriteStorage<'a, ThreadPin<ParticlesData>>,
+            WriteStorage<'a, ParticleField>,
             ReadStorage<'a, MultyLazer>,
             ReadStorage<'a, Chain>,
             ReadStorage<'a, Rift>,
-            ReadStorage<'a, ThreadPin<GeometryData>>,
+            ReadStorage<'a, Mesh>,
             ReadStorage<'a, DamageFlash>,
             WriteStorage<'a, TextComponent>,
         ),
@@ -222,19 +221,42 @@ impl<'a> System<'a> for RenderingSystem {
         ReadStorage<'a, Lifetime>,
         WriteExpect<'a, TeleGraph>,
         Read<'a, Mouse>,
-        ReadExpect<'a, ThreadPin<red::GL>>,
         ReadExpect<'a, red::Viewport>,
         WriteExpect<'a, Canvas>,
         ReadExpect<'a, PreloadedParticles>,
         Read<'a, World<f32>>,
         Write<'a, EventChannel<Primitive>>,
         Write<'a, UI>,
-        WriteExpect<'a, ThreadPin<TextData<'static>>>,
-        WriteExpect<'a, ThreadPin<WorldTextData<'static>>>,
         WriteExpect<'a, GlobalParams>,
         ReadExpect<'a, DevInfo>,
         Write<'a, EventChannel<Sound>>,
         ReadExpect<'a, PreloadedImages>,
+        ReadStorage<'a, AttackTelegraph>,
+        ReadStorage<'a, Elite>,
+        ReadStorage<'a, AsteroidType>,
+        (
+            ReadExpect<'a, Sectors>,
+            Read<'a, CurrentSector>,
+            Read<'a, ActiveArena>,
+            Read<'a, WorldOffset>,
+            Read<'a, TimeScale>,
+            Read<'a, Warp>,
+            ReadStorage<'a, Light>,
+            Read<'a, PostProcessSettings>,
+            ReadStorage<'a, Lifes>,
+            ReadStorage<'a, ShipStats>,
+            WriteStorage<'a, Emitter>,
+            WriteExpect<'a, ThreadPin<ParticlePool>>,
+            ReadStorage<'a, Trail>,
+            Write<'a, EventChannel<AnimationEvent>>,
+            ReadExpect<'a, Camera>,
+            ReadStorage<'a, EnemyMarker>,
+            ReadStorage<'a, CameraTarget>,
+            Read<'a, HudSettings>,
+            ReadStorage<'a, FloatingText>,
+            ReadExpect<'a, BackgroundLayers>,
+            ReadStorage<'a, Tint>,
+        ),
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -248,10 +270,7 @@ impl<'a> System<'a> for RenderingSystem {
                 ship_markers,
                 asteroid_markers,
                 light_markers,
-                stars,
-                nebulas,
-                planets,
-                _fog_markers,
+                background_markers,
                 projectiles,
                 image_datas,
                 atlas_images,
@@ -259,11 +278,11 @@ impl<'a> System<'a> for RenderingSystem {
                 sizes,
                 geometries,
                 collectables,
-                mut particles_datas,
+                mut particle_fields,
                 multy_lazers,
                 _chains,
                 rifts,
-                geom_datas,
+                meshes,
                 damage_flash,
                 mut text_components,
             ),
@@ -271,55 +290,67 @@ impl<'a> System<'a> for RenderingSystem {
             lifetimes,
             mut telegraph,
             mouse,
-            gl,
             viewport,
             mut canvas,
             preloaded_particles,
             _world,
             mut primitives_channel,
             mut ui,
-            mut text_data,
-            mut world_text_data,
             mut global_params,
             dev_info,
             mut sounds_channel,
             preloaded_images,
+            attack_telegraphs,
+            elites,
+            asteroid_types,
+            (
+                sectors,
+                current_sector,
+                active_arena,
+                world_offset,
+                time_scale,
+                warp,
+                lights,
+                post_process_settings,
+                lifes,
+                ships_stats,
+                mut emitters,
+                mut particle_pool,
+                trails,
+                mut animation_events,
+                camera,
+                enemy_markers,
+                camera_targets,
+                hud_settings,
+                floating_texts,
+                background_layers,
+                tints,
+            ),
         ) = data;
         let dims = viewport.dimensions();
         flame::start("rendering");
         flame::start("clear");
-        let mut frame = red::Frame::new(&gl);
+        canvas.begin_post_process(&post_process_settings);
         global_params.update();
-        frame.set_clear_color(global_params.red.min(1.0), 0.004, 0.0, 1.0);
-        // frame.set_clear_color(0.15, 0.004, 0.0, 1.0);
-        frame.set_clear_stencil(0);
-        frame.clear_color_and_stencil();
-        flame::start("color");
-        frame.clear_color();
-        flame::end("color");
-        flame::start("stencil");
-        frame.clear_stencil();
-        flame::end("stencil");
+        canvas.clear((global_params.red.min(1.0), 0.004, 0.0, 1.0));
         telegraph.update();
         flame::end("clear");
-        if let Some((iso, vel, _)) =
-            (&isometries, &velocities, &character_markers).join().next()
+        if let Some((iso, _)) = (&isometries, &character_markers).join().next()
         {
-            canvas.update_observer(
-                Point2::new(
-                    iso.0.translation.vector.x,
-                    iso.0.translation.vector.y,
-                ),
-                vel.0.norm() / VELOCITY_MAX,
-                Vector2::new(mouse.x01, mouse.y01).normalize(),
-            );
+            canvas.set_observer(camera.observer(), camera.z_far());
             let char_pos = iso.0.translation.vector;
             flame::start("shadow rendering");
             let mut final_triangulation = Triangulation::new();
+            let mut occluders = vec![];
             for (_entity, iso, geom, _) in
                 (&entities, &isometries, &geometries, &asteroid_markers).join()
             {
-                if visible(&*canvas, &iso.0, dims) {
+                if visible_with_margin(
+                    &*canvas,
+                    &iso.0,
+                    dims,
+                    LIGHT_OCCLUDER_MARGIN,
+                ) {
                     let pos = Point2::new(
                         iso.0.translation.vector.x,
                         iso.0.translation.vector.y,
@@ -333,6 +364,10 @@ impl<'a> System<'a> for RenderingSystem {
                     } else {
                         geom.clone()
                     };
+                    occluders.push((pos, rotation, geom.clone()));
+                    if !visible(&*canvas, &iso.0, dims) {
+                        continue;
+                    }
                     let shadow_triangulation = shadow_geometry(
                         Point2::new(char_pos.x, char_pos.y),
                         geom,
@@ -346,29 +381,93 @@ impl<'a> System<'a> for RenderingSystem {
                     }
                 }
             }
-            let geometry_data = GeometryData::new(
-                &gl,
+            let shadows_mesh = Mesh::new(
                 &final_triangulation.points,
                 &final_triangulation.indicies,
-            )
-            .unwrap();
+            );
             let iso = Isometry3::new(
                 Vector3::new(0f32, 0f32, 0f32),
                 Vector3::new(0f32, 0f32, 0f32),
             );
             // draw shadows
             canvas.render_geometry(
-                &gl,
-                &viewport,
-                &mut frame,
-                &geometry_data,
+                shadows_mesh,
                 &iso,
                 RenderMode::StencilWrite,
                 Point3::new(0f32, 0f32, 0f32),
             );
+            // visibility pre-pass: brightest lights on the screen
+            let mut shadow_lights = vec![];
+            for (iso, light) in (&isometries, &lights).join() {
+                if visible_with_margin(&*canvas, &iso.0, dims, light.radius) {
+                    let pos = iso.0.translation.vector;
+                    shadow_lights.push((Point2::new(pos.x, pos.y), *light));
+                }
+            }
+            shadow_lights.sort_by(|(_, a), (_, b)| {
+                (b.intensity * b.radius)
+                    .partial_cmp(&(a.intensity * a.radius))
+                    .unwrap()
+            });
+            shadow_lights.truncate(MAX_SHADOW_LIGHTS);
+            for (light_pos, light) in shadow_lights.iter() {
+                let mut shadows = Triangulation::new();
+                for (pos, rotation, geom) in occluders.iter() {
+                    let distance = (pos - light_pos).norm();
+                    if distance > light.radius + LIGHT_OCCLUDER_MARGIN {
+                        continue;
+                    }
+                    if let Some(mut shadow_triangulation) = shadow_geometry(
+                        *light_pos,
+                        geom.clone(),
+                        *pos,
+                        *rotation,
+                    ) {
+                        shadow_triangulation.translate(pos.coords);
+                        shadows.extend(shadow_triangulation);
+                    }
+                }
+                let shadows_mesh =
+                    Mesh::new(&shadows.points, &shadows.indicies);
+                let disk = light_disk(*light_pos, light.radius);
+                canvas.render_geometry(
+                    shadows_mesh.clone(),
+                    &iso,
+                    RenderMode::ShadowMark,
+                    Point3::new(0f32, 0f32, 0f32),
+                );
+                canvas.render_geometry(
+                    Mesh::new(&disk.points, &disk.indicies),
+                    &iso,
+                    RenderMode::LightReveal,
+                    Point3::new(0f32, 0f32, 0f32),
+                );
+                canvas.render_geometry(
+                    shadows_mesh,
+                    &iso,
+                    RenderMode::ShadowClear,
+                    Point3::new(0f32, 0f32, 0f32),
+                );
+            }
 
             flame::end("shadow rendering");
         };
+        flame::start("trails rendering");
+        let ribbons = (&trails)
+            .join()
+            .map(|trail| {
+                trail
+                    .ribbon()
+                    .map(|(position, half_width, color)| TrailPoint {
+                        position: (position.x, position.y),
+                        half_width,
+                        color,
+                    })
+                    .collect()
+            })
+            .collect();
+        canvas.render_trails(ribbons);
+        flame::end("trails rendering");
         flame::start("sprite batch rendering");
         // struct to store batch data(needed to do z sorting)
         pub struct MiniBatch {
@@ -408,54 +507,100 @@ impl<'a> System<'a> for RenderingSystem {
         let mut background_batch = MiniBatch::new();
         let mut hide_foreground_batch = MiniBatch::new();
         let mut visible_foreground_batch = MiniBatch::new();
-        let mut stars_batch = MiniBatch::new();
-        let mut nebulas_batch = MiniBatch::new();
-        let mut planets_batch = MiniBatch::new();
-        let mut fog_batch = MiniBatch::new();
-        for (entity, iso, atlas_image, size) in
-            (&entities, &isometries, &atlas_images, &sizes).join()
+        // layers are drawn in the order of rons/background.ron
+        let mut layer_batches: Vec<MiniBatch> = background_layers
+            .layers
+            .iter()
+            .map(|_| MiniBatch::new())
+            .collect();
+        for (entity, iso, size, marker) in
+            (&entities, &isometries, &sizes, &background_markers).join()
         {
-            if stars.get(entity).is_some() {
-                stars_batch.append(*atlas_image, iso.0, size.0);
-            }
-            if nebulas.get(entity).is_some() {
-                nebulas_batch.append(*atlas_image, iso.0, size.0);
-            }
-
-            if planets.get(entity).is_some() {
-                planets_batch.append(*atlas_image, iso.0, size.0);
-            }
-            if _fog_markers.get(entity).is_some() {
-                fog_batch.append(*atlas_image, iso.0, size.0)
+            let image = if let Some(atlas_image) = atlas_images.get(entity) {
+                Some(*atlas_image)
+            } else if let Some(animation) = animations.get_mut(entity) {
+                animation.next_frame().map(|frame| frame.image)
+            } else {
+                None
+            };
+            if let Some(mut image) = image {
+                image.color = background_layers.layers[marker.0].desc.tint;
+                layer_batches[marker.0].append(image, iso.0, size.0);
             }
         }
-        background_batch.extend(stars_batch);
-        background_batch.extend(nebulas_batch);
-        background_batch.extend(planets_batch);
-        background_batch.extend(fog_batch);
-        for (entity, iso, atlas_image, size, (), (), (), ()) in (
+        for layer_batch in layer_batches {
+            background_batch.extend(layer_batch);
+        }
+        // glow of the lights, dying lights fade out
+        for (entity, iso, light) in (&entities, &isometries, &lights).join() {
+            let mut glow_image = preloaded_images.light_white;
+            let fade = if let Some(lifetime) = lifetimes.get(entity) {
+                lifetime.rest_fraction().max(0f32)
+            } else {
+                1f32
+            };
+            glow_image.transparency = (light.intensity * fade).min(1f32);
+            glow_image.color =
+                (light.color.0, light.color.1, light.color.2, 1f32);
+            background_batch.append(glow_image, iso.0, light.radius);
+        }
+        // in fog biomes distant ships and asteroids are hidden
+        let fog_center = if sectors.biomes[current_sector.biome].fog {
+            (&isometries, &character_markers)
+                .join()
+                .next()
+                .map(|(iso, _)| iso.0.translation.vector)
+        } else {
+            None
+        };
+        for (entity, iso, atlas_image, size, ()) in (
             &entities,
             &isometries,
             &atlas_images,
             &sizes,
-            !&stars,
-            !&planets,
-            !&nebulas,
-            !&_fog_markers,
+            !&background_markers,
         )
             .join()
         {
+            if let Some(center) = fog_center {
+                let pos = iso.0.translation.vector;
+                let distance =
+                    Vector2::new(pos.x - center.x, pos.y - center.y).norm();
+                let hidden = ship_markers.get(entity).is_some()
+                    || asteroid_markers.get(entity).is_some();
+                if hidden
+                    && character_markers.get(entity).is_none()
+                    && distance > FOG_VISIBILITY
+                {
+                    continue;
+                }
+            }
             let mut image = *atlas_image;
             let intensity = if let Some(flash) = damage_flash.get(entity) {
                 flash.0
             } else {
                 0f32
             };
-            image.color = (1f32, 1f32, 1f32, intensity);
+            image.color = match tints.get(entity) {
+                Some(tint) if intensity == 0f32 => tint.0,
+                _ => (1f32, 1f32, 1f32, intensity),
+            };
+            if let Some(elite) = elites.get(entity) {
+                if intensity < ELITE_TINT {
+                    let (r, g, b) = elite.modifier.color();
+                    image.color = (r, g, b, ELITE_TINT);
+                }
+            }
             hide_foreground_batch.append(image, iso.0, size.0);
         }
-        for (iso, size, animation) in
-            (&isometries, &sizes, &mut animations).join()
+        for (entity, iso, size, animation, ()) in (
+            &entities,
+            &isometries,
+            &sizes,
+            &mut animations,
+            !&background_markers,
+        )
+            .join()
         {
             if visible(&*canvas, &iso.0, dims) {
                 let animation_frame = animation.next_frame();
@@ -466,6 +611,12 @@ impl<'a> System<'a> for RenderingSystem {
                         size.0,
                     );
                 };
+                for name in animation.events() {
+                    animation_events.single_write(AnimationEvent {
+                        entity,
+                        name: name.to_string(),
+                    });
+                }
             }
         }
         // cursor image
@@ -480,6 +631,57 @@ impl<'a> System<'a> for RenderingSystem {
                 cursor_size * cursor_scale,
             );
         }
+        // off-screen indicators
+        if let (true, Some((iso, _))) = (
+            hud_settings.indicators,
+            (&isometries, &character_markers).join().next(),
+        ) {
+            let translation = iso.0.translation.vector;
+            let player = Point2::new(translation.x, translation.y);
+            let observer = canvas.observer();
+            let center = Point2::new(observer.x, observer.y);
+            let half_extents =
+                visible_half_extents(observer, dims.0 as f32 / dims.1 as f32);
+            for (target, blip) in blips(
+                player,
+                hud_settings.indicator_range,
+                &isometries,
+                &enemy_markers,
+                &collectables,
+                &camera_targets,
+            ) {
+                let edge = if let Some(edge) = edge_position(
+                    center,
+                    half_extents,
+                    target,
+                    INDICATOR_MARGIN,
+                ) {
+                    edge
+                } else {
+                    continue;
+                };
+                let dir = target - edge;
+                let angle =
+                    Rotation2::rotation_between(&Vector2::new(0.0, 1.0), &dir)
+                        .angle();
+                // closer things get bigger arrows
+                let closeness =
+                    1.0 - (dir.norm() / hud_settings.indicator_range).min(1.0);
+                let size = INDICATOR_MIN_SIZE
+                    + closeness * (INDICATOR_MAX_SIZE - INDICATOR_MIN_SIZE);
+                let mut image = preloaded_images.direction;
+                let (r, g, b) = blip.color();
+                image.color = (r, g, b, 1.0);
+                visible_foreground_batch.append(
+                    image,
+                    Isometry3::new(
+                        Vector3::new(edge.x, edge.y, 0.0),
+                        Vector3::new(0.0, 0.0, angle),
+                    ),
+                    size,
+                );
+            }
+        }
         // speed glow
         {
             if let Some((iso, vel, _char_marker)) =
@@ -490,58 +692,32 @@ impl<'a> System<'a> for RenderingSystem {
                 background_batch.append(glow_image, iso.0, 1f32);
             }
         }
-        let sprite_batch = SpriteBatch::new(
-            &gl,
+        canvas.render_sprite_batch(
             &background_batch.images,
             &background_batch.isometries,
             &background_batch.sizes,
-        );
-        canvas.render_sprite_batch(
-            &gl,
-            &viewport,
-            &mut frame,
-            &sprite_batch,
             false,
             Some(red::Blend),
         );
-        let sprite_batch = SpriteBatch::new(
-            &gl,
+        canvas.render_sprite_batch(
             &hide_foreground_batch.images,
             &hide_foreground_batch.isometries,
             &hide_foreground_batch.sizes,
-        );
-        canvas.render_sprite_batch(
-            &gl,
-            &viewport,
-            &mut frame,
-            &sprite_batch,
             true,
             Some(red::Blend),
         );
         flame::end("sprite batch rendering");
         flame::start("particles rendering");
-        for (entity, particles_data) in (&entities, &mut particles_datas).join()
-        {
-            match **particles_data {
-                ParticlesData::Explosion(ref mut particles) => {
-                    if particles.update() {
-                        canvas.render_instancing(
-                            &gl,
-                            &viewport,
-                            &mut frame,
-                            &particles.instancing_data,
-                            &Isometry3::new(
-                                Vector3::new(0f32, 0f32, 0f32),
-                                Vector3::new(0f32, 0f32, 0f32),
-                            ),
-                        );
-                    } else {
-                        entities.delete(entity).unwrap();
-                    }
-                }
-                _ => (),
-            };
+        let frame_delta = TRACKER.lock().unwrap().last_delta().as_secs_f32();
+        particle_pool.clear();
+        for (entity, emitter) in (&entities, &mut emitters).join() {
+            if emitter.update(frame_delta) {
+                particle_pool.push(emitter);
+            } else {
+                entities.delete(entity).unwrap();
+            }
         }
+        particle_pool.render(&mut canvas);
 
         let (iso, vel) = if let Some((iso, vel, _char_marker)) =
             (&isometries, &velocities, &character_markers).join().next()
@@ -559,22 +735,26 @@ impl<'a> System<'a> for RenderingSystem {
                 Isometry3::new(translation_vec, Vector3::new(0f32, 0f32, 0f32));
             let pure_isometry = isometry.clone();
             isometry.translation.vector.z = canvas.get_z_shift();
-            match **particles_datas
+            let field = particle_fields
                 .get_mut(preloaded_particles.movement)
-                .unwrap()
-            {
-                ParticlesData::MovementParticles(ref mut particles) => {
-                    particles.update(PHYSICS_SIMULATION_TIME * 1.0 * Vector2::new(-vel.0.x, -vel.0.y));
-                    canvas.render_instancing(
-                        &gl,
-                        &viewport,
-                        &mut frame,
-                        &particles.instancing_data,
-                        &pure_isometry,
-                    );
-                }
-                _ => panic!(),
-            };
+                .unwrap();
+            field.stretch(
+                -PHYSICS_SIMULATION_TIME
+                    * WARP_STREAK_FRAMES
+                    * warp.intensity()
+                    * vel.0,
+            );
+            field.emitter.update(frame_delta);
+            field.emitter.shift(
+                PHYSICS_SIMULATION_TIME
+                    * time_scale.scale
+                    * Vector3::new(-vel.0.x, -vel.0.y, 0f32),
+            );
+            canvas.render_instancing(
+                &field.shape,
+                &field.positions(),
+                &pure_isometry,
+            );
         }
 
         flame::end("particles rendering");
@@ -595,7 +775,6 @@ impl<'a> System<'a> for RenderingSystem {
                         .map(|v: Vector2| Point2::from(rotation * v))
                         .collect();
                     let indices = [0u16, 1, 2];
-                    // let geometry_data = GeometryData::new(&gl, &positions, &indices).unwrap();
                     let iso2 = iso3_iso2(&iso.0);
                     let mut triangulation = Triangulation {
                         points: positions,
@@ -603,15 +782,6 @@ impl<'a> System<'a> for RenderingSystem {
                     };
                     triangulation.apply(iso2);
                     return triangulation;
-                    // canvas.render_geometry(
-                    //     &gl,
-                    //     &viewport,
-                    //     &mut frame,
-                    //     &geometry_data,
-                    //     &iso.0,
-                    //     RenderMode::StencilCheck,
-                    //     Point3::new(1.0, 0.0, 0.0),
-                    // );
                 }
                 Triangulation::new()
             };
@@ -654,17 +824,8 @@ impl<'a> System<'a> for RenderingSystem {
             }
         }
         // render lazers with only one draw call
-        let geometry_data = GeometryData::new(
-            &gl,
-            &lazer_geometries.points,
-            &lazer_geometries.indicies,
-        )
-        .unwrap();
         canvas.render_geometry(
-            &gl,
-            &viewport,
-            &mut frame,
-            &geometry_data,
+            Mesh::new(&lazer_geometries.points, &lazer_geometries.indicies),
             &iso0,
             RenderMode::StencilCheck,
             Point3::new(1.0, 0.0, 0.0),
@@ -672,71 +833,112 @@ impl<'a> System<'a> for RenderingSystem {
         flame::end("other");
         flame::start("asteroids rendering");
         // let mut asteroids_triangulation = Triangulation::new();
-        for (_entity, iso, _size, geom_data, _asteroid) in (
-            &entities,
-            &isometries,
-            &sizes,
-            &geom_datas,
-            &asteroid_markers,
-        )
-            .join()
+        let mut asteroid_batch = MiniBatch::new();
+        for (entity, iso, _size, mesh, _asteroid) in
+            (&entities, &isometries, &sizes, &meshes, &asteroid_markers).join()
         {
             if visible(&*canvas, &iso.0, dims) {
                 canvas.render_geometry(
-                    &gl,
-                    &viewport,
-                    &mut frame,
-                    &geom_data,
+                    mesh.clone(),
                     &iso.0,
                     RenderMode::Draw,
-                    Point3::new(0.8, 0.8, 0.8),
-                )
+                    if let Some(kind) = asteroid_types.get(entity) {
+                        Point3::new(kind.color.0, kind.color.1, kind.color.2)
+                    } else {
+                        Point3::new(0.8, 0.8, 0.8)
+                    },
+                );
+                let image = asteroid_types
+                    .get(entity)
+                    .and_then(|kind| kind.atlas_image);
+                if let (Some(image), Some(Geometry::Polygon(polygon))) =
+                    (image, geometries.get(entity))
+                {
+                    asteroid_batch.append(image, iso.0, polygon.max_r);
+                }
             }
         }
+        canvas.render_sprite_batch(
+            &asteroid_batch.images,
+            &asteroid_batch.isometries,
+            &asteroid_batch.sizes,
+            true,
+            Some(red::Blend),
+        );
         flame::end("asteroids rendering");
-        let _render_line = |a: Point2, b: Point2| {
-            let line_width = 0.05;
-            let line_length = (b.coords - a.coords).norm();
-            let positions = vec![
-                Point2::new(-line_width / 2.0, 0f32),
-                Point2::new(line_width / 2.0, 0f32),
-                Point2::new(-line_width / 2.0, -line_length),
-                Point2::new(line_width / 2.0, -line_length),
-            ];
-            let up = Vector2::new(0.0, -line_length);
-            let rotation =
-                Rotation2::rotation_between(&up, &(&b.coords - a.coords));
-            let iso = Isometry3::new(
-                Vector3::new(a.x, a.y, 0f32),
-                Vector3::new(0f32, 0f32, rotation.angle()),
-            );
-            let indices = [0u16, 1, 2, 0, 2, 3];
-            let geometry_data =
-                GeometryData::new(&gl, &positions, &indices).unwrap();
-            canvas.render_geometry(
-                &gl,
-                &viewport,
-                &mut frame,
-                &geometry_data,
-                &iso,
-                RenderMode::Draw,
-                Point3::new(1f32, 1f32, 1f32),
-            );
-        };
-        for (iso, text, lifetime) in
-            (&isometries, &mut text_components, &lifetimes).join()
+        // warn about upcoming kamikadze or charging dash
+        for (iso, attack_telegraph) in (&isometries, &attack_telegraphs).join()
         {
+            if attack_telegraph.is_active() {
+                let progress = attack_telegraph.progress();
+                let pos = iso.0.translation.vector;
+                canvas.draw_line(
+                    Point2::new(pos.x, pos.y),
+                    attack_telegraph.target,
+                    Point3::new(1.0, 0.5 * (1.0 - progress), 0.0),
+                    0.02 + 0.08 * progress,
+                );
+            }
+        }
+        if let Some(arena) = active_arena.arena {
+            let center = active_arena.center - world_offset.0;
+            let border: Vec<Point2> = match arena.shape {
+                ArenaShape::Circle { radius } => (0..ARENA_BORDER_SEGMENTS)
+                    .map(|i| {
+                        let angle = 2.0 * std::f32::consts::PI * i as f32
+                            / ARENA_BORDER_SEGMENTS as f32;
+                        center + radius * Vector2::new(angle.cos(), angle.sin())
+                    })
+                    .collect(),
+                ArenaShape::Rectangle { width, height } => vec![
+                    center + Vector2::new(-width / 2.0, -height / 2.0),
+                    center + Vector2::new(width / 2.0, -height / 2.0),
+                    center + Vector2::new(width / 2.0, height / 2.0),
+                    center + Vector2::new(-width / 2.0, height / 2.0),
+                ],
+            };
+            let color = match arena.behavior {
+                ArenaBehavior::Bounce => Point3::new(0.3, 0.6, 1.0),
+                ArenaBehavior::Wrap => Point3::new(0.6, 0.3, 1.0),
+            };
+            for i in 0..border.len() {
+                canvas.draw_line(
+                    border[i],
+                    border[(i + 1) % border.len()],
+                    color,
+                    0.1,
+                );
+            }
+        }
+        for (iso, text, lifetime, floating) in (
+            &isometries,
+            &mut text_components,
+            &lifetimes,
+            floating_texts.maybe(),
+        )
+            .join()
+        {
+            // expired floating texts wait in the pool
+            if lifetime.delete() {
+                continue;
+            }
             let lifetime_fraction = lifetime.rest_fraction();
             text.color.3 = lifetime_fraction;
+            let mut position = Point2::new(
+                iso.0.translation.vector.x,
+                iso.0.translation.vector.y,
+            );
+            let mut font_size = 1.0;
+            if let Some(floating) = floating {
+                position += floating.velocity * lifetime.rest().as_secs_f32();
+                font_size = floating.font_size;
+            }
             ui.primitives.push(Primitive {
                 kind: PrimitiveKind::Text(Text {
-                    position: Point2::new(
-                        iso.0.translation.vector.x,
-                        iso.0.translation.vector.y,
-                    ),
+                    position,
                     color: text.color,
                     text: text.text.clone(),
-                    font_size: 1.0,
+                    font_size,
                 }),
                 with_projection: true,
             });
@@ -757,33 +959,36 @@ impl<'a> System<'a> for RenderingSystem {
             });
         }
         for (i, (iso, _)) in (&isometries, &ship_markers).join().enumerate() {}
+        // ui is drawn after the passes, so it's not distorted
+        let health = if let Some((lifes, ship_stats, _)) =
+            (&lifes, &ships_stats, &character_markers).join().next()
+        {
+            lifes.0 as f32 / ship_stats.max_health.max(1) as f32
+        } else {
+            1f32
+        };
+        let post_process_params = PostProcessParams {
+            aberration: (global_params.red / DAMAGED_RED).min(1f32),
+            vignette: (1f32 - health / LOW_HEALTH).max(0f32),
+            warp: warp.intensity(),
+        };
+        canvas.end_post_process(&post_process_settings, &post_process_params);
         flame::start("primitives rendering");
         primitives_channel.iter_write(ui.primitives.drain(..));
         sounds_channel.iter_write(ui.sounds.drain(..));
         render_primitives(
             &mouse,
             &mut self.reader,
-            &mut frame,
-            &gl,
             &mut canvas,
             &viewport,
             &mut primitives_channel,
-            &mut text_data,
-            &mut world_text_data,
         );
         flame::end("primitives rendering");
         // kind of hacky to write it here. and for now it only used as cursor wich would be convinient to draw with other ui
-        let sprite_batch = SpriteBatch::new(
-            &gl,
+        canvas.render_sprite_batch(
             &visible_foreground_batch.images,
             &visible_foreground_batch.isometries,
             &visible_foreground_batch.sizes,
-        );
-        canvas.render_sprite_batch(
-            &gl,
-            &viewport,
-            &mut frame,
-            &sprite_batch,
             false,
             Some(red::Blend),
         );
@@ -834,10 +1039,7 @@ impl<'a> System<'a> for RenderingSystem {
             }
             for name in telegraph.iter_names() {
                 if let Some(plot) = telegraph.iter(name.to_string()) {
-                    render_plot(
-                        plot.0, plot.1, 14.0, 10.0, &gl, &viewport, &canvas,
-                        &mut frame,
-                    );
+                    render_plot(plot.0, plot.1, 14.0, 10.0, &mut canvas);
                 }
             }
         }
@@ -1,5 +1,4 @@
 use super::*;
-use gfx_h::{TextData, WorldTextData};
 
 pub struct ScoreTableRendering {
     reader: ReaderId<Primitive>,
@@ -14,34 +13,26 @@ impl ScoreTableRendering {
 impl<'a> System<'a> for ScoreTableRendering {
     type SystemData = (
         ReadStorage<'a, ThreadPin<ImageData>>,
-        ReadExpect<'a, ThreadPin<red::GL>>,
         WriteExpect<'a, Canvas>,
         ReadExpect<'a, red::Viewport>,
         Write<'a, EventChannel<Primitive>>,
         Write<'a, UI>,
         Read<'a, Mouse>,
-        WriteExpect<'a, ThreadPin<TextData<'static>>>,
-        WriteExpect<'a, ThreadPin<WorldTextData<'static>>>,
         Write<'a, AppState>,
         ReadExpect<'a, MacroGame>,
     );
     fn run(&mut self, data: Self::SystemData) {
         let (
             image_datas,
-            gl,
             mut canvas,
             viewport,
             mut primitives_channel,
             mut ui,
             mouse,
-            mut text_data,
-            mut world_text_data,
             mut app_state,
             macro_game,
         ) = data;
-        let mut frame = red::Frame::new(&gl);
-        frame.set_clear_color(0.0, 0.0, 0.0, 1.0);
-        frame.clear_color();
+        canvas.clear((0.0, 0.0, 0.0, 1.0));
         let dims = viewport.dimensions();
         let (w, h) = (dims.0 as f32, dims.1 as f32);
         let (button_w, button_h) = (w / 4f32, h / 4f32);
@@ -81,13 +72,9 @@ impl<'a> System<'a> for ScoreTableRendering {
         render_primitives(
             &mouse,
             &mut self.reader,
-            &mut frame,
-            &gl,
             &mut canvas,
             &viewport,
             &mut primitives_channel,
-            &mut text_data,
-            &mut world_text_data,
         );
     }
 }
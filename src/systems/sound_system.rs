@@ -22,6 +22,9 @@ impl<'a> System<'a> for SoundSystem {
         ReadExpect<'a, ThreadPin<MusicData<'static>>>,
         Write<'a, Music>,
         Read<'a, AppState>,
+        ReadExpect<'a, Sectors>,
+        Read<'a, CurrentSector>,
+        Read<'a, TimeScale>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -36,6 +39,9 @@ impl<'a> System<'a> for SoundSystem {
             music_data,
             mut music,
             app_state,
+            sectors,
+            current_sector,
+            time_scale,
         ) = data;
         for s in sounds_channel.read(&mut self.reader) {
             let sound = &sounds.get(s.0).unwrap().0;
@@ -81,12 +87,19 @@ impl<'a> System<'a> for SoundSystem {
                 }
             }
         }
+        set_pitch(time_scale.scale);
         match *app_state {
             AppState::Play(_) => {
-                if music.current_battle.is_none() {
+                let biome_music = sectors.biomes[current_sector.biome].music;
+                let switch = match biome_music {
+                    Some(_) => music.current_battle != biome_music,
+                    None => music.current_battle.is_none(),
+                };
+                if switch {
                     let mut rng = thread_rng();
-                    let music_id =
-                        rng.gen_range(0, music_data.battle_music.len());
+                    let music_id = biome_music.unwrap_or_else(|| {
+                        rng.gen_range(0, music_data.battle_music.len())
+                    });
                     sdl2::mixer::Music::halt();
                     music.menu_play = false;
                     music_data.battle_music[music_id].play(-1).unwrap();
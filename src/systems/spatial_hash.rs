@@ -0,0 +1,25 @@
+use super::*;
+use log::info;
+
+/// rebuild spatial index of all positioned entities
+#[derive(Default)]
+pub struct SpatialHashSystem;
+
+impl<'a> System<'a> for SpatialHashSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Isometry>,
+        Write<'a, SpatialHash>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("asteroids: spatial hash started");
+        let (entities, isometries, mut spatial_hash) = data;
+        spatial_hash.clear();
+        for (entity, iso) in (&entities, &isometries).join() {
+            let position = iso.0.translation.vector;
+            spatial_hash.insert(entity, Point2::new(position.x, position.y));
+        }
+        info!("asteroids: spatial hash ended");
+    }
+}
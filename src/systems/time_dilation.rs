@@ -0,0 +1,64 @@
+use super::*;
+use log::info;
+use std::collections::HashSet;
+
+/// brief slowdown when a projectile flies by the character,
+/// eases the global time scale towards its target
+#[derive(Default)]
+pub struct TimeDilationSystem {
+    // hostile projectiles in NEAR_MISS_RADIUS on the previous frame
+    near_projectiles: HashSet<specs::Entity>,
+}
+
+impl<'a> System<'a> for TimeDilationSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Isometry>,
+        ReadStorage<'a, CharacterMarker>,
+        ReadStorage<'a, Projectile>,
+        Read<'a, SpatialHash>,
+        Write<'a, TimeScale>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("asteroids: time dilation started");
+        let (
+            entities,
+            isometries,
+            character_markers,
+            projectiles,
+            spatial_hash,
+            mut time_scale,
+        ) = data;
+        if let Some((character, iso, _)) =
+            (&entities, &isometries, &character_markers).join().next()
+        {
+            let position = iso.0.translation.vector;
+            let near: HashSet<specs::Entity> = spatial_hash
+                .query_radius(
+                    Point2::new(position.x, position.y),
+                    NEAR_MISS_RADIUS,
+                )
+                .into_iter()
+                .map(|(entity, _)| entity)
+                .filter(|&entity| {
+                    projectiles.get(entity).map_or(false, |projectile| {
+                        projectile.owner != character
+                    })
+                })
+                .collect();
+            // projectiles that hit the character are already deleted
+            let dodged = self.near_projectiles.iter().any(|&entity| {
+                entities.is_alive(entity) && !near.contains(&entity)
+            });
+            if dodged {
+                time_scale.near_miss(Duration::from_millis(NEAR_MISS_MS));
+            }
+            self.near_projectiles = near;
+        } else {
+            self.near_projectiles.clear();
+        }
+        time_scale.update();
+        info!("asteroids: time dilation ended");
+    }
+}
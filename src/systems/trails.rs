@@ -0,0 +1,29 @@
+use super::*;
+
+/// Drags trails after their entities and ages their points
+#[derive(Default)]
+pub struct TrailSystem {
+    /// world offset of the last run, points follow its changes
+    offset: Option<Vector2>,
+}
+
+impl<'a> System<'a> for TrailSystem {
+    type SystemData = (
+        ReadStorage<'a, Isometry>,
+        WriteStorage<'a, Trail>,
+        Read<'a, WorldOffset>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (isometries, mut trails, world_offset) = data;
+        let delta = TRACKER.lock().unwrap().last_delta().as_secs_f32();
+        // physics moved the world by that much since the last run
+        let shift = world_offset.0 - self.offset.unwrap_or(world_offset.0);
+        self.offset = Some(world_offset.0);
+        for (isometry, trail) in (&isometries, &mut trails).join() {
+            trail.translate(-shift);
+            let position = isometry.0.translation.vector;
+            trail.update(Point2::new(position.x, position.y), delta);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use super::*;
+use log::info;
+
+/// warp jump between waves, a small state machine over Warp resource
+#[derive(Default)]
+pub struct WarpSystem;
+
+impl<'a> System<'a> for WarpSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, CharacterMarker>,
+        ReadStorage<'a, PhysicsComponent>,
+        ReadStorage<'a, AsteroidMarker>,
+        ReadStorage<'a, BackgroundMarker>,
+        Write<'a, World<f32>>,
+        Write<'a, Warp>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        info!("asteroids: warp started");
+        let (
+            entities,
+            character_markers,
+            physics,
+            asteroid_markers,
+            background_markers,
+            mut world,
+            mut warp,
+        ) = data;
+        if !warp.is_active() {
+            return;
+        }
+        let body_handle = if let Some((physics_component, _)) =
+            (&physics, &character_markers).join().next()
+        {
+            physics_component.body_handle
+        } else {
+            return;
+        };
+        let body = world.rigid_body_mut(body_handle).unwrap();
+        let mut velocity = *body.velocity();
+        match warp.state {
+            WarpState::Idle => (),
+            WarpState::Accelerate | WarpState::Jump => {
+                velocity.linear += WARP_ACCELERATION * warp.direction;
+                if velocity.linear.norm() > WARP_SPEED {
+                    velocity.linear = WARP_SPEED * velocity.linear.normalize();
+                }
+            }
+            WarpState::Arrive => {
+                velocity.linear *= WARP_BRAKE;
+            }
+        }
+        body.set_velocity(velocity);
+        if warp.progress() < 1f32 {
+            return;
+        }
+        match warp.state {
+            WarpState::Idle => (),
+            WarpState::Accelerate => {
+                // leftovers of the previous wave don't follow us,
+                // background is respawned by BackgroundSystem
+                for (entity, _) in (&entities, &asteroid_markers).join() {
+                    entities.delete(entity).unwrap();
+                }
+                for (entity, _) in (&entities, &background_markers).join() {
+                    entities.delete(entity).unwrap();
+                }
+                warp.switch(WarpState::Jump);
+            }
+            WarpState::Jump => warp.switch(WarpState::Arrive),
+            WarpState::Arrive => warp.switch(WarpState::Idle),
+        }
+        info!("asteroids: warp ended");
+    }
+}
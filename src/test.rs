@@ -31,3 +31,1072 @@ fn sound() -> Result<(), String> {
     sdl2::mixer::Channel::all().play(&sound_chunk, 0)?;
     Ok(())
 }
+
+#[test]
+fn fast_projectiles_do_not_tunnel() {
+    use crate::nalgebra::{Isometry2, Point2, Vector2};
+    use crate::ncollide2d::shape::{Ball, ShapeHandle};
+    use crate::nphysics2d::object::BodyStatus;
+    use crate::nphysics2d::world::World;
+    use crate::setup::{
+        data_setup, load_animations, preloaded_images, read_atlas, setup_images,
+    };
+    use crate::shrev::EventChannel;
+    use crate::specs::prelude::*;
+    use crate::systems::{asteroid_shape, CollisionSystem};
+    use common::Velocity2;
+    use components::*;
+    use geometry::Polygon;
+    use physics::safe_maintain;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let mut specs_world = specs::World::new();
+    data_setup(&mut specs_world);
+    let atlas = read_atlas("assets/out.ron");
+    specs_world.add_resource(preloaded_images(
+        &setup_images(&atlas),
+        &load_animations(&atlas),
+    ));
+    add_silent_sounds(&mut specs_world);
+    specs_world.add_resource(World::<f32>::new());
+    specs_world.add_resource(EventChannel::<InsertEvent>::new());
+    specs_world.add_resource(EventChannel::<Sound>::new());
+    specs_world
+        .add_resource(Arc::new(Mutex::new(EventChannel::<InsertEvent>::new())));
+    specs_world.add_resource(Progress::default());
+    specs_world.add_resource(AppState::default());
+    specs_world.add_resource(MacroGame::default());
+    specs_world.add_resource(GlobalParams::default());
+    specs_world.add_resource(Factions::default());
+    let file = std::fs::read_to_string("rons/asteroids.ron").unwrap();
+    let asteroid_types: AsteroidTypes = ron::de::from_str(&file).unwrap();
+    let rock = asteroid_types
+        .0
+        .into_iter()
+        .find(|kind| !kind.reflective)
+        .unwrap();
+    // too small to be carved, so every hit just damages it
+    let half_size = 0.5;
+    let polygon = Polygon::new(vec![
+        Point2::new(-half_size, -half_size),
+        Point2::new(half_size, -half_size),
+        Point2::new(half_size, half_size),
+        Point2::new(-half_size, half_size),
+    ]);
+    let asteroid_collision_groups = specs_world
+        .read_resource::<Factions>()
+        .asteroid_collision_groups();
+    let bullet_collision_groups = specs_world
+        .read_resource::<Factions>()
+        .bullet_collision_groups(EntityType::Player.faction());
+    let asteroid = specs_world
+        .create_entity()
+        .with(AsteroidMarker::default())
+        .with(rock)
+        .with(polygon.clone())
+        .with(Isometry::new(0.0, 0.0, 0.0))
+        .with(Lifes(std::usize::MAX))
+        .with(Size(1.0))
+        .build();
+    PhysicsComponent::safe_insert(
+        &mut specs_world.write_storage::<PhysicsComponent>(),
+        asteroid,
+        asteroid_shape(&polygon).unwrap(),
+        Isometry2::identity(),
+        Velocity2::linear(0.0, 0.0),
+        BodyStatus::Static,
+        &mut specs_world.write_resource::<World<f32>>(),
+        &mut specs_world.write_resource::<BodiesMap>(),
+        asteroid_collision_groups,
+        0.5,
+    );
+    let mut dispatcher = DispatcherBuilder::new()
+        .with_thread_local(CollisionSystem::default())
+        .build();
+    let bullet_size = 0.1;
+    let mut misses = 0;
+    // every stack of bullet speed upgrades fast enough to skip the asteroid
+    for upgrades in 20..200 {
+        let speed = BULLET_SPEED_INIT * (1.0 + 0.1 * upgrades as f32);
+        // grazing shots touch the asteroid only with the bullet edge
+        let y = if upgrades % 2 == 0 {
+            0.0
+        } else {
+            half_size + bullet_size / 2.0
+        };
+        let bullet = specs_world
+            .create_entity()
+            .with(Projectile { owner: asteroid })
+            .with(Damage(10))
+            .with(Isometry::new(-speed / 2.0, y, 0.0))
+            .with(Lifetime::new(Duration::from_secs(10)))
+            .with(Size(bullet_size))
+            .with(EntityType::Player.faction())
+            .build();
+        PhysicsComponent::safe_insert(
+            &mut specs_world.write_storage::<PhysicsComponent>(),
+            bullet,
+            ShapeHandle::new(Ball::new(bullet_size)),
+            Isometry2::new(Vector2::new(-speed / 2.0, y), 0.0),
+            Velocity2::linear(speed, 0.0),
+            BodyStatus::Dynamic,
+            &mut specs_world.write_resource::<World<f32>>(),
+            &mut specs_world.write_resource::<BodiesMap>(),
+            bullet_collision_groups,
+            0.1,
+        );
+        {
+            let mut world = specs_world.write_resource::<World<f32>>();
+            // empty step to put the bullet in the broad phase
+            world.set_timestep(0f32);
+            world.step();
+        }
+        dispatcher.dispatch(&specs_world.res);
+        {
+            // bullet is already past the asteroid after the step
+            let mut world = specs_world.write_resource::<World<f32>>();
+            world.set_timestep(1f32);
+            world.step();
+        }
+        dispatcher.dispatch(&specs_world.res);
+        if specs_world.is_alive(bullet) {
+            misses += 1;
+            specs_world.delete_entity(bullet).unwrap();
+        }
+        safe_maintain(&mut specs_world);
+    }
+    assert_eq!(misses, 0);
+}
+
+#[test]
+fn concave_polygon_is_split_into_convex_pieces() {
+    use crate::nalgebra::Point2;
+    use geometry::{is_convex, polygon_area, Polygon};
+    // L shape with one reflex corner
+    let points = vec![
+        Point2::new(0.0, 0.0),
+        Point2::new(2.0, 0.0),
+        Point2::new(2.0, 1.0),
+        Point2::new(1.0, 1.0),
+        Point2::new(1.0, 2.0),
+        Point2::new(0.0, 2.0),
+    ];
+    let polygon = Polygon::new(points);
+    assert!(polygon.pieces.len() > 1);
+    let mut area = 0.0;
+    for piece in polygon.pieces.iter() {
+        assert!(is_convex(piece));
+        area += polygon_area(piece).abs();
+    }
+    assert!((area - 3.0).abs() < 1E-3);
+}
+
+#[test]
+fn crater_far_from_piece_keeps_it_whole() {
+    use crate::nalgebra::Point2;
+    use geometry::convex_difference;
+    let square = vec![
+        Point2::new(0.0, 0.0),
+        Point2::new(1.0, 0.0),
+        Point2::new(1.0, 1.0),
+        Point2::new(0.0, 1.0),
+    ];
+    let crater = vec![
+        Point2::new(3.0, 3.0),
+        Point2::new(4.0, 3.0),
+        Point2::new(4.0, 4.0),
+    ];
+    assert_eq!(convex_difference(&square, &crater), vec![square.clone()]);
+}
+
+#[test]
+fn carved_polygon_mass_center_comes_from_pieces() {
+    use crate::nalgebra::Point2;
+    use geometry::{Polygon, TriangulateFromCenter};
+    let points = vec![
+        Point2::new(0.0, 0.0),
+        Point2::new(2.0, 0.0),
+        Point2::new(2.0, 1.0),
+        Point2::new(1.0, 1.0),
+        Point2::new(1.0, 2.0),
+        Point2::new(0.0, 2.0),
+    ];
+    let polygon = Polygon::from_pieces(Polygon::new(points).pieces);
+    // three unit squares, the hull would put it to (1, 1)
+    let center = polygon.center();
+    assert!((center.x - 5.0 / 6.0).abs() < 1E-3);
+    assert!((center.y - 5.0 / 6.0).abs() < 1E-3);
+    let corner = (Point2::new(2.0, 0.0) - center).norm();
+    assert!((polygon.max_r - corner).abs() < 1E-3);
+    let notch = (Point2::new(1.0, 1.0) - center).norm();
+    assert!((polygon.min_r - notch).abs() < 1E-3);
+}
+
+#[test]
+fn recorded_frame_roundtrips_through_ron() {
+    use gfx_h::{
+        CommandList, DrawCommand, Mesh, ProgramKind, RenderMode, StencilMode,
+        TextRun, Transform,
+    };
+    let list = CommandList {
+        observer: (0.0, 0.0, 15.0),
+        z_far: 15.0,
+        commands: vec![
+            DrawCommand::Geometry {
+                program: ProgramKind::Light,
+                model: Transform {
+                    position: (1.0, 2.0, 0.0),
+                    angle: 0.5,
+                },
+                mesh: Mesh {
+                    positions: vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+                    indices: vec![0, 1, 2],
+                },
+                color: (1.0, 1.0, 1.0),
+                with_projection: true,
+                stencil: StencilMode::from(&RenderMode::StencilWrite),
+            },
+            DrawCommand::Text {
+                world: false,
+                runs: vec![TextRun {
+                    text: "Score: 42".to_string(),
+                    position: (10.0, 20.0),
+                    scale: 24.0,
+                    color: (1.0, 1.0, 1.0, 1.0),
+                }],
+            },
+        ],
+    };
+    let serialized = ron::ser::to_string(&list).unwrap();
+    let deserialized: CommandList = ron::de::from_str(&serialized).unwrap();
+    assert_eq!(list, deserialized);
+}
+
+#[test]
+fn particle_emitters_burn_out() {
+    use crate::setup::{load_particles, read_atlas, setup_images};
+    use common::Point2;
+    use gfx_h::ParticleEffects;
+    let name_to_atlas = setup_images(&read_atlas("assets/out.ron"));
+    let config = load_particles();
+    let effects =
+        ParticleEffects::new(&config.emitters, &name_to_atlas).unwrap();
+    for desc in config.emitters.iter().filter(|desc| !desc.wrap) {
+        let mut emitter =
+            effects.emitter(&desc.name, Point2::new(0.0, 0.0)).unwrap();
+        let mut frames = 0;
+        while emitter.update(1.0 / 60.0) {
+            frames += 1;
+            assert!(frames < 60 * 10, "{} never ends", desc.name);
+        }
+    }
+}
+
+#[test]
+fn particle_field_wraps_around_emitter() {
+    use crate::setup::{load_particles, read_atlas, setup_images};
+    use common::{Point2, Vector3};
+    use gfx_h::{ParticleEffects, ParticleField};
+    let name_to_atlas = setup_images(&read_atlas("assets/out.ron"));
+    let config = load_particles();
+    let effects =
+        ParticleEffects::new(&config.emitters, &name_to_atlas).unwrap();
+    let mut field = ParticleField::new(
+        effects.emitter("movement", Point2::new(0.0, 0.0)).unwrap(),
+    );
+    let num = field.positions().len();
+    let (w, h) = field.emitter.desc().area;
+    let (z_min, z_max) = field.emitter.desc().depth;
+    for _ in 0..100 {
+        assert!(field.emitter.update(1.0 / 60.0));
+        field.emitter.shift(Vector3::new(-1.7, 0.9, 0.0));
+    }
+    let positions = field.positions();
+    assert_eq!(positions.len(), num);
+    for position in positions.iter() {
+        assert!(position.x >= -w && position.x <= w);
+        assert!(position.y >= -h && position.y <= h);
+        assert!(position.z >= z_min && position.z <= z_max);
+    }
+}
+
+#[test]
+fn particle_effects_report_unknown_image() {
+    use crate::setup::{load_particles, read_atlas, setup_images};
+    use gfx_h::ParticleEffects;
+    let name_to_atlas = setup_images(&read_atlas("assets/out.ron"));
+    let mut emitters = load_particles().emitters;
+    emitters[0].image = "no_such_image".to_string();
+    let err = ParticleEffects::new(&emitters, &name_to_atlas)
+        .err()
+        .unwrap();
+    assert!(err.contains(&emitters[0].name));
+}
+
+#[test]
+fn trail_points_fade_out() {
+    use common::Point2;
+    use components::{Trail, TrailDesc};
+    let desc = TrailDesc {
+        length: 0.5,
+        width: 1.0,
+        color: (1.0, 1.0, 1.0, 1.0),
+    };
+    let mut trail = Trail::new(desc);
+    for i in 0..60 {
+        trail.update(Point2::new(i as f32, 0.0), 1.0 / 60.0);
+    }
+    let ribbon: Vec<_> = trail.ribbon().collect();
+    // only the last half a second is kept
+    assert!(ribbon.len() <= 31);
+    let (head, tail) = (ribbon[0], ribbon[ribbon.len() - 1]);
+    assert_eq!(head.0, Point2::new(59.0, 0.0));
+    assert!(tail.1 < head.1 && (tail.2).3 < (head.2).3);
+}
+
+#[test]
+fn trails_follow_world_offset() {
+    use crate::specs::prelude::*;
+    use crate::systems::TrailSystem;
+    use common::{Point2, Vector2};
+    use components::{Isometry, Trail, TrailDesc, WorldOffset};
+    let mut world = World::new();
+    world.register::<Isometry>();
+    world.register::<Trail>();
+    world.add_resource(WorldOffset::default());
+    let mut trail = Trail::new(TrailDesc {
+        length: 10.0,
+        width: 1.0,
+        color: (1.0, 1.0, 1.0, 1.0),
+    });
+    trail.update(Point2::new(0.0, 0.0), 1.0 / 60.0);
+    trail.update(Point2::new(1.0, 0.0), 1.0 / 60.0);
+    let entity = world
+        .create_entity()
+        .with(Isometry::new(1.0, 0.0, 0.0))
+        .with(trail)
+        .build();
+    let mut trail_system = TrailSystem::default();
+    trail_system.run_now(&world.res);
+    // physics re-centred the world on the entity
+    world.write_resource::<WorldOffset>().0 = Vector2::new(5.0, 0.0);
+    *world.write_storage::<Isometry>().get_mut(entity).unwrap() =
+        Isometry::new(-4.0, 0.0, 0.0);
+    trail_system.run_now(&world.res);
+    let trails = world.read_storage::<Trail>();
+    let ribbon: Vec<_> = trails.get(entity).unwrap().ribbon().collect();
+    assert_eq!(ribbon[0].0, Point2::new(-4.0, 0.0));
+    assert_eq!(ribbon[ribbon.len() - 1].0, Point2::new(-5.0, 0.0));
+}
+
+#[test]
+fn atlas_animations() {
+    use crate::setup::{load_animations, read_atlas};
+    let name_to_animation = load_animations(&read_atlas("assets/out.ron"));
+    for name in ["explosion_anim", "blast2_anim", "bullet_contact_anim"].iter()
+    {
+        let mut animation = name_to_animation[*name].clone();
+        let mut ticks = 0;
+        while animation.next_frame().is_some() {
+            ticks += 1;
+            assert!(ticks < 60 * 10, "{} never ends", name);
+        }
+    }
+    let mut impact = name_to_animation["impact_anim"].clone();
+    let frames: Vec<usize> = (0..3 * 9)
+        .map(|_| {
+            let frame = impact.current_frame;
+            impact.next_frame().unwrap();
+            frame
+        })
+        .step_by(3)
+        .collect();
+    assert_eq!(frames, vec![0, 1, 2, 3, 4, 3, 2, 1, 0]);
+}
+
+#[test]
+fn camera_cinematic_returns_to_player() {
+    use common::{Point2, Vector2};
+    use gfx_h::{Camera, CameraConfig};
+    let config: CameraConfig =
+        ron::de::from_str(&std::fs::read_to_string("rons/camera.ron").unwrap())
+            .unwrap();
+    let mut camera = Camera::new(config);
+    let player = Point2::new(0.0, 0.0);
+    let update = |camera: &mut Camera| {
+        camera.update(
+            1.0 / 60.0,
+            player,
+            Vector2::new(0.0, 0.0),
+            0.0,
+            Vector2::new(1.0, 0.0),
+            &[],
+            1.0,
+        )
+    };
+    camera.cinematic(Point2::new(20.0, 0.0));
+    for _ in 0..90 {
+        update(&mut camera);
+    }
+    assert!(camera.is_cinematic());
+    assert!(camera.observer().x > 10.0);
+    for _ in 0..60 * 5 {
+        update(&mut camera);
+    }
+    assert!(!camera.is_cinematic());
+    assert!(camera.observer().x < 3.0);
+    assert!(camera.shake("explosion"));
+    assert!(!camera.shake("no such shake"));
+}
+
+#[test]
+fn camera_config_needs_zoom_levels() {
+    use gfx_h::CameraConfig;
+    let file = std::fs::read_to_string("rons/camera.ron").unwrap();
+    let config: CameraConfig = ron::de::from_str(&file).unwrap();
+    assert!(config.validate().is_ok());
+    let config = CameraConfig {
+        zoom_levels: vec![],
+        ..config
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn indicators_stick_to_screen_edge() {
+    use crate::systems::edge_position;
+    use common::{Point2, Vector2};
+    let center = Point2::new(0.0, 0.0);
+    let half_extents = Vector2::new(16.0, 9.0);
+    assert!(
+        edge_position(center, half_extents, Point2::new(5.0, 5.0), 1.0)
+            .is_none()
+    );
+    let edge =
+        edge_position(center, half_extents, Point2::new(0.0, -30.0), 1.0)
+            .unwrap();
+    assert!((edge - Point2::new(0.0, -8.0)).norm() < 1e-4);
+    let edge =
+        edge_position(center, half_extents, Point2::new(60.0, 30.0), 1.0)
+            .unwrap();
+    assert!((edge - Point2::new(15.0, 7.5)).norm() < 1e-4);
+}
+
+#[test]
+fn hud_settings_load() {
+    use components::HudSettings;
+    let file = std::fs::read_to_string("rons/hud.ron").unwrap();
+    let settings: HudSettings = ron::de::from_str(&file).unwrap();
+    assert!(settings.floating_text);
+    assert!(settings.radar_range > 0.0);
+}
+
+#[test]
+fn floating_text_pool_reuses_expired_entities() {
+    use crate::specs::prelude::*;
+    use crate::systems::FloatingTextPool;
+    use components::{Lifetime, TRACKER};
+    use std::time::Duration;
+    let mut world = World::new();
+    world.register::<Lifetime>();
+    let mut pool = FloatingTextPool::default();
+    let mut acquire = |world: &World, lifetime: Duration| {
+        let entity = pool.acquire(&world.entities(), &world.read_storage());
+        world
+            .write_storage()
+            .insert(entity, Lifetime::new(lifetime))
+            .unwrap();
+        entity
+    };
+    let short = acquire(&world, Duration::from_millis(1));
+    let long = acquire(&world, Duration::from_secs(60));
+    assert_ne!(short, long);
+    std::thread::sleep(Duration::from_millis(5));
+    TRACKER.lock().unwrap().update(1.0);
+    assert_eq!(acquire(&world, Duration::from_secs(60)), short);
+    // alive texts are kept while the pool has room
+    let new = acquire(&world, Duration::from_secs(60));
+    assert!(new != short && new != long);
+}
+
+#[test]
+fn background_layers_fill_grid_cells() {
+    use crate::setup::{
+        load_animations, load_background, read_atlas, setup_images,
+    };
+    use common::Point2;
+    use components::{BackgroundLayers, Grid, LayerSprite};
+    let atlas = read_atlas("assets/out.ron");
+    let layers = BackgroundLayers::new(
+        &load_background().layers,
+        &setup_images(&atlas),
+        &load_animations(&atlas),
+    );
+    for layer in layers.layers.iter() {
+        assert_eq!(layer.grid.size, 2 * layer.desc.cells + 1);
+        if let LayerSprite::Images(images) = &layer.sprite {
+            assert!(!images.is_empty());
+        }
+    }
+    let mut grid: Grid<usize> = Grid::new(1, 10.0, 10.0, 1.0, 1.0);
+    *grid.cell_mut(Point2::new(12.0, -3.0)).unwrap() += 1;
+    assert_eq!(*grid.get_cell_value(1, 2), 1);
+    assert!(grid.cell_mut(Point2::new(31.0, 0.0)).is_none());
+}
+
+#[test]
+fn ram_damage_follows_ship_stats() {
+    use components::ShipStats;
+    let stats: ShipStats = ron::de::from_str(
+        "(thrust_force: 0.1, torque: 0.2, maneuverability: None, \
+         health_regen: 0, shield_regen: 0, max_health: 10, max_shield: 0, \
+         damage: 10)",
+    )
+    .unwrap();
+    assert_eq!(stats.ram_factor(0.01, 1.0), 0.0);
+    assert!((stats.ram_factor(0.1, 1.0) - 1.0).abs() < 1e-6);
+    let heavy = ShipStats {
+        ram_speed_threshold: 0.2,
+        ..stats
+    };
+    assert_eq!(heavy.ram_factor(0.1, 1.0), 0.0);
+}
+
+#[test]
+fn asteroid_type_images_are_in_atlas() {
+    use crate::setup::{read_atlas, setup_images};
+    use components::AsteroidTypes;
+    let name_to_atlas = setup_images(&read_atlas("assets/out.ron"));
+    let file = std::fs::read_to_string("rons/asteroids.ron").unwrap();
+    let mut asteroid_types: AsteroidTypes = ron::de::from_str(&file).unwrap();
+    asteroid_types.load_images(&name_to_atlas).unwrap();
+    for kind in asteroid_types.0.iter() {
+        assert_eq!(kind.image.is_some(), kind.atlas_image.is_some());
+    }
+    asteroid_types.0[0].image = Some("no such image".to_string());
+    assert!(asteroid_types.load_images(&name_to_atlas).is_err());
+}
+
+#[test]
+fn wave_spawns_stay_inside_arena() {
+    use crate::nalgebra::{Point2, Vector2};
+    use crate::systems::arena_spawn_position;
+    use components::{
+        ActiveArena, Arena, ArenaBehavior, ArenaShape, WorldOffset,
+    };
+    let world_offset = WorldOffset(Vector2::new(5.0, -3.0));
+    let character = Point2::new(1.0, 1.0);
+    let active_arena = ActiveArena {
+        center: character + world_offset.0,
+        arena: Some(Arena {
+            shape: ArenaShape::Circle { radius: 18.0 },
+            behavior: ArenaBehavior::Bounce,
+        }),
+    };
+    for _ in 0..1000 {
+        let position =
+            arena_spawn_position(character, &active_arena, &world_offset);
+        assert!((position - character).norm() <= 18.0 + 1e-3);
+    }
+    let active_arena = ActiveArena {
+        arena: Some(Arena {
+            shape: ArenaShape::Rectangle {
+                width: 36.0,
+                height: 24.0,
+            },
+            behavior: ArenaBehavior::Wrap,
+        }),
+        ..active_arena
+    };
+    for _ in 0..1000 {
+        let position =
+            arena_spawn_position(character, &active_arena, &world_offset);
+        assert!((position.x - character.x).abs() <= 18.0 + 1e-3);
+        assert!((position.y - character.y).abs() <= 12.0 + 1e-3);
+    }
+}
+
+#[test]
+fn bullet_bounces_off_metallic_asteroid_once() {
+    use crate::nalgebra::{Isometry2, Vector2};
+    use crate::ncollide2d::shape::{Ball, ShapeHandle};
+    use crate::ncollide2d::world::CollisionGroups;
+    use crate::nphysics2d::object::BodyStatus;
+    use crate::nphysics2d::world::World;
+    use crate::setup::{
+        data_setup, load_animations, preloaded_images, read_atlas, setup_images,
+    };
+    use crate::shrev::EventChannel;
+    use crate::specs::prelude::*;
+    use crate::systems::CollisionSystem;
+    use common::Velocity2;
+    use components::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let mut specs_world = specs::World::new();
+    data_setup(&mut specs_world);
+    let atlas = read_atlas("assets/out.ron");
+    specs_world.add_resource(preloaded_images(
+        &setup_images(&atlas),
+        &load_animations(&atlas),
+    ));
+    add_silent_sounds(&mut specs_world);
+    specs_world.add_resource(World::<f32>::new());
+    specs_world.add_resource(EventChannel::<InsertEvent>::new());
+    specs_world.add_resource(EventChannel::<Sound>::new());
+    specs_world
+        .add_resource(Arc::new(Mutex::new(EventChannel::<InsertEvent>::new())));
+    specs_world.add_resource(Progress::default());
+    specs_world.add_resource(AppState::default());
+    specs_world.add_resource(MacroGame::default());
+    specs_world.add_resource(GlobalParams::default());
+    specs_world.add_resource(Factions::default());
+    let file = std::fs::read_to_string("rons/asteroids.ron").unwrap();
+    let asteroid_types: AsteroidTypes = ron::de::from_str(&file).unwrap();
+    let metallic = asteroid_types
+        .0
+        .into_iter()
+        .find(|kind| kind.reflective)
+        .unwrap();
+    let insert_body = |specs_world: &mut specs::World,
+                       entity,
+                       radius: f32,
+                       x: f32,
+                       speed: f32,
+                       status| {
+        PhysicsComponent::safe_insert(
+            &mut specs_world.write_storage::<PhysicsComponent>(),
+            entity,
+            ShapeHandle::new(Ball::new(radius)),
+            Isometry2::new(Vector2::new(x, 0.0), 0.0),
+            Velocity2::linear(speed, 0.0),
+            status,
+            &mut specs_world.write_resource::<World<f32>>(),
+            &mut specs_world.write_resource::<BodiesMap>(),
+            CollisionGroups::new(),
+            0.5,
+        )
+    };
+    let asteroid = specs_world
+        .create_entity()
+        .with(AsteroidMarker::default())
+        .with(metallic)
+        .with(geometry::generate_convex_polygon(10, 1.0))
+        .with(Isometry::new(0.0, 0.0, 0.0))
+        .with(Lifes(1000))
+        .with(Size(1.0))
+        .build();
+    insert_body(
+        &mut specs_world,
+        asteroid,
+        1.0,
+        0.0,
+        0.0,
+        BodyStatus::Dynamic,
+    );
+    let bullet = specs_world
+        .create_entity()
+        .with(Projectile { owner: asteroid })
+        .with(Damage(10))
+        .with(Isometry::new(-1.6, 0.0, 0.0))
+        .with(Lifetime::new(Duration::from_secs(10)))
+        .build();
+    // kinematic bullet goes halfway into the asteroid in one step
+    let bullet_body = insert_body(
+        &mut specs_world,
+        bullet,
+        0.05,
+        -1.6,
+        1.0,
+        BodyStatus::Kinematic,
+    )
+    .body_handle;
+    {
+        let mut world = specs_world.write_resource::<World<f32>>();
+        // empty step to put new colliders in the broad phase
+        world.set_timestep(0f32);
+        world.step();
+        world.set_timestep(1f32);
+    }
+    let mut collision_system = CollisionSystem::default();
+    for _ in 0..5 {
+        collision_system.run_now(&specs_world.res);
+        specs_world.write_resource::<World<f32>>().step();
+    }
+    // ordinary bullet bounces without turning into a reflected one
+    assert!(specs_world.is_alive(bullet));
+    assert!(specs_world
+        .read_storage::<Reflection>()
+        .get(bullet)
+        .is_none());
+    let world = specs_world.read_resource::<World<f32>>();
+    let body = world.rigid_body(bullet_body).unwrap();
+    assert!(body.velocity().linear.x < 0.0);
+    assert!(body.position().translation.vector.x < -1.0);
+}
+
+#[test]
+fn spatial_hash_nearest_matches_full_scan() {
+    use crate::rand::prelude::*;
+    use crate::specs::prelude::*;
+    use common::Point2;
+    use components::SpatialHash;
+    let mut world = World::new();
+    let mut rng = thread_rng();
+    let scene: Vec<(Entity, Point2)> = (0..500)
+        .map(|_| {
+            let point = Point2::new(
+                rng.gen_range(-40.0, 40.0),
+                rng.gen_range(-40.0, 40.0),
+            );
+            (world.create_entity().build(), point)
+        })
+        .collect();
+    let mut spatial_hash = SpatialHash::default();
+    for &(entity, point) in scene.iter() {
+        spatial_hash.insert(entity, point);
+    }
+    for &(entity, position) in scene.iter().take(50) {
+        let expected = scene
+            .iter()
+            .filter(|(target, point)| {
+                *target != entity && (point - position).norm() <= 20.0
+            })
+            .map(|(_, point)| (point - position).norm())
+            .fold(None, |best: Option<f32>, d| {
+                Some(best.map_or(d, |best| best.min(d)))
+            });
+        let nearest = spatial_hash
+            .nearest(position, 20.0, |target| target != entity)
+            .map(|(_, point)| (point - position).norm());
+        assert_eq!(nearest, expected);
+    }
+}
+
+#[test]
+fn pitch_shifter_keeps_level_and_lowers_tone() {
+    use sound::PitchShifter;
+    let frequency = 44_100;
+    let tone = |pitch: f32| {
+        let mut shifter = PitchShifter::new(frequency, 1);
+        let mut samples: Vec<i16> = (0..frequency)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * 441.0 * i as f32
+                    / frequency as f32;
+                (10_000.0 * phase.sin()) as i16
+            })
+            .collect();
+        for chunk in samples.chunks_mut(1_024) {
+            shifter.process(chunk, pitch);
+        }
+        // zero crossings per second over the settled half
+        samples[frequency / 2..]
+            .windows(2)
+            .filter(|pair| (pair[0] < 0) != (pair[1] < 0))
+            .count()
+    };
+    assert_eq!(tone(1.0), 441);
+    assert!((tone(0.5) as i32 - 220).abs() < 20);
+    let mut shifter = PitchShifter::new(frequency, 2);
+    let mut constant = vec![5_000i16; 20_000];
+    shifter.process(&mut constant, 0.5);
+    assert!(constant[19_000..].iter().all(|&x| (x - 5_000).abs() <= 1));
+}
+
+/// Every sound of the game is the same empty entity
+fn add_silent_sounds(specs_world: &mut crate::specs::World) {
+    use crate::specs::prelude::*;
+    use sound::PreloadedSounds;
+    let sound = specs_world.create_entity().build();
+    specs_world.add_resource(PreloadedSounds {
+        shot: sound,
+        asteroid_explosion: sound,
+        ship_explosion: sound,
+        blast: sound,
+        lazer: sound,
+        enemy_blaster: sound,
+        enemy_shotgun: sound,
+        collision: sound,
+        coin: sound,
+        coin2: sound,
+        exp: sound,
+        hover: sound,
+        click: sound,
+        play: sound,
+        deny: sound,
+        buy: sound,
+        windup: sound,
+    });
+}
+
+/// World with everything the rendering systems read,
+/// frames go to the recording backend instead of a window
+fn rendering_world() -> crate::specs::World {
+    use crate::gui::{Primitive, UI};
+    use crate::nalgebra::Point2;
+    use crate::nphysics2d::world::World;
+    use crate::setup::{
+        data_setup, load_animations, load_background, load_description,
+        load_particles, preloaded_images, read_atlas, setup_images,
+        setup_telegraph,
+    };
+    use crate::shrev::EventChannel;
+    use crate::specs::prelude::*;
+    use common::ThreadPin;
+    use components::*;
+    use gfx_h::{
+        Canvas, Emitter, ParticleField, ParticlePool, RecordingBackend,
+        MAX_PARTICLES,
+    };
+    use std::sync::Arc;
+
+    let mut specs_world = specs::World::new();
+    data_setup(&mut specs_world);
+    let atlas = read_atlas("assets/out.ron");
+    let name_to_atlas = setup_images(&atlas);
+    let name_to_animation = load_animations(&atlas);
+    load_description(&mut specs_world, &name_to_atlas);
+    // saved coins and scores should not change the frames
+    specs_world.add_resource(MacroGame::default());
+    specs_world
+        .add_resource(preloaded_images(&name_to_atlas, &name_to_animation));
+    specs_world.add_resource(BackgroundLayers::new(
+        &load_background().layers,
+        &name_to_atlas,
+        &name_to_animation,
+    ));
+    // particles are placed randomly, keep them out of the snapshots
+    let field = |name: &str| {
+        let mut desc = load_particles()
+            .emitters
+            .into_iter()
+            .find(|desc| desc.name == name)
+            .unwrap();
+        desc.burst = 0;
+        let image = name_to_atlas[&desc.image];
+        ParticleField::new(Emitter::new(
+            Arc::new(desc),
+            image,
+            Point2::new(0.0, 0.0),
+        ))
+    };
+    let movement = specs_world.create_entity().with(field("movement")).build();
+    let menu = specs_world.create_entity().with(field("menu")).build();
+    specs_world.add_resource(PreloadedParticles { movement, menu });
+    specs_world.add_resource(name_to_atlas);
+    specs_world.add_resource(ThreadPin::new(ParticlePool::new(MAX_PARTICLES)));
+    add_silent_sounds(&mut specs_world);
+    specs_world.add_resource(setup_telegraph());
+    specs_world.add_resource(red::Viewport::for_window(1920, 1080));
+    specs_world.add_resource(Mouse {
+        wdpi: 1.0,
+        hdpi: 1.0,
+        ..Mouse::default()
+    });
+    specs_world.add_resource(ThreadPin::new(Canvas::new()));
+    specs_world.add_resource(ThreadPin::new(RecordingBackend::default()));
+    specs_world.add_resource(GlobalParams::default());
+    specs_world.add_resource(World::<f32>::new());
+    specs_world.add_resource(UI::default());
+    specs_world.add_resource(AppState::default());
+    specs_world.add_resource(Progress::default());
+    specs_world.add_resource(EventChannel::<Primitive>::new());
+    specs_world.add_resource(EventChannel::<Sound>::new());
+    specs_world.add_resource(EventChannel::<InsertEvent>::new());
+    specs_world.add_resource(EventChannel::<AnimationEvent>::new());
+    specs_world
+}
+
+/// Runs the present system and takes the frame from the recording backend
+fn presented_frame(specs_world: &crate::specs::World) -> gfx_h::CommandList {
+    use crate::specs::prelude::*;
+    use crate::systems::PresentSystem;
+    use common::ThreadPin;
+    use gfx_h::RecordingBackend;
+    PresentSystem::<RecordingBackend>::default().run_now(&specs_world.res);
+    let mut backend =
+        specs_world.write_resource::<ThreadPin<RecordingBackend>>();
+    assert_eq!(backend.frames.len(), 1);
+    backend.frames.pop().unwrap()
+}
+
+/// Compares the frame with `snapshots/<name>.ron`.
+/// Snapshots are only written with UPDATE_SNAPSHOTS set
+fn assert_frame_snapshot(name: &str, frame: &gfx_h::CommandList) {
+    use ron::ser::{to_string_pretty, PrettyConfig};
+    let path = format!("snapshots/{}.ron", name);
+    let serialized = to_string_pretty(frame, PrettyConfig::default()).unwrap();
+    if std::env::var("UPDATE_SNAPSHOTS").is_ok() {
+        std::fs::create_dir_all("snapshots").unwrap();
+        std::fs::write(&path, serialized).unwrap();
+        return;
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(saved) => {
+            assert!(saved == serialized, "frame differs from {}", path)
+        }
+        Err(err) => panic!(
+            "no snapshot {}: {}, run with UPDATE_SNAPSHOTS=1 to write it",
+            path, err
+        ),
+    }
+}
+
+fn has_text(frame: &gfx_h::CommandList, text: &str) -> bool {
+    use gfx_h::DrawCommand;
+    frame.commands.iter().any(|command| {
+        if let DrawCommand::Text { runs, .. } = command {
+            runs.iter().any(|run| run.text == text)
+        } else {
+            false
+        }
+    })
+}
+
+#[test]
+fn menu_frame_snapshot() {
+    use crate::gui::Primitive;
+    use crate::shrev::EventChannel;
+    use crate::specs::prelude::*;
+    use crate::systems::{MenuRenderingSystem, RenderingSystem};
+    use gfx_h::DrawCommand;
+    let specs_world = rendering_world();
+    let reader = specs_world
+        .write_resource::<EventChannel<Primitive>>()
+        .register_reader();
+    MenuRenderingSystem.run_now(&specs_world.res);
+    RenderingSystem::new(reader).run_now(&specs_world.res);
+    let frame = presented_frame(&specs_world);
+    let clears = frame
+        .commands
+        .iter()
+        .filter(|command| {
+            if let DrawCommand::Clear { .. } = command {
+                true
+            } else {
+                false
+            }
+        })
+        .count();
+    assert_eq!(clears, 1);
+    assert!(has_text(&frame, "Play"));
+    assert!(has_text(&frame, "Score Table"));
+    assert_frame_snapshot("menu", &frame);
+}
+
+#[test]
+fn dead_screen_frame_snapshot() {
+    use crate::gui::Primitive;
+    use crate::shrev::EventChannel;
+    use crate::specs::prelude::*;
+    use crate::systems::{DeadScreen, RenderingSystem};
+    use components::Progress;
+    use gfx_h::{DrawCommand, ProgramKind};
+    let specs_world = rendering_world();
+    specs_world.write_resource::<Progress>().score = 42;
+    let reader = specs_world
+        .write_resource::<EventChannel<Primitive>>()
+        .register_reader();
+    DeadScreen.run_now(&specs_world.res);
+    RenderingSystem::new(reader).run_now(&specs_world.res);
+    let frame = presented_frame(&specs_world);
+    assert!(has_text(&frame, "Your score: 42"));
+    // dark background over the whole screen
+    assert!(frame.commands.iter().any(|command| {
+        if let DrawCommand::Image {
+            program: ProgramKind::PrimitiveTexture,
+            with_projection: false,
+            ..
+        } = command
+        {
+            true
+        } else {
+            false
+        }
+    }));
+    assert_frame_snapshot("dead_screen", &frame);
+}
+
+#[test]
+fn game_frame_snapshot() {
+    use crate::gui::Primitive;
+    use crate::nalgebra::Point2;
+    use crate::shrev::EventChannel;
+    use crate::specs::prelude::*;
+    use crate::systems::RenderingSystem;
+    use components::*;
+    use geometry::{Geometry, Polygon, TriangulateFromCenter};
+    use gfx_h::{DrawCommand, Mesh, StencilMode};
+    let mut specs_world = rendering_world();
+    let ship_image = specs_world.read_resource::<PreloadedImages>().basic_ship;
+    specs_world
+        .create_entity()
+        .with(CharacterMarker::default())
+        .with(Isometry::new(0.0, 0.0, 0.0))
+        .with(Velocity::new(0.0, 0.0))
+        .with(ship_image)
+        .with(Size(1.0))
+        .build();
+    let polygon = Polygon::new(vec![
+        Point2::new(-1.0, -1.0),
+        Point2::new(1.0, -1.0),
+        Point2::new(1.0, 1.0),
+        Point2::new(-1.0, 1.0),
+    ]);
+    let triangulation = polygon.triangulate();
+    let mesh = Mesh::new(&triangulation.points, &triangulation.indicies);
+    specs_world
+        .create_entity()
+        .with(AsteroidMarker::default())
+        .with(Isometry::new(3.0, 0.0, 0.0))
+        .with(Geometry::Polygon(polygon))
+        .with(mesh.clone())
+        .with(Size(1.0))
+        .build();
+    specs_world
+        .create_entity()
+        .with(Isometry::new(-2.0, 0.0, 0.0))
+        .with(Light::new((1.0, 0.5, 0.2), 5.0, 1.0))
+        .build();
+    let reader = specs_world
+        .write_resource::<EventChannel<Primitive>>()
+        .register_reader();
+    RenderingSystem::new(reader).run_now(&specs_world.res);
+    let frame = presented_frame(&specs_world);
+    let geometry_stencils: Vec<StencilMode> = frame
+        .commands
+        .iter()
+        .filter_map(|command| {
+            if let DrawCommand::Geometry { stencil, .. } = command {
+                Some(*stencil)
+            } else {
+                None
+            }
+        })
+        .collect();
+    // shadows of the player, then shadows of the light
+    assert_eq!(
+        &geometry_stencils[..4],
+        &[
+            StencilMode::Write,
+            StencilMode::ShadowMark,
+            StencilMode::LightReveal,
+            StencilMode::ShadowClear,
+        ]
+    );
+    assert!(frame.commands.iter().any(|command| {
+        if let DrawCommand::Geometry {
+            mesh: asteroid_mesh,
+            stencil: StencilMode::Off,
+            ..
+        } = command
+        {
+            *asteroid_mesh == mesh
+        } else {
+            false
+        }
+    }));
+    assert_frame_snapshot("game", &frame);
+}
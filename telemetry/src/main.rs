@@ -1,10 +1,10 @@
 use red;
 
 use common::*;
-use gfx_h::Canvas;
+use gfx_h::{Backend, Canvas, GlBackend};
 use rand::prelude::*;
 use red::glow::RenderLoop;
-use red::{glow, Frame, GL};
+use red::{glow, GL};
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
@@ -39,16 +39,17 @@ fn main() -> Result<(), String> {
     });
     let context = GL::new(context);
     let glsl_version = "#version 330";
-    let canvas = Canvas::new(&context, "", &glsl_version).unwrap();
-    let mut frame = Frame::new(&context);
+    let font: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans.ttf");
+    let mut backend =
+        GlBackend::new(context, "", "atlas", &glsl_version, font).unwrap();
+    let mut canvas = Canvas::new();
     let mut event_loop = sdl_context.event_pump().unwrap();
     let mut telegraph = TeleGraph::new(Duration::from_secs(10));
     let (w, h) = (16f32, 9f32);
     telegraph.set_color("plot a".to_string(), Point3::new(1.0, 1.0, 1.0));
     telegraph.set_color("plot b".to_string(), Point3::new(0.0, 1.0, 0.0));
     render_loop.run(move |running: &mut bool| {
-        frame.set_clear_color(0.015, 0.004, 0.0, 1.0);
-        frame.clear_color_and_stencil();
+        canvas.clear((0.015, 0.004, 0.0, 1.0));
         let mut rng = rand::thread_rng();
         telegraph.update();
         if rng.gen_range(0.0, 1.0) < 0.06 {
@@ -57,12 +58,10 @@ fn main() -> Result<(), String> {
         }
         for name in telegraph.iter_names() {
             if let Some(plot) = telegraph.iter(name.to_string()) {
-                render_plot(
-                    plot.0, plot.1, w, h, &context, &viewport, &canvas,
-                    &mut frame,
-                );
+                render_plot(plot.0, plot.1, w, h, &mut canvas);
             }
         }
+        backend.execute(&viewport, canvas.take_commands());
         for event in event_loop.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -1,6 +1,5 @@
 use common::*;
 use gfx_h::Canvas;
-use red::GL;
 use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
 
@@ -120,24 +119,10 @@ pub fn render_plot<T>(
     iter_plot: T,
     w: f32,
     h: f32,
-    context: &GL,
-    viewport: &red::Viewport,
-    canvas: &Canvas,
-    frame: &mut red::Frame,
+    canvas: &mut Canvas,
 ) where
     T: Iterator<Item = (f32, f32)>,
 {
-    let render_lines = move |lines: &[(Point2, Point2)],
-                             frame: &mut red::Frame| {
-        canvas.draw_lines(
-            &lines,
-            &context,
-            frame,
-            &viewport,
-            plot_data.color,
-            0.1f32,
-        );
-    };
     let mut prev = None;
     let mut lines_to_draw = vec![];
     for (x_fract, value) in iter_plot {
@@ -146,9 +131,8 @@ pub fn render_plot<T>(
         let current = Point2::new(x, y);
         if let Some(prev) = prev {
             lines_to_draw.push((prev, current));
-            // render_line(prev, current, frame);
         }
         prev = Some(current);
     }
-    render_lines(&lines_to_draw, frame);
+    canvas.draw_lines(&lines_to_draw, plot_data.color, 0.1f32);
 }